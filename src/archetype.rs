@@ -1,11 +1,6 @@
 use bevy_ptr::OwningPtr;
 use bevy_utils::HashMap;
-use std::{
-    cell::{Cell, RefCell},
-    collections::BTreeSet,
-    hash::Hash,
-    rc::Rc,
-};
+use std::{cell::RefCell, collections::BTreeSet, hash::Hash, rc::Rc};
 
 use crate::{
     archetypes::{Archetypes, COMPONENT_CAPACITY},
@@ -31,16 +26,6 @@ impl From<usize> for ArchetypeRow {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
 pub struct ArchetypeId(pub usize);
 
-thread_local! {
-    static ARCHETYPE_ID: Cell<usize> = const{ Cell::new(0) };
-}
-
-fn archetype_id() -> ArchetypeId {
-    let id = ARCHETYPE_ID.get();
-    ARCHETYPE_ID.set(id + 1);
-    id.into()
-}
-
 impl From<usize> for ArchetypeId {
     fn from(value: usize) -> Self {
         Self(value)
@@ -73,8 +58,11 @@ pub struct Archetype {
 }
 
 impl Archetype {
-    pub fn new(table: Rc<RefCell<Table>>, components: BTreeSet<Identifier>) -> Self {
-        let id = archetype_id();
+    pub fn new(
+        id: ArchetypeId,
+        table: Rc<RefCell<Table>>,
+        components: BTreeSet<Identifier>,
+    ) -> Self {
         let mut entities = vec![];
         entities.reserve_exact(COMPONENT_CAPACITY);
         let components_vec: Vec<_> = components.iter().cloned().collect();
@@ -97,6 +85,14 @@ impl Archetype {
         self.len() == 0
     }
 
+    pub fn reserve(&mut self, additional: usize) {
+        self.entity_indices.reserve(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.entity_indices.shrink_to_fit();
+    }
+
     pub fn push_entity(
         &mut self,
         index: usize,
@@ -236,6 +232,10 @@ impl Archetype {
         &self.table
     }
 
+    pub fn set_table(&mut self, table: Rc<RefCell<Table>>) {
+        self.table = table;
+    }
+
     pub fn id(&self) -> ArchetypeId {
         self.id
     }
@@ -248,9 +248,24 @@ impl Archetype {
         self.edges.entry(id).or_default()
     }
 
+    pub fn clear_edges_to(&mut self, archetype: ArchetypeId) {
+        for edge in self.edges.values_mut() {
+            if edge.add == Some(archetype) {
+                edge.add = None;
+            }
+            if edge.remove == Some(archetype) {
+                edge.remove = None;
+            }
+        }
+    }
+
     pub fn entity_indices(&self) -> &[usize] {
         &self.entity_indices
     }
+
+    pub fn set_entity_index(&mut self, row: ArchetypeRow, index: usize) {
+        self.entity_indices[row.0] = index;
+    }
 }
 
 impl PartialEq for Archetype {
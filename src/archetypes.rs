@@ -1,16 +1,18 @@
 use std::{
     alloc::Layout,
     any::{Any, TypeId},
-    cell::{Ref, RefCell, RefMut},
+    cell::{Cell, Ref, RefCell, RefMut},
+    cmp::Reverse,
     collections::{BTreeSet, VecDeque},
     marker::PhantomData,
+    ops::RangeInclusive,
     ptr::NonNull,
     rc::Rc,
 };
 
 use anyhow::{bail, Result};
-use bevy_ptr::{Ptr, PtrMut};
-use bevy_reflect::Reflect;
+use bevy_ptr::{OwningPtr, Ptr, PtrMut};
+use bevy_reflect::{std_traits::ReflectDefault, ApplyError, Reflect};
 use bevy_utils::{hashbrown::HashMap, HashSet};
 use bimap::BiHashMap;
 use packed_struct::PackedStruct;
@@ -19,6 +21,8 @@ use thiserror::Error;
 
 use crate::{
     archetype::{Archetype, ArchetypeAdd, ArchetypeId, ArchetypeRow},
+    binary_snapshot::{ArchetypeSnapshot, IdPart, IdSnapshot, SparseSetSnapshot, WorldSnapshot},
+    blob_vec::BlobVec,
     children_iter::{self, ChildrenRecursiveIterRef, Depth},
     components::{
         component::{AbstractComponent, EnumTag},
@@ -28,14 +32,16 @@ use crate::{
     },
     entity::{Entity, WILDCARD},
     entity_parser::{self, EntityParser, IdOrName, ParseError, ParsedEntityItem, TagType},
+    events::CurrentSystemTypeId,
     expect_fn::ExpectFnOption,
     filter_mask::FilterMask,
     identifier::{Identifier, IdentifierHigh32, IdentifierUnpacked, WildcardKind},
     on_change_callbacks::{OnAddCallback, OnChangeCallbacks, OnRemoveCallback},
     query::RequiredIds,
     relationship::FindRelationshipsIter,
-    systems::{EnumId, Systems},
-    table::{Storage, Table, TableRow},
+    sparse_set::SparseSet,
+    systems::{EnumId, SystemId, Systems},
+    table::{ChangeTick, Storage, Table, TableRow},
     world::{archetypes, archetypes_mut},
     wrappers::{ArchetypeCell, TableCell},
 };
@@ -47,6 +53,8 @@ pub const COMPONENT_ID: Identifier = Identifier([1, 0, 0, 0, 0, 0, 0, 0]);
 pub const WILDCARD_32: u32 = u32::MAX;
 pub const WILDCARD_25: u32 = u32::MAX >> 7;
 pub const ENTITIES_START_CAPACITY: usize = 512;
+//records are only compacted once at least 1/N of them are free
+pub const RECORDS_COMPACT_RATIO: usize = 4;
 //max low32, max high32, is_relationship
 pub const WILDCARD_RELATIONSHIP: Identifier = Identifier([255, 255, 255, 255, 255, 255, 255, 129]);
 
@@ -63,6 +71,36 @@ pub enum GetComponentError {
     NoComponent(SmolStr, SmolStr),
 }
 
+#[derive(Debug, Error)]
+pub enum PatchError {
+    #[error("unknown component {0}")]
+    UnknownComponent(SmolStr),
+    #[error("expected entity {0} to have the component {1}")]
+    Missing(SmolStr, SmolStr),
+    #[error("component {0} has no reflected data")]
+    NotReflected(SmolStr),
+    #[error(transparent)]
+    Apply(#[from] ApplyError),
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("name '{0}' already exists under the same parent")]
+pub struct NameExistsError(pub SmolStr);
+
+#[derive(Debug, Clone, Error)]
+pub enum ReparentError {
+    #[error(transparent)]
+    NameExists(#[from] NameExistsError),
+    #[error("entity {0} can't be parented under itself or its own descendant")]
+    Cycle(u32),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum NameError {
+    #[error("name '{0}' already exists under parent {1}")]
+    Duplicate(SmolStr, usize),
+}
+
 #[derive(Debug, Clone, Error)]
 pub enum ComponentTypeError {
     #[error("Expected entity {0} to be alive")]
@@ -100,6 +138,7 @@ pub enum OperationType {
     },
     RemoveComponent(Identifier),
     RemoveEntity,
+    AddSparseTag(Identifier),
 }
 
 #[derive(Debug)]
@@ -111,9 +150,11 @@ pub struct ArchetypeOperation {
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash)]
 pub struct StrippedIdentifier(Identifier);
 
+const STRIPPED_BITS: u64 = 0xFFFFFFFFFFFFFF81;
+
 impl From<Identifier> for StrippedIdentifier {
     fn from(value: Identifier) -> Self {
-        let value = u64::from(value) & 0xFFFFFFFFFFFFFF81;
+        let value = u64::from(value) & STRIPPED_BITS;
         Self(Identifier(u64::to_be_bytes(value)))
     }
 }
@@ -122,20 +163,53 @@ impl StrippedIdentifier {
     pub fn low32(&self) -> u32 {
         self.0.low32()
     }
+
+    //the flags are the lowest bits, so every identifier stripping to this one is in this range
+    pub(crate) fn range(&self) -> RangeInclusive<Identifier> {
+        let value = u64::from(self.0);
+        self.0..=Identifier::from(value | !STRIPPED_BITS)
+    }
 }
 pub type CloneFn = fn(Ptr<'_>, RefMut<Storage>);
+pub type DropFn = unsafe fn(OwningPtr<'_>);
+pub type DefaultFn = fn(RefMut<Storage>);
 pub type SerializeFn = fn(Ptr<'_>) -> serde_json::Result<serde_json::Value>;
 pub type DeserializeFn = fn(serde_json::Value, RefMut<Storage>) -> serde_json::Result<()>;
+pub type SerializeBinFn = fn(Ptr<'_>, &mut Vec<u8>) -> bincode::Result<()>;
+pub type DeserializeBinFn = fn(&mut &[u8], RefMut<Storage>) -> bincode::Result<()>;
 pub type AsReflectRefFn = fn(Ptr<'_>, f: &dyn Fn(Option<&dyn Reflect>));
 pub type AsReflectMutFn = fn(PtrMut<'_>, f: &dyn Fn(Option<&mut dyn Reflect>));
+pub type VariantNameFn = fn(EnumId) -> Option<&'static str>;
+pub type VariantIdFn = fn(&str) -> Option<EnumId>;
 
 #[derive(Clone)]
 pub struct Functions {
     pub clone: CloneFn,
     pub serialize: SerializeFn,
     pub deserialize: DeserializeFn,
+    pub serialize_bin: SerializeBinFn,
+    pub deserialize_bin: DeserializeBinFn,
     pub as_reflect_ref: AsReflectRefFn,
     pub as_reflect_mut: AsReflectMutFn,
+    pub drop: Option<DropFn>,
+    //only for types with `#[reflect(Default)]`
+    pub default: Option<DefaultFn>,
+}
+
+//SAFETY: the pointer has to point to a valid value of type T
+unsafe fn drop_ptr<T>(value: OwningPtr<'_>) {
+    value.drop_as::<T>();
+}
+
+fn reflect_default_fn<T: AbstractComponent>() -> Option<DefaultFn> {
+    T::get_type_registration().data::<ReflectDefault>()?;
+    Some(push_reflect_default::<T>)
+}
+
+fn push_reflect_default<T: AbstractComponent>(mut storage: RefMut<Storage>) {
+    let registration = T::get_type_registration();
+    let value = registration.data::<ReflectDefault>().unwrap().default();
+    storage.push(*value.into_any().downcast::<T>().unwrap());
 }
 
 pub struct MyTypeRegistry {
@@ -145,6 +219,25 @@ pub struct MyTypeRegistry {
     pub identifiers: HashMap<TypeId, Identifier>,
     pub identifiers_by_names: HashMap<SmolStr, Identifier>,
     pub tags: HashSet<StrippedIdentifier>,
+    pub capacity_hints: HashMap<StrippedIdentifier, usize>,
+    pub enum_variant_names: HashMap<StrippedIdentifier, VariantNameFn>,
+    pub enum_variant_ids: HashMap<StrippedIdentifier, VariantIdFn>,
+    //relations an entity can have only one target of
+    pub exclusive_relations: HashSet<StrippedIdentifier>,
+    //relations not listed here use OnTargetDelete::Remove
+    pub on_target_delete: HashMap<StrippedIdentifier, OnTargetDelete>,
+    //shared with every storage, bumped on each world run and after each query iteration
+    pub change_tick: ChangeTick,
+}
+
+//what happens to entities holding a relationship when its target is deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnTargetDelete {
+    //only the relationship is removed
+    #[default]
+    Remove,
+    //the holding entity is deleted as well
+    Delete,
 }
 
 pub enum ComponentAddState {
@@ -178,6 +271,9 @@ impl_component! {
 impl_component! {
     pub struct DynamicTag {}
 }
+impl_component! {
+    pub struct Overridden {}
+}
 
 #[derive(Debug)]
 pub enum EntityKind {
@@ -211,7 +307,7 @@ impl EntityNameGetter {
     }
 
     pub fn set(&self, name: &str) {
-        archetypes_mut(|a| a.set_entity_name(self.entity, name.into()));
+        archetypes_mut(|a| a.set_entity_name(self.entity, name.into())).unwrap();
     }
 
     pub fn set_fn<F>(&self, f: F)
@@ -219,17 +315,61 @@ impl EntityNameGetter {
         F: FnOnce() -> String,
     {
         archetypes_mut(|a| {
-            a.set_entity_name(self.entity, f().into());
+            a.set_entity_name(self.entity, f().into()).unwrap();
         });
     }
 }
 
+//keeps the resolved name key, so repeated access skips looking up the parent.
+//reparenting moves the name to a new key, which makes the handle stale until refreshed
+pub struct NameHandle {
+    entity: Entity,
+    left: NameLeft,
+}
+
+impl NameHandle {
+    pub fn new(entity: Entity) -> Self {
+        let parent = entity.name_parent();
+        Self {
+            entity,
+            left: NameLeft::from_ids(entity.0, parent.0),
+        }
+    }
+
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn is_stale(&self) -> bool {
+        archetypes(|a| !a.entity_has_name(&self.left))
+    }
+
+    pub fn refresh(&mut self) {
+        *self = Self::new(self.entity);
+    }
+
+    pub fn get<F, U>(&self, f: F) -> Option<U>
+    where
+        F: FnOnce(&str) -> U,
+    {
+        archetypes(|a| a.name_by_entity(&self.left).map(|name| f(name)))
+    }
+
+    pub fn set(&mut self, name: &str) -> std::result::Result<(), NameExistsError> {
+        if self.is_stale() {
+            self.refresh();
+        }
+        archetypes_mut(|a| a.change_entity_name(self.left, name.to_smolstr()))
+    }
+}
+
 pub struct ComponentGetter<T: AbstractComponent> {
     phantom_data: PhantomData<T>,
     component: Identifier,
     record_index: usize,
     records: Records,
     table: TableCell,
+    row: Option<TableRow>,
 }
 
 pub trait TryGetComponent<T: AbstractComponent> {
@@ -279,6 +419,23 @@ impl<T: AbstractComponent> ComponentGetter<T> {
             component,
             record_index: entity.low32() as usize,
             table: table.clone().into(),
+            row: None,
+        }
+    }
+
+    pub fn sparse(
+        entity: Identifier,
+        component: Identifier,
+        archetypes: &Archetypes,
+        sparse_set: &SparseSet,
+    ) -> Self {
+        Self {
+            phantom_data: PhantomData,
+            records: archetypes.records.clone(),
+            component,
+            record_index: entity.low32() as usize,
+            table: sparse_set.table().clone(),
+            row: sparse_set.row(entity.low32() as usize),
         }
     }
 
@@ -321,7 +478,7 @@ impl<T: AbstractComponent> ComponentGetter<T> {
             )
         });
         let storage_mut = storage.borrow();
-        let component = storage_mut.component(record.table_row);
+        let component = storage_mut.component(self.row.unwrap_or(record.table_row));
         //ooo spooky
         unsafe { &*(component.as_ptr() as *mut T) }
     }
@@ -336,8 +493,8 @@ impl<T: AbstractComponent> ComponentGetter<T> {
                 tynm::type_name::<T>()
             )
         });
-        let storage_mut = storage.borrow_mut();
-        let component = storage_mut.component(record.table_row);
+        let mut storage_mut = storage.borrow_mut();
+        let component = storage_mut.component_mut(self.row.unwrap_or(record.table_row));
         unsafe { &mut *(component.as_ptr() as *mut T) }
     }
 }
@@ -359,6 +516,12 @@ impl MyTypeRegistry {
             layouts: HashMap::new(),
             functions: HashMap::new(),
             identifiers_by_names: HashMap::new(),
+            capacity_hints: HashMap::new(),
+            enum_variant_names: HashMap::new(),
+            enum_variant_ids: HashMap::new(),
+            exclusive_relations: HashSet::new(),
+            on_target_delete: HashMap::new(),
+            change_tick: Rc::new(Cell::new(1)),
         }
     }
 
@@ -384,6 +547,9 @@ type TableVec = Vec<TableCell>;
 pub struct QueryStorage {
     pub archetypes: Vec<ArchetypeCell>,
     pub mask: FilterMask,
+    //the interned id of `mask` in the match cache
+    pub mask_id: usize,
+    pub sparse: Vec<Identifier>,
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -482,15 +648,76 @@ pub struct StateOperation {
     pub state: Rc<RefCell<dyn Any>>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorldStats {
+    pub entity_count: usize,
+    pub archetype_count: usize,
+    pub table_count: usize,
+    pub total_component_bytes: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    //allocated by tables, unused capacity included
+    pub total_bytes: usize,
+    pub table_count: usize,
+    //(name, bytes, entity count) of every stored component, heaviest first
+    pub components: Vec<(SmolStr, usize, usize)>,
+}
+
+//archetypes never change their components, so a result stays valid until the archetype is removed.
+//Masks are interned, so equal masks share results and different ones never collide. Ids are
+//never handed out twice, query storages keep theirs after the cache is cleared
+#[derive(Default)]
+pub struct MatchCache {
+    mask_ids: HashMap<FilterMask, usize>,
+    next_mask_id: usize,
+    results: HashMap<(ArchetypeId, usize), bool>,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+//masks and results are dropped all at once past these, dynamic queries and archetype churn
+//would grow them forever
+const MAX_INTERNED_MASKS: usize = 1 << 12;
+const MAX_CACHED_MATCHES: usize = 1 << 16;
+
+impl MatchCache {
+    pub fn mask_id(&mut self, mask: &FilterMask) -> usize {
+        if let Some(id) = self.mask_ids.get(mask) {
+            return *id;
+        }
+        if self.mask_ids.len() >= MAX_INTERNED_MASKS {
+            self.clear();
+        }
+        let id = self.next_mask_id;
+        self.next_mask_id += 1;
+        self.mask_ids.insert(mask.clone(), id);
+        id
+    }
+
+    fn clear(&mut self) {
+        self.mask_ids.clear();
+        self.results.clear();
+    }
+}
+
 pub type Resources = HashMap<TypeId, Rc<RefCell<dyn Any>>>;
 type Operations = Vec<ArchetypeOperation>;
 type Storages = HashMap<u64, Rc<RefCell<QueryStorage>>>;
+//built queries keyed by the type id of their (data, filter) pair
+type CachedQueries = HashMap<TypeId, Rc<dyn Any>>;
+//the running system (None outside of systems) and the query hash
+pub type QueryTickKey = (Option<SystemId>, u64);
 
 pub struct Archetypes {
     query_storages: Storages,
+    cached_queries: CachedQueries,
+    //change tick of the last finished iteration per system and query, for Added and Changed
+    query_ticks: HashMap<QueryTickKey, u32>,
     records: Records,
     type_registry: Rc<RefCell<MyTypeRegistry>>,
-    archetypes: Vec<ArchetypeCell>,
+    archetypes: Vec<Option<ArchetypeCell>>,
     archetypes_by_hashes: HashMap<u64, ArchetypeVec>,
     tables_by_hashes: HashMap<u64, TableVec>,
     names: NamesMap,
@@ -505,17 +732,24 @@ pub struct Archetypes {
     resources: Rc<RefCell<Resources>>,
     locked: bool,
     locked_depth: u32,
+    iterating_depth: u32,
     systems: Rc<RefCell<Systems>>,
     temp_components: TempComponentsStorage,
     callbacks: Rc<RefCell<OnChangeCallbacks>>,
     state_operations: Rc<RefCell<Vec<StateOperation>>>,
     entity_parser: EntityParser,
+    sparse_sets: HashMap<StrippedIdentifier, SparseSet>,
+    match_cache: RefCell<MatchCache>,
 }
 
 impl Archetypes {
     pub fn new() -> Self {
+        Self::with_capacity(ENTITIES_START_CAPACITY)
+    }
+
+    pub fn with_capacity(entities: usize) -> Self {
         let mut archetypes = Self {
-            records: RefCell::new(vec![None; ENTITIES_START_CAPACITY]).into(),
+            records: RefCell::new(vec![None; entities]).into(),
             archetypes: vec![],
             type_registry: Rc::new(MyTypeRegistry::new().into()),
             archetypes_by_ids: HashMap::new(),
@@ -524,13 +758,16 @@ impl Archetypes {
             unused_ids: VecDeque::new(),
             entity_id: 0,
             query_storages: HashMap::new(),
+            cached_queries: HashMap::new(),
+            query_ticks: HashMap::new(),
             names: BiHashMap::new(),
-            children_pool: RefCell::new(vec![]).into(),
-            operations: RefCell::new(vec![]).into(),
+            children_pool: RefCell::new(Vec::with_capacity(entities)).into(),
+            operations: RefCell::new(Vec::with_capacity(entities)).into(),
             locked: false,
             locked_depth: 0,
-            operatoins_pool: RefCell::new(vec![]).into(),
-            entities_pool: RefCell::new(vec![]).into(),
+            iterating_depth: 0,
+            operatoins_pool: RefCell::new(Vec::with_capacity(entities)).into(),
+            entities_pool: RefCell::new(Vec::with_capacity(entities)).into(),
             resources: RefCell::new(HashMap::new()).into(),
             unique_names: HashSet::new(),
             systems: RefCell::new(Systems::new()).into(),
@@ -538,6 +775,8 @@ impl Archetypes {
             callbacks: RefCell::new(OnChangeCallbacks::new()).into(),
             state_operations: RefCell::new(vec![]).into(),
             entity_parser: EntityParser::new(),
+            sparse_sets: HashMap::new(),
+            match_cache: RefCell::new(MatchCache::default()),
         };
         {
             let mut registry = archetypes.type_registry.borrow_mut();
@@ -558,15 +797,79 @@ impl Archetypes {
             archetypes.type_registry.clone(),
         )
         .into();
-        archetypes.add_archetype(&table, &entity_archetype_components);
+        archetypes
+            .add_archetype(&table, &entity_archetype_components)
+            .borrow_mut()
+            .reserve(entities);
         archetypes.register_component::<InstanceOf>();
         archetypes.register_component::<EnumTagId>();
         archetypes.register_component::<ChildOf>();
         archetypes.register_component::<Prefab>();
         archetypes.register_component::<DynamicTag>();
+        archetypes.register_component::<Overridden>();
+        let child_of = archetypes.component_id::<ChildOf>();
+        archetypes.register_exclusive_relation(child_of);
         archetypes
     }
 
+    pub fn register_exclusive_relation(&mut self, relation: Identifier) {
+        self.type_registry
+            .borrow_mut()
+            .exclusive_relations
+            .insert(relation.stripped());
+    }
+
+    pub fn set_on_target_delete(&mut self, relation: Identifier, policy: OnTargetDelete) {
+        self.type_registry
+            .borrow_mut()
+            .on_target_delete
+            .insert(relation.stripped(), policy);
+    }
+
+    fn on_target_delete(&self, relationship: Identifier) -> OnTargetDelete {
+        self.relation_entity(relationship)
+            .and_then(|relation| {
+                self.type_registry()
+                    .on_target_delete
+                    .get(&relation.stripped())
+                    .copied()
+            })
+            .unwrap_or_default()
+    }
+
+    //removes every (relation, *) relationship of the entity except the one with `target`
+    fn remove_other_targets(
+        &mut self,
+        entity: Identifier,
+        relation: Identifier,
+        target: Identifier,
+    ) -> Result<()> {
+        let Some(record) = self.record(entity) else {
+            bail!("expected valid entity record");
+        };
+        let components = self
+            .archetype_by_id(record.arhetype_id)
+            .borrow()
+            .components_ids_set_rc()
+            .clone();
+        for component in components.iter().copied() {
+            if !component.is_relationship()
+                || self.relation_entity(component).map(|r| r.stripped())
+                    != Some(relation.stripped())
+                || self.target_entity(component).map(|t| t.stripped()) == Some(target.stripped())
+            {
+                continue;
+            }
+            let table_reusage = if self.is_component_empty(component) {
+                TableReusage::Reuse
+            } else {
+                TableReusage::New
+            };
+            self.remove_component(component, entity, table_reusage)?;
+        }
+        Ok(())
+    }
+
     pub fn systems(&mut self) -> &Rc<RefCell<Systems>> {
         &self.systems
     }
@@ -595,25 +898,92 @@ impl Archetypes {
         }
     }
 
+    pub fn records_capacity(&self) -> usize {
+        self.records.borrow().len()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn change_tick(&self) -> u32 {
+        self.type_registry.borrow().change_tick.get()
+    }
+
+    //returns the tick before the increment
+    pub fn query_tick(&self, key: QueryTickKey) -> u32 {
+        self.query_ticks.get(&key).copied().unwrap_or(0)
+    }
+
+    pub fn set_query_tick(&mut self, key: QueryTickKey, tick: u32) {
+        self.query_ticks.insert(key, tick);
+    }
+
+    pub fn current_system_id(&self) -> Option<SystemId> {
+        let resource = self
+            .resources
+            .borrow()
+            .get(&TypeId::of::<CurrentSystemTypeId>())
+            .cloned()?;
+        let resource = resource.borrow();
+        resource
+            .downcast_ref::<CurrentSystemTypeId>()
+            .map(|id| id.value)
+    }
+
+    pub fn increment_change_tick(&self) -> u32 {
+        let registry = self.type_registry.borrow();
+        let tick = registry.change_tick.get();
+        registry.change_tick.set(tick + 1);
+        tick
+    }
+
     pub fn lock(&mut self) {
         self.locked_depth += 1;
         self.locked = true;
     }
 
     pub fn unlock(&mut self) {
-        self.locked_depth = u32::max(0, self.locked_depth - 1);
+        //a stray unlock without a matching lock
+        if self.locked_depth == 0 {
+            return;
+        }
+        self.locked_depth = self.locked_depth.saturating_sub(1);
         if self.locked_depth > 0 {
             return;
         }
 
         self.locked = false;
 
+        //most queries don't defer anything, so skip the drain pass entirely
+        if self.operations.borrow().is_empty() {
+            return;
+        }
+
         for operation in self.operations.clone().borrow_mut().drain(..) {
             if !self.is_entity_alive(operation.entity) {
                 continue;
             }
 
             match operation.op_type {
+                OperationType::AddComponent {
+                    component_id,
+                    table_reusage: _,
+                } if self.is_sparse(component_id) => {
+                    let component = self.temp_components.remove_comp(component_id);
+                    let sparse_set = self.sparse_sets.get_mut(&component_id.stripped()).unwrap();
+                    let replaced = sparse_set.contains(operation.entity.low32() as usize);
+                    sparse_set.insert_ptr(operation.entity.low32() as usize, component);
+                    self.callbacks.borrow_mut().queue_set_callbacks(
+                        component_id,
+                        operation.entity,
+                        replaced,
+                    );
+                }
+                OperationType::AddSparseTag(tag) => {
+                    let sparse_set = self.sparse_sets.get_mut(&tag.stripped()).unwrap();
+                    sparse_set.insert(operation.entity.low32() as usize, ());
+                }
                 OperationType::AddComponent {
                     component_id,
                     table_reusage,
@@ -625,15 +995,27 @@ impl Archetypes {
                     let component = self.temp_components.remove_comp(component_id);
 
                     let mut archetype = archetype.borrow_mut();
-                    match add_state {
+                    let replaced = match add_state {
                         ComponentAddState::New => {
                             archetype.push_component_ptr(component_id, component);
+                            false
                         }
                         ComponentAddState::AlreadyExisted => {
                             let table_mut = archetype.table().borrow_mut();
                             let mut storage = table_mut.storage(component_id).unwrap().borrow_mut();
                             storage.replace_unchecked_ptr(table_row, component);
+                            true
                         }
+                    };
+                    drop(archetype);
+                    self.callbacks.borrow_mut().queue_set_callbacks(
+                        component_id,
+                        operation.entity,
+                        replaced,
+                    );
+                    //deferred data relationships skip add_relationship
+                    if !replaced && component_id.is_relationship() {
+                        self.queue_rel_add_callbacks(component_id, operation.entity);
                     }
                 }
                 OperationType::RemoveComponent(component) => {
@@ -655,6 +1037,32 @@ impl Archetypes {
         }
     }
 
+    //query iterators lock through these, so flush can tell their locks from manual ones
+    pub fn lock_iteration(&mut self) {
+        self.iterating_depth += 1;
+        self.lock();
+    }
+
+    pub fn unlock_iteration(&mut self) {
+        self.iterating_depth = self.iterating_depth.saturating_sub(1);
+        self.unlock();
+    }
+
+    //releases every manual lock and applies deferred operations. A live query iterator
+    //would see its rows move, so flushing under one fails
+    pub fn flush(&mut self) -> Result<()> {
+        if self.iterating_depth > 0 {
+            bail!("can't flush while a query is iterating")
+        }
+        if self.locked_depth == 0 {
+            self.locked_depth = 1;
+        }
+        while self.locked_depth > 0 {
+            self.unlock();
+        }
+        Ok(())
+    }
+
     pub fn entities_pool_rc(&self) -> &Rc<RefCell<Vec<Identifier>>> {
         &self.entities_pool
     }
@@ -667,14 +1075,7 @@ impl Archetypes {
         let Some(record) = self.record(id) else {
             return format!("Invalid entity {0:?}", id).into();
         };
-        let parent = {
-            let child_of_rel = self.find_rels::<ChildOf, Wildcard>(&record).unwrap().next();
-            if let Some(child_of_rel) = child_of_rel {
-                self.target_entity(child_of_rel.0).unwrap()
-            } else {
-                WILDCARD.0
-            }
-        };
+        let parent = self.name_parent(&record);
         if let Some(name) = self.name_by_entity(&NameLeft {
             entity_index: id.low32() as _,
             parent_index: parent.low32() as _,
@@ -703,13 +1104,26 @@ impl Archetypes {
                 .1
                 .clone(),
             CT::EntityTag => u64::from(id).to_smolstr(),
-            CT::EnumTag => "TODO".to_smolstr(),
+            CT::EnumTag => match self.relation_entity(id) {
+                Some(enum_type) => self.debug_id_name(enum_type),
+                None => format!("Invalid entity {0:?} - no enum type", id).into(),
+            },
             CT::RelationshipComponentTag | CT::MixedRelationshipTag | CT::DataRelationship(_) => {
                 self.relationship_component_name(id)
             }
         }
     }
 
+    //like debug_id_name, but enum tags resolve to the variant stored on the entity
+    pub fn debug_component_name_on(&self, id: Identifier, entity: Identifier) -> SmolStr {
+        if let Ok(ComponentType::EnumTag) = self.component_type(id) {
+            if let Some((type_name, variant)) = self.enum_tag_variant(id, entity) {
+                return format_smolstr!("{type_name}::{variant}");
+            }
+        }
+        self.debug_id_name(id)
+    }
+
     pub fn relationship_component_name(&self, id: Identifier) -> SmolStr {
         let Some(relation) = self.relation_entity(id) else {
             return format!("Invalid entity {0:?} - no relation entity", id).into();
@@ -728,6 +1142,12 @@ impl Archetypes {
         self.has_component(COMPONENT_ID, id)
     }
 
+    //registered types, as opposed to entities used as components
+    pub fn is_type_component(&self, id: Identifier) -> bool {
+        self.get_component::<Component>(COMPONENT_ID, id)
+            .is_ok_and(|c| c.get(|c| c.is_type))
+    }
+
     pub fn component_type(
         &self,
         component: Identifier,
@@ -812,8 +1232,16 @@ impl Archetypes {
     //6) Enum tags
     //TODO: add enum tags support, add full #'entity_name' support, clean code
     pub fn serialize_entity(&self, entity: Identifier) -> Option<String> {
-        let registry = self.type_registry.clone();
-        let registry_ref = registry.borrow();
+        let json_value = self.serialize_entity_value(entity, false)?;
+        Some(serde_json::to_string_pretty(&json_value).unwrap())
+    }
+
+    //with `by_reference`, components pointing at other entities go to "Refs" as world keys
+    fn serialize_entity_value(
+        &self,
+        entity: Identifier,
+        by_reference: bool,
+    ) -> Option<serde_json::Value> {
         let record = self.record(entity)?;
         let archetype = self.archetype_by_id(record.arhetype_id).clone();
         let archetype_ref = archetype.borrow();
@@ -821,31 +1249,24 @@ impl Archetypes {
 
         let mut json_value = serde_json::json!({});
         let mut tags = serde_json::json!([]);
+        let mut refs = serde_json::json!([]);
 
         for component in components.iter().copied() {
             use ComponentType as CT;
+            //added back once the entity is used as a component again
+            if by_reference && component.stripped() == COMPONENT_ID.stripped() {
+                continue;
+            }
+            if by_reference && self.references_entity(component) {
+                let reference = self.serialize_reference(component, &record, &archetype_ref)?;
+                refs.as_array_mut().unwrap().push(reference);
+                continue;
+            }
             let debug_name_smol = self.debug_id_name(component);
             let debug_name = debug_name_smol.to_string();
             match self.component_type(component).unwrap() {
                 CT::DataRelationship(data_pos) => {
-                    let serialize = registry_ref
-                        .functions
-                        .get(&component.stripped())
-                        .unwrap()
-                        .serialize;
-                    let storage = archetype_ref
-                        .table()
-                        .borrow()
-                        .storage(component)
-                        .unwrap()
-                        .clone();
-                    let storage_mut = storage.borrow_mut();
-                    let component_ptr: *mut u8 =
-                        unsafe { storage_mut.0.get_checked(record.table_row.0).as_ptr() };
-
-                    let component_value =
-                        serialize(unsafe { Ptr::new(NonNull::new(component_ptr).unwrap()) })
-                            .unwrap();
+                    let component_value = self.serialize_stored(component, &record, &archetype_ref);
                     let insertion_pos = match data_pos {
                         RelationshipDataPosition::First => 1,
                         RelationshipDataPosition::Second => debug_name.find(',').unwrap() + 2,
@@ -861,24 +1282,7 @@ impl Archetypes {
                         .insert(debug_name, component_value);
                 }
                 CT::RegularComponent => {
-                    let serialize = registry_ref
-                        .functions
-                        .get(&component.stripped())
-                        .unwrap()
-                        .serialize;
-                    let storage = archetype_ref
-                        .table()
-                        .borrow()
-                        .storage(component)
-                        .unwrap()
-                        .clone();
-                    let storage_mut = storage.borrow_mut();
-                    let component_ptr: *mut u8 =
-                        unsafe { storage_mut.0.get_checked(record.table_row.0).as_ptr() };
-
-                    let component_value =
-                        serialize(unsafe { Ptr::new(NonNull::new(component_ptr).unwrap()) })
-                            .unwrap();
+                    let component_value = self.serialize_stored(component, &record, &archetype_ref);
                     let _ = json_value
                         .as_object_mut()
                         .unwrap()
@@ -893,19 +1297,53 @@ impl Archetypes {
                     }
                 }
                 CT::MixedRelationshipTag => {}
-                CT::EnumTag => tags.as_array_mut().unwrap().push("TODO".into()),
+                CT::EnumTag => {
+                    if let Some((type_name, variant)) = self.enum_tag_variant(component, entity) {
+                        tags.as_array_mut()
+                            .unwrap()
+                            .push(format!("{type_name}::{variant}").into());
+                    }
+                }
                 CT::RelationshipComponentTag => {
                     tags.as_array_mut().unwrap().push(debug_name.into())
                 }
             }
         }
+        for sparse_set in self.sparse_sets.values() {
+            let Some(row) = sparse_set.row(entity.low32() as usize) else {
+                continue;
+            };
+            let component = sparse_set.component();
+            let debug_name = self.debug_id_name(component).to_string();
+            if self.is_component_empty(component) {
+                tags.as_array_mut().unwrap().push(debug_name.into());
+                continue;
+            }
+            let value = self.serialize_in_table(component, &sparse_set.table().borrow(), row);
+            json_value
+                .as_object_mut()
+                .unwrap()
+                .insert(debug_name, value);
+        }
         if !tags.as_array().unwrap().is_empty() {
             json_value
                 .as_object_mut()
                 .unwrap()
                 .insert("Tags".into(), tags);
         }
-        let name_left = NameLeft::from_ids(entity, WILDCARD.0);
+        if !refs.as_array().unwrap().is_empty() {
+            json_value
+                .as_object_mut()
+                .unwrap()
+                .insert("Refs".into(), refs);
+        }
+        //children are named in their parent's scope, which only a whole world can restore
+        let name_parent = if by_reference {
+            self.name_parent(&record)
+        } else {
+            WILDCARD.0
+        };
+        let name_left = NameLeft::from_ids(entity, name_parent);
         if self.entity_has_name(&name_left) {
             let name = self.name_by_entity(&name_left).unwrap();
             json_value
@@ -914,145 +1352,767 @@ impl Archetypes {
                 .insert("Name".into(), name.to_string().into());
         }
 
-        Some(serde_json::to_string_pretty(&json_value).unwrap())
+        Some(json_value)
     }
 
-    fn tag_by_id_or_name(&mut self, id_or_name: IdOrName) -> (Identifier, TagType) {
-        use crate::either::Either;
-        match id_or_name {
-            Either::First((id, tag_type)) => (id, tag_type),
-            Either::Second(name) => {
-                if let Some(entity) = self.entity_by_global_name(name.clone()) {
-                    return (entity, TagType::Entity);
-                }
-                let tag = self.add_entity(EntityKind::Regular);
-                self.set_entity_name(NameLeft::global(tag), name);
-                self.add_component_tag(self.component_id::<DynamicTag>(), tag)
-                    .unwrap();
-                (tag, TagType::Entity)
+    fn serialize_stored(
+        &self,
+        component: Identifier,
+        record: &EntityRecord,
+        archetype: &Archetype,
+    ) -> serde_json::Value {
+        self.serialize_in_table(component, &archetype.table().borrow(), record.table_row)
+    }
+
+    fn serialize_in_table(
+        &self,
+        component: Identifier,
+        table: &Table,
+        row: TableRow,
+    ) -> serde_json::Value {
+        let serialize = self
+            .type_registry()
+            .functions
+            .get(&component.stripped())
+            .unwrap()
+            .serialize;
+        let storage = table.storage(component).unwrap().clone();
+        let storage_mut = storage.borrow_mut();
+        let component_ptr: *mut u8 = unsafe { storage_mut.0.get_checked(row.0).as_ptr() };
+        serialize(unsafe { Ptr::new(NonNull::new(component_ptr).unwrap()) }).unwrap()
+    }
+
+    //entity tags and relationships with an entity (not a type) on either side
+    fn references_entity(&self, component: Identifier) -> bool {
+        if !component.is_relationship() {
+            return !self.is_type_component(component);
+        }
+        match (
+            self.relation_entity(component),
+            self.target_entity(component),
+        ) {
+            (Some(relation), Some(target)) => {
+                !self.is_type_component(relation) || !self.is_type_component(target)
             }
+            _ => false,
         }
     }
 
-    pub fn deserialize_entity(&mut self, json: &str) -> Result<Entity, ParseError> {
-        let entity = self.add_entity(EntityKind::Regular);
-        for parsed_component in self.entity_parser.parse(json, self)? {
-            match parsed_component {
-                ParsedEntityItem::Tag(id_or_name) => {
-                    let name = id_or_name.clone();
-                    let (tag, tag_type) = self.tag_by_id_or_name(id_or_name);
-                    self.add_entity_tag_inner(entity, tag, tag_type.is_type())
-                        .unwrap();
-                }
-                ParsedEntityItem::RelationshipTag(relation, target) => {
-                    let (relation, _) = self.tag_by_id_or_name(relation);
-                    let (target, _) = self.tag_by_id_or_name(target);
-                    self.add_relationship(entity, relation, target, TableReusage::New)
-                        .unwrap();
-                }
-                ParsedEntityItem::Component(id, deserialize_fn, value, comp_type) => {
-                    let (archetype, _) = match comp_type {
-                        entity_parser::ComponentType::Regular => {
-                            self.add_component(id, entity, TableReusage::New).unwrap()
-                        }
-                        entity_parser::ComponentType::DataRelationship(data_pos) => self
-                            .add_data_relationship(
-                                entity,
-                                self.relation_entity(id).unwrap(),
-                                self.target_entity(id).unwrap(),
-                                data_pos,
-                            )
-                            .unwrap(),
-                    };
-                    let storage = archetype
-                        .borrow()
-                        .table()
-                        .borrow()
-                        .storage(id)
-                        .unwrap()
-                        .clone();
-                    let storage = storage.borrow_mut();
-                    deserialize_fn(value, storage).unwrap();
-                }
-                ParsedEntityItem::Name(name) => {
-                    self.set_entity_name(NameLeft::global(entity), name);
-                }
-            }
+    fn serialize_reference(
+        &self,
+        component: Identifier,
+        record: &EntityRecord,
+        archetype: &Archetype,
+    ) -> Option<serde_json::Value> {
+        let mut reference = serde_json::Map::new();
+        if !component.is_relationship() {
+            reference.insert("Tag".into(), self.world_reference(component).into());
+            return Some(reference.into());
         }
+        let relation = self.relation_entity(component)?;
+        let target = self.target_entity(component)?;
+        reference.insert("Relation".into(), self.world_reference(relation).into());
+        reference.insert("Target".into(), self.world_reference(target).into());
+        if let Ok(ComponentType::DataRelationship(_)) = self.component_type(component) {
+            let data = self.serialize_stored(component, record, archetype);
+            reference.insert("Data".into(), data);
+        }
+        Some(reference.into())
+    }
 
-        Ok(entity.into())
+    //types are referenced by name, entities by their world key prefixed with #
+    fn world_reference(&self, id: Identifier) -> String {
+        if self.is_type_component(id) {
+            self.debug_component_name(id).to_string()
+        } else {
+            format!("#{}", id.low32())
+        }
     }
 
-    pub fn clone_entity(&mut self, entity: Identifier) -> Option<Identifier> {
-        let cloned_entity = self.add_entity(EntityKind::Regular);
-        let old_record = self.record(entity)?;
-        let old_archetype = self.archetype_by_id(old_record.arhetype_id).clone();
-        let old_archetype_ref = old_archetype.borrow();
-        let registry = self.type_registry.clone();
-        let registry_ref = registry.borrow();
-        let components = old_archetype_ref.components_ids_set_rc().clone();
-        drop(old_archetype_ref);
+    fn name_parent(&self, record: &EntityRecord) -> Identifier {
+        let child_of_rel = self.find_rels::<ChildOf, Wildcard>(record).unwrap().next();
+        if let Some(child_of_rel) = child_of_rel {
+            self.target_entity(child_of_rel.0).unwrap()
+        } else {
+            WILDCARD.0
+        }
+    }
 
-        for component in components.iter().copied() {
-            let table_reusage = if self.is_component_empty(component) {
-                TableReusage::Reuse
-            } else {
-                TableReusage::New
+    //every entity except component types, keyed by its index
+    pub fn serialize_world(&self) -> serde_json::Value {
+        let entities: Vec<_> = self
+            .records
+            .borrow()
+            .iter()
+            .flatten()
+            .map(|r| r.entity)
+            .filter(|e| !self.is_type_component(*e))
+            .collect();
+        let mut world = serde_json::Map::new();
+        for entity in entities {
+            if let Some(value) = self.serialize_entity_value(entity, true) {
+                world.insert(entity.low32().to_string(), value);
+            }
+        }
+        world.into()
+    }
+
+    //same entities as serialize_world, written column by column
+    pub fn save_binary(&self) -> Vec<u8> {
+        let mut by_archetype: HashMap<ArchetypeId, Vec<EntityRecord>> = HashMap::new();
+        for record in self.records.borrow().iter().flatten() {
+            if !self.is_type_component(record.entity) {
+                by_archetype
+                    .entry(record.arhetype_id)
+                    .or_default()
+                    .push(*record);
+            }
+        }
+        let mut snapshot = WorldSnapshot::default();
+        for (archetype_id, records) in by_archetype {
+            let archetype = self.archetype_by_id(archetype_id).borrow();
+            let table = archetype.table().borrow();
+            let mut archetype_snapshot = ArchetypeSnapshot {
+                entities: records.iter().map(|r| r.entity.low32()).collect(),
+                ..Default::default()
+            };
+            for component in archetype.components_ids_set_rc().iter().copied() {
+                if component == ENTITY_ID || component.stripped() == COMPONENT_ID.stripped() {
+                    continue;
+                }
+                let mut column = vec![];
+                let storage = table.storage(component);
+                if let Some(storage) = storage {
+                    let serialize_bin =
+                        self.type_registry().functions[&component.stripped()].serialize_bin;
+                    let storage = storage.borrow();
+                    for record in records.iter() {
+                        let value = storage.try_component(record.table_row).unwrap();
+                        serialize_bin(value, &mut column).unwrap();
+                    }
+                }
+                archetype_snapshot
+                    .ids
+                    .push(self.snapshot_id(component, storage.is_some()));
+                archetype_snapshot.columns.push(column);
+            }
+            snapshot.archetypes.push(archetype_snapshot);
+        }
+
+        for sparse_set in self.sparse_sets.values() {
+            let component = sparse_set.component();
+            let mut set_snapshot = SparseSetSnapshot {
+                component: self.debug_component_name(component).to_string(),
+                entities: vec![],
+                column: vec![],
             };
-            let (cloned_archetype, _) = self
-                .add_component(component, cloned_entity, table_reusage)
-                .ok()?;
+            let serialize_bin = self
+                .type_registry()
+                .functions
+                .get(&component.stripped())
+                .map(|f| f.serialize_bin);
+            let table = sparse_set.table().borrow();
+            for entity in sparse_set.entity_indices() {
+                set_snapshot.entities.push(entity as u32);
+                if let Some(serialize_bin) = serialize_bin {
+                    let storage = table.storage(component).unwrap().borrow();
+                    let value = storage
+                        .try_component(sparse_set.row(entity).unwrap())
+                        .unwrap();
+                    serialize_bin(value, &mut set_snapshot.column).unwrap();
+                }
+            }
+            snapshot.sparse_sets.push(set_snapshot);
+        }
 
-            if matches!(table_reusage, TableReusage::Reuse) {
+        for record in self.records.borrow().iter().flatten() {
+            if self.is_type_component(record.entity) {
                 continue;
             }
+            let name_left = NameLeft::from_ids(record.entity, self.name_parent(record));
+            if let Some(name) = self.name_by_entity(&name_left) {
+                snapshot
+                    .names
+                    .push((record.entity.low32(), name.to_string()));
+            }
+        }
+        bincode::serialize(&snapshot).unwrap()
+    }
+
+    fn snapshot_id(&self, id: Identifier, has_data: bool) -> IdSnapshot {
+        let part = |id: Identifier| {
+            if self.is_type_component(id) {
+                IdPart::Type(self.debug_component_name(id).to_string())
+            } else {
+                IdPart::Entity(id.low32())
+            }
+        };
+        if !id.is_relationship() {
+            return IdSnapshot::Single(part(id));
+        }
+        IdSnapshot::Pair {
+            relation: part(self.relation_entity(id).unwrap()),
+            target: part(self.target_entity(id).unwrap()),
+            has_data,
+        }
+    }
+
+    //returns the spawned entities by their indices in the saved world
+    pub fn load_binary(&mut self, bytes: &[u8]) -> Result<HashMap<SmolStr, Entity>, ParseError> {
+        let snapshot: WorldSnapshot = bincode::deserialize(bytes)?;
+        let mut entities = HashMap::new();
+        for key in snapshot.archetypes.iter().flat_map(|a| a.entities.iter()) {
+            entities.insert(*key, self.add_entity(EntityKind::Regular));
+        }
+        let resolve = |archetypes: &Self, part: &IdPart| match part {
+            IdPart::Type(name) => archetypes
+                .type_registry()
+                .identifiers_by_names
+                .get(name.as_str())
+                .copied()
+                .ok_or_else(|| ParseError::UnknownType(name.to_smolstr())),
+            IdPart::Entity(key) => entities
+                .get(key)
+                .copied()
+                .ok_or_else(|| ParseError::UnknownType(format_smolstr!("#{key}"))),
+        };
+
+        for archetype in snapshot.archetypes.iter() {
+            let mut ids = vec![];
+            for id in archetype.ids.iter() {
+                ids.push(match id {
+                    IdSnapshot::Single(part) => (resolve(self, part)?, None),
+                    IdSnapshot::Pair {
+                        relation,
+                        target,
+                        has_data,
+                    } => {
+                        let relation = resolve(self, relation)?;
+                        let target = resolve(self, target)?;
+                        let data_pos = has_data.then(|| {
+                            if self
+                                .type_registry()
+                                .layouts
+                                .contains_key(&relation.stripped())
+                            {
+                                RelDataPosition::First
+                            } else {
+                                RelDataPosition::Second
+                            }
+                        });
+                        (relation, Some((target, data_pos)))
+                    }
+                });
+            }
+            let mut columns: Vec<&[u8]> = archetype.columns.iter().map(|c| c.as_slice()).collect();
+            for key in archetype.entities.iter() {
+                let entity = entities[key];
+                for (&(id, pair), column) in ids.iter().zip(columns.iter_mut()) {
+                    self.load_snapshot_id(entity, id, pair, column)?;
+                }
+            }
+        }
 
-            let old_archetype_ref = old_archetype.borrow();
-            let cloned_archetype_ref = cloned_archetype.borrow();
-            let clone_into = registry_ref
+        for sparse_set in snapshot.sparse_sets.iter() {
+            let component = resolve(self, &IdPart::Type(sparse_set.component.clone()))?;
+            let functions = self
+                .type_registry()
                 .functions
                 .get(&component.stripped())
-                .unwrap()
-                .clone;
-            let old_storage = old_archetype_ref
-                .table()
-                .borrow()
-                .storage(component)
-                .unwrap()
-                .clone();
-            let old_storage_mut = old_storage.borrow_mut();
-            let component_ptr: *mut u8 = unsafe {
-                old_storage_mut
+                .cloned();
+            let mut column = sparse_set.column.as_slice();
+            for key in sparse_set.entities.iter() {
+                let entity = resolve(self, &IdPart::Entity(*key))?;
+                let Some(functions) = &functions else {
+                    self.add_component_tag(entity, component).unwrap();
+                    continue;
+                };
+                self.add_component_with(entity, component, functions, |storage| {
+                    Ok((functions.deserialize_bin)(&mut column, storage)?)
+                })
+                .map_err(|err| err.downcast::<bincode::Error>().unwrap())?;
+            }
+        }
+
+        for (key, name) in snapshot.names {
+            let entity = resolve(self, &IdPart::Entity(key))?;
+            let record = self.record(entity).unwrap();
+            let name_left = NameLeft::from_ids(entity, self.name_parent(&record));
+            self.set_entity_name(name_left, name.to_smolstr())?;
+        }
+        Ok(entities
+            .into_iter()
+            .map(|(key, entity)| (key.to_smolstr(), Entity(entity)))
+            .collect())
+    }
+
+    //`pair` holds the target and the data position of a relationship with `id` as its relation
+    fn load_snapshot_id(
+        &mut self,
+        entity: Identifier,
+        id: Identifier,
+        pair: Option<(Identifier, Option<RelDataPosition>)>,
+        column: &mut &[u8],
+    ) -> Result<(), ParseError> {
+        let archetype = match pair {
+            None if !self.is_type_component(id) => {
+                self.add_entity_tag_inner(entity, id, false).unwrap();
+                return Ok(());
+            }
+            None if self.is_component_empty(id) => {
+                self.add_component_tag(entity, id).unwrap();
+                return Ok(());
+            }
+            None => self.add_component(id, entity, TableReusage::New).unwrap().0,
+            Some((target, None)) => {
+                self.add_relationship(entity, id, target, TableReusage::New)
+                    .unwrap();
+                return Ok(());
+            }
+            Some((target, Some(data_pos))) => {
+                self.add_data_relationship(entity, id, target, data_pos)
+                    .unwrap()
                     .0
-                    .get_checked(old_record.table_row.0)
-                    .as_ptr()
+            }
+        };
+        let stored = match pair {
+            Some((target, _)) => Archetypes::relationship_id(id, target),
+            None => id,
+        };
+        let deserialize_bin = self.type_registry().functions[&stored.stripped()].deserialize_bin;
+        let storage = archetype
+            .borrow()
+            .table()
+            .borrow()
+            .storage(stored)
+            .unwrap()
+            .clone();
+        deserialize_bin(column, storage.borrow_mut())?;
+        Ok(())
+    }
+
+    //entities are spawned first, so references resolve regardless of order
+    pub fn deserialize_world(
+        &mut self,
+        json: &serde_json::Value,
+    ) -> Result<HashMap<SmolStr, Entity>, ParseError> {
+        let Some(world) = json.as_object() else {
+            return Err(ParseError::JsonIsNotObject);
+        };
+        let mut entities = HashMap::new();
+        for (key, value) in world.iter() {
+            let Some(object) = value.as_object() else {
+                return Err(ParseError::JsonIsNotObject);
             };
-            let cloned_storage = cloned_archetype_ref
-                .table()
-                .borrow()
-                .storage(component)
-                .unwrap()
-                .clone();
-            if Rc::ptr_eq(&cloned_storage, &old_storage) {
-                clone_into(
-                    unsafe { Ptr::new(NonNull::new(component_ptr).unwrap()) },
-                    old_storage_mut,
-                );
-            } else {
-                let cloned_storage_mut = cloned_storage.borrow_mut();
-                clone_into(
-                    unsafe { Ptr::new(NonNull::new(component_ptr).unwrap()) },
-                    cloned_storage_mut,
-                );
+            let mut object = object.clone();
+            object.remove("Refs");
+            object.remove("Name");
+            let entity = self.deserialize_entity_value(&object.into())?;
+            entities.insert(key.to_smolstr(), entity);
+        }
+
+        for (key, value) in world.iter() {
+            let entity = entities[key.as_str()].0;
+            let refs = value.get("Refs").and_then(|r| r.as_array());
+            for reference in refs.into_iter().flatten() {
+                self.deserialize_reference(entity, reference, &entities)?;
             }
+        }
+
+        for (key, value) in world.iter() {
+            let Some(name) = value.get("Name").and_then(|n| n.as_str()) else {
+                continue;
+            };
+            let entity = entities[key.as_str()].0;
+            let record = self.record(entity).unwrap();
+            let name_left = NameLeft::from_ids(entity, self.name_parent(&record));
+            self.set_entity_name(name_left, name.to_smolstr())?;
+        }
+        Ok(entities)
+    }
+
+    fn deserialize_reference(
+        &mut self,
+        entity: Identifier,
+        reference: &serde_json::Value,
+        entities: &HashMap<SmolStr, Entity>,
+    ) -> Result<(), ParseError> {
+        let resolve = |archetypes: &Self, name: &serde_json::Value| {
+            let name = name.as_str().unwrap_or_default().to_smolstr();
+            let id = match name.strip_prefix('#') {
+                Some(key) => entities.get(key).map(|e| e.0),
+                None => archetypes
+                    .type_registry()
+                    .identifiers_by_names
+                    .get(&name)
+                    .copied(),
+            };
+            id.ok_or(ParseError::UnknownType(name))
+        };
+        if let Some(tag) = reference.get("Tag") {
+            let tag = resolve(self, tag)?;
+            self.add_entity_tag_inner(entity, tag, false).unwrap();
+            return Ok(());
+        }
+        let (Some(relation), Some(target)) = (reference.get("Relation"), reference.get("Target"))
+        else {
+            return Err(ParseError::InvalidReference);
+        };
+        let relation = resolve(self, relation)?;
+        let target = resolve(self, target)?;
+        let Some(data) = reference.get("Data") else {
+            self.add_relationship(entity, relation, target, TableReusage::New)
+                .unwrap();
+            return Ok(());
+        };
+        let (data_pos, data_type) = if self
+            .type_registry()
+            .layouts
+            .contains_key(&relation.stripped())
+        {
+            (RelDataPosition::First, relation)
+        } else {
+            (RelDataPosition::Second, target)
+        };
+        let deserialize_fn = self.type_registry().functions[&data_type.stripped()].deserialize;
+        let relationship = Archetypes::relationship_id(relation, target);
+        self.deserialize_component(
+            entity,
+            relationship,
+            deserialize_fn,
+            data.clone(),
+            entity_parser::ComponentType::DataRelationship(data_pos),
+        )?;
+        Ok(())
+    }
+
+    fn tag_by_id_or_name(&mut self, id_or_name: IdOrName) -> (Identifier, TagType) {
+        use crate::either::Either;
+        match id_or_name {
+            Either::First((id, tag_type)) => (id, tag_type),
+            Either::Second(name) => {
+                if let Some(entity) = self.entity_by_global_name(name.clone()) {
+                    return (entity, TagType::Entity);
+                }
+                let tag = self.add_entity(EntityKind::Regular);
+                self.set_entity_name(NameLeft::global(tag), name).unwrap();
+                self.add_component_tag(self.component_id::<DynamicTag>(), tag)
+                    .unwrap();
+                (tag, TagType::Entity)
+            }
+        }
+    }
+
+    pub fn deserialize_entity(&mut self, json: &str) -> Result<Entity, ParseError> {
+        let value = serde_json::from_str::<serde_json::Value>(json)?;
+        self.deserialize_entity_value(&value)
+    }
+
+    pub fn spawn_from_serialized(&mut self, json: &str) -> Result<Entity, ParseError> {
+        let value = serde_json::from_str::<serde_json::Value>(json)?;
+        let unregistered = self.entity_parser.unregistered_types(&value, self);
+        if !unregistered.is_empty() {
+            return Err(ParseError::UnregisteredTypes(unregistered));
+        }
+        self.deserialize_entity_value(&value)
+    }
+
+    fn deserialize_entity_value(
+        &mut self,
+        value: &serde_json::Value,
+    ) -> Result<Entity, ParseError> {
+        let entity = self.add_entity(EntityKind::Regular);
+        //don't leave a half built entity behind
+        if let Err(err) = self.deserialize_into(entity, value) {
+            let pool = self.entities_pool.clone();
+            self.remove_entity(entity, 0.into(), &mut pool.borrow_mut())
+                .unwrap();
+            return Err(err);
+        }
+        Ok(entity.into())
+    }
+
+    fn deserialize_into(
+        &mut self,
+        entity: Identifier,
+        value: &serde_json::Value,
+    ) -> Result<(), ParseError> {
+        for parsed_component in self.entity_parser.parse(value, self)? {
+            match parsed_component {
+                ParsedEntityItem::Tag(id_or_name) => {
+                    let name = id_or_name.clone();
+                    let (tag, tag_type) = self.tag_by_id_or_name(id_or_name);
+                    if self.is_sparse(tag) {
+                        self.add_component_tag(entity, tag).unwrap();
+                    } else {
+                        self.add_entity_tag_inner(entity, tag, tag_type.is_type())
+                            .unwrap();
+                    }
+                }
+                ParsedEntityItem::RelationshipTag(relation, target) => {
+                    let (relation, _) = self.tag_by_id_or_name(relation);
+                    let (target, _) = self.tag_by_id_or_name(target);
+                    self.add_relationship(entity, relation, target, TableReusage::New)
+                        .unwrap();
+                }
+                ParsedEntityItem::Component(id, deserialize_fn, value, comp_type) => {
+                    self.deserialize_component(entity, id, deserialize_fn, value, comp_type)?;
+                }
+                ParsedEntityItem::Name(name) => {
+                    self.set_entity_name(NameLeft::global(entity), name)?;
+                }
+                ParsedEntityItem::EnumTag(enum_type, value) => {
+                    self.add_enum_tag_by_id(entity, enum_type, value).unwrap();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn deserialize_component(
+        &mut self,
+        entity: Identifier,
+        id: Identifier,
+        deserialize_fn: DeserializeFn,
+        value: serde_json::Value,
+        comp_type: entity_parser::ComponentType,
+    ) -> Result<(), ParseError> {
+        if self.is_sparse(id) {
+            let functions = self.type_registry().functions[&id.stripped()].clone();
+            return self
+                .add_component_with(entity, id, &functions, |storage| {
+                    Ok(deserialize_fn(value, storage)?)
+                })
+                .map_err(|err| err.downcast::<serde_json::Error>().unwrap().into());
+        }
+        let (archetype, _) = match comp_type {
+            entity_parser::ComponentType::Regular => {
+                self.add_component(id, entity, TableReusage::New).unwrap()
+            }
+            entity_parser::ComponentType::DataRelationship(data_pos) => self
+                .add_data_relationship(
+                    entity,
+                    self.relation_entity(id).unwrap(),
+                    self.target_entity(id).unwrap(),
+                    data_pos,
+                )
+                .unwrap(),
+        };
+        let storage = archetype
+            .borrow()
+            .table()
+            .borrow()
+            .storage(id)
+            .unwrap()
+            .clone();
+        let storage = storage.borrow_mut();
+        deserialize_fn(value, storage)?;
+        Ok(())
+    }
+
+    pub fn clone_entity(&mut self, entity: Identifier) -> Option<Identifier> {
+        let cloned_entity = self.add_entity(EntityKind::Regular);
+        let old_record = self.record(entity)?;
+        let components = self
+            .archetype_by_id(old_record.arhetype_id)
+            .borrow()
+            .components_ids_set_rc()
+            .clone();
+
+        //the clone already lives in the entity archetype
+        for component in components.iter().copied().filter(|&c| c != ENTITY_ID) {
+            self.clone_component(component, entity, cloned_entity)?;
             //TODO: should add callbacks fire when cloning entities?
             // self.callbacks
             //     .borrow_mut()
             //     .run_add_callback(component, cloned_entity);
         }
+        let sparse: Vec<_> = self
+            .sparse_sets
+            .values()
+            .filter(|set| set.contains(entity.low32() as usize))
+            .map(|set| set.component())
+            .collect();
+        for component in sparse {
+            self.clone_sparse_component(component, entity, cloned_entity)?;
+        }
         Some(cloned_entity)
     }
 
+    fn clone_sparse_component(
+        &mut self,
+        component: Identifier,
+        from: Identifier,
+        to: Identifier,
+    ) -> Option<()> {
+        let Some(functions) = self
+            .type_registry()
+            .functions
+            .get(&component.stripped())
+            .cloned()
+        else {
+            return self.add_component_tag(to, component).ok();
+        };
+        let sparse_set = self.sparse_set(component)?;
+        let row = sparse_set.row(from.low32() as usize)?;
+        let table = sparse_set.table().clone();
+        let storage = table.borrow().storage(component)?.clone();
+        //the value is copied out before the sparse set is touched
+        let component_ptr = unsafe { storage.borrow().0.get_checked(row.0).as_ptr() };
+        self.add_component_with(to, component, &functions, |storage| {
+            (functions.clone)(
+                unsafe { Ptr::new(NonNull::new(component_ptr).unwrap()) },
+                storage,
+            );
+            Ok(())
+        })
+        .ok()
+    }
+
+    //`to` must not have the component yet
+    fn clone_component(
+        &mut self,
+        component: Identifier,
+        from: Identifier,
+        to: Identifier,
+    ) -> Option<()> {
+        let table_reusage = if self.is_component_empty(component) {
+            TableReusage::Reuse
+        } else {
+            TableReusage::New
+        };
+        let (cloned_archetype, _) = self.add_component(component, to, table_reusage).ok()?;
+
+        if matches!(table_reusage, TableReusage::Reuse) {
+            return Some(());
+        }
+
+        //moving `to` might have moved `from` within a shared table
+        let old_record = self.record(from)?;
+        let old_archetype = self.archetype_by_id(old_record.arhetype_id).clone();
+        let old_archetype_ref = old_archetype.borrow();
+        let cloned_archetype_ref = cloned_archetype.borrow();
+        let clone_into = self
+            .type_registry()
+            .functions
+            .get(&component.stripped())
+            .unwrap()
+            .clone;
+        let old_storage = old_archetype_ref
+            .table()
+            .borrow()
+            .storage(component)
+            .unwrap()
+            .clone();
+        let old_storage_mut = old_storage.borrow_mut();
+        let component_ptr: *mut u8 = unsafe {
+            old_storage_mut
+                .0
+                .get_checked(old_record.table_row.0)
+                .as_ptr()
+        };
+        let cloned_storage = cloned_archetype_ref
+            .table()
+            .borrow()
+            .storage(component)
+            .unwrap()
+            .clone();
+        if Rc::ptr_eq(&cloned_storage, &old_storage) {
+            clone_into(
+                unsafe { Ptr::new(NonNull::new(component_ptr).unwrap()) },
+                old_storage_mut,
+            );
+        } else {
+            let cloned_storage_mut = cloned_storage.borrow_mut();
+            clone_into(
+                unsafe { Ptr::new(NonNull::new(component_ptr).unwrap()) },
+                cloned_storage_mut,
+            );
+        }
+        Some(())
+    }
+
+    //copies the prefab's components onto the instance again, except the ones marked with
+    //(Overridden, component)
+    pub fn resync_instance(&mut self, instance: Identifier, prefab: Identifier) -> Result<()> {
+        if self.locked {
+            bail!("instances can't be resynced while archetypes are locked")
+        }
+        let Some(record) = self.record(prefab) else {
+            bail!("prefab {prefab:?} is not alive");
+        };
+        let components = self
+            .archetype_by_id(record.arhetype_id)
+            .borrow()
+            .components_ids_set_rc()
+            .clone();
+        let overridden = self.component_id::<Overridden>();
+        let skipped = [
+            self.component_id::<InstanceOf>().stripped(),
+            self.component_id::<ChildOf>().stripped(),
+            overridden.stripped(),
+        ];
+        let prefab_tag = self.component_id::<Prefab>().stripped();
+
+        for component in components.iter().copied() {
+            let relation = self.relation_entity(component).map(|r| r.stripped());
+            if component.stripped() == prefab_tag || relation.is_some_and(|r| skipped.contains(&r))
+            {
+                continue;
+            }
+            let mark = Archetypes::relationship_id(overridden, component);
+            if self.has_component(mark, instance) {
+                continue;
+            }
+            if self.has_component(component, instance) {
+                if self.is_component_empty(component) {
+                    continue;
+                }
+                self.remove_component(component, instance, TableReusage::New)?;
+            }
+            if self.clone_component(component, prefab, instance).is_none() {
+                bail!("failed to copy component {component:?} onto {instance:?}");
+            }
+        }
+        Ok(())
+    }
+
+    //copies what a nested instance changed on top of its prefab onto a fresh instance of the
+    //same prefab: components the prefab doesn't have and values marked as overridden
+    pub fn copy_instance_overrides(&mut self, from: Identifier, to: Identifier) -> Result<()> {
+        let Some(record) = self.record(from) else {
+            bail!("instance {from:?} is not alive");
+        };
+        let components = self
+            .archetype_by_id(record.arhetype_id)
+            .borrow()
+            .components_ids_set_rc()
+            .clone();
+        let overridden = self.component_id::<Overridden>();
+        let skipped = [
+            self.component_id::<InstanceOf>().stripped(),
+            self.component_id::<ChildOf>().stripped(),
+        ];
+
+        for component in components.iter().copied() {
+            let relation = self.relation_entity(component).map(|r| r.stripped());
+            if relation.is_some_and(|r| skipped.contains(&r)) {
+                continue;
+            }
+            let mark = Archetypes::relationship_id(overridden, component);
+            if self.has_component(component, to) {
+                if !self.has_component(mark, from) || self.is_component_empty(component) {
+                    continue;
+                }
+                self.remove_component(component, to, TableReusage::New)?;
+            }
+            if self.clone_component(component, from, to).is_none() {
+                bail!("failed to copy component {component:?} onto {to:?}");
+            }
+        }
+        Ok(())
+    }
+
     pub fn resource_exists<T: 'static>(&self) -> bool {
         self.resources.borrow().contains_key(&TypeId::of::<T>())
     }
@@ -1071,49 +2131,175 @@ impl Archetypes {
         &self.names
     }
 
-    pub fn set_entity_name(&mut self, left: NameLeft, name: SmolStr) {
+    pub fn names_mut(&mut self) -> &mut NamesMap {
+        &mut self.names
+    }
+
+    pub fn set_entity_name(
+        &mut self,
+        left: NameLeft,
+        name: SmolStr,
+    ) -> std::result::Result<(), NameError> {
+        //naming an already named entity renames it, freeing the old name
+        if self.names.contains_left(&left) {
+            return self
+                .change_entity_name(left, name.clone())
+                .map_err(|_| NameError::Duplicate(name, left.parent_index));
+        }
         let unique_name = UniqueName::new(left.parent_index, name.clone());
         if self.unique_names.contains(&unique_name) {
-            panic!("attempt to add an existing name '{}'", name,);
+            return Err(NameError::Duplicate(name, left.parent_index));
         }
         self.unique_names.insert(unique_name);
-        self.names.insert(left, left.to_name_and_parent(name));
+        let old_name = self.names.get_by_left(&left).map(|r| r.name.clone());
+        self.names
+            .insert(left, left.to_name_and_parent(name.clone()));
+        self.queue_name_callbacks(left, old_name.as_deref(), &name);
+        Ok(())
     }
 
-    pub fn change_entity_name(&mut self, left: NameLeft, name: SmolStr) {
+    fn queue_name_callbacks(&self, left: NameLeft, old_name: Option<&str>, new_name: &str) {
+        let Some(entity) = self.id_by_record_index(left.entity_index) else {
+            return;
+        };
+        self.callbacks
+            .borrow_mut()
+            .queue_name_callbacks(entity, old_name, new_name);
+    }
+
+    pub fn change_entity_name(
+        &mut self,
+        left: NameLeft,
+        name: SmolStr,
+    ) -> std::result::Result<(), NameExistsError> {
         let old_name = self.names.get_by_left(&left).map(|r| r.name.clone());
-        if let Some(old_name) = old_name {
-            let old_unique_name = UniqueName::new(left.parent_index, old_name.clone());
-            let new_unique_name = UniqueName::new(left.parent_index, old_name.clone());
-            self.unique_names.remove(&old_unique_name);
-            self.unique_names.insert(new_unique_name);
+        if old_name.as_ref() == Some(&name) {
+            return Ok(());
+        }
+        let new_unique_name = UniqueName::new(left.parent_index, name.clone());
+        if self.unique_names.contains(&new_unique_name) {
+            return Err(NameExistsError(name));
         }
-        self.names.insert(left, left.to_name_and_parent(name));
+        if let Some(old_name) = &old_name {
+            self.unique_names
+                .remove(&UniqueName::new(left.parent_index, old_name.clone()));
+        }
+        self.unique_names.insert(new_unique_name);
+        self.names
+            .insert(left, left.to_name_and_parent(name.clone()));
+        self.queue_name_callbacks(left, old_name.as_deref(), &name);
+        Ok(())
     }
 
     pub fn remove_entity_name(&mut self, left: NameLeft) {
         let name = self.names.get_by_left(&left).map(|r| r.name.clone());
-        if let Some(name) = name {
+        if let Some(name) = &name {
             let unique_name = UniqueName::new(left.parent_index, name.clone());
             self.unique_names.remove(&unique_name);
         }
         self.names.remove_by_left(&left);
+        if let Some(name) = name {
+            self.queue_name_callbacks(left, Some(&name), "");
+        }
     }
 
-    pub fn set_entity_name_parent(&mut self, left: NameLeft, parent: Identifier) {
-        let name = self.names.get_by_left(&left).map(|r| r.name.clone());
-        if let Some(name) = name {
-            let old_unique_name = UniqueName::new(left.parent_index, name.clone());
-            let new_unique_name = UniqueName::new(parent.low32() as usize, name.clone());
-            self.unique_names.remove(&old_unique_name);
-            self.unique_names.insert(new_unique_name);
-        }
-        if self.name_by_entity(&left).is_some() {
-            let (_, old_right) = self.names.remove_by_left(&left).unwrap();
-            let entity = NameLeft::new(left.entity_index, parent.low32() as usize);
-            self.names
-                .insert(entity, entity.to_name_and_parent(old_right.name));
+    pub fn set_entity_name_parent(
+        &mut self,
+        left: NameLeft,
+        parent: Identifier,
+    ) -> std::result::Result<(), NameExistsError> {
+        let Some(name) = self.names.get_by_left(&left).map(|r| r.name.clone()) else {
+            return Ok(());
+        };
+        let parent_index = parent.low32() as usize;
+        if parent_index == left.parent_index {
+            return Ok(());
+        }
+        let new_unique_name = UniqueName::new(parent_index, name.clone());
+        if self.unique_names.contains(&new_unique_name) {
+            return Err(NameExistsError(name));
+        }
+        self.unique_names
+            .remove(&UniqueName::new(left.parent_index, name.clone()));
+        self.unique_names.insert(new_unique_name);
+        self.names.remove_by_left(&left);
+        let entity = NameLeft::new(left.entity_index, parent_index);
+        self.names.insert(entity, entity.to_name_and_parent(name));
+        Ok(())
+    }
+
+    //returns `base` or the first `base_N` that isn't taken under the parent
+    pub fn free_name(&self, parent: Identifier, base: &str) -> SmolStr {
+        let parent_index = parent.low32() as usize;
+        let is_taken = |name: &SmolStr| {
+            self.unique_names
+                .contains(&UniqueName::new(parent_index, name.clone()))
+        };
+        let mut name = base.to_smolstr();
+        let mut suffix = 1;
+        while is_taken(&name) {
+            name = format_smolstr!("{base}_{suffix}");
+            suffix += 1;
+        }
+        name
+    }
+
+    //both searches scan every name, which is fine for editor tooling but not for hot paths
+    pub fn find_entities_by_name_prefix(&self, prefix: &str) -> Vec<Identifier> {
+        self.find_entities_by_name(|name| name.starts_with(prefix))
+    }
+
+    pub fn find_entities_by_name_ci(&self, needle: &str) -> Vec<Identifier> {
+        let needle = needle.to_lowercase();
+        self.find_entities_by_name(|name| name.to_lowercase().contains(&needle))
+    }
+
+    fn find_entities_by_name(&self, matches: impl Fn(&str) -> bool) -> Vec<Identifier> {
+        let mut entities: Vec<_> = self
+            .names
+            .iter()
+            .filter(|(_, right)| matches(&right.name))
+            .filter_map(|(left, _)| self.record_by_index(left.entity_index).map(|r| r.entity))
+            .collect();
+        entities.sort_by_key(|e| e.low32());
+        entities
+    }
+
+    pub fn named_children(&self, parent: Identifier) -> Vec<(SmolStr, Identifier)> {
+        let parent_index = parent.low32() as usize;
+        self.names
+            .iter()
+            .filter(|(left, _)| left.parent_index == parent_index)
+            .filter_map(|(left, right)| {
+                let entity = self.record_by_index(left.entity_index).map(|r| r.entity)?;
+                Some((right.name.clone(), entity))
+            })
+            .collect()
+    }
+
+    //the bimap only keeps names unique by `NameRight`, so entries whose parents disagree can still
+    //give several entities the same name under one parent
+    pub fn name_conflicts(&self) -> Vec<(u32, SmolStr, Vec<Identifier>)> {
+        let mut groups: HashMap<(usize, SmolStr), Vec<Identifier>> = HashMap::new();
+        for (left, right) in self.names.iter() {
+            let Some(entity) = self.id_by_record_index(left.entity_index) else {
+                continue;
+            };
+            groups
+                .entry((left.parent_index, right.name.clone()))
+                .or_default()
+                .push(entity);
         }
+        let mut conflicts: Vec<_> = groups
+            .into_iter()
+            .filter(|(_, entities)| entities.len() > 1)
+            .map(|((parent_index, name), mut entities)| {
+                entities.sort_by_key(|e| e.low32());
+                (parent_index as u32, name, entities)
+            })
+            .collect();
+        conflicts.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        conflicts
     }
 
     pub fn entity_has_name(&self, entity: &NameLeft) -> bool {
@@ -1139,6 +2325,19 @@ impl Archetypes {
             .and_then(|n| self.record_by_index(n.entity_index).map(|r| r.entity))
     }
 
+    //"root/child/grandchild", each segment is looked up in the scope of the previous one
+    pub fn entity_by_path(&self, path: &str) -> Option<Identifier> {
+        let mut parent = WILDCARD.0;
+        for segment in path.split('/') {
+            if segment.is_empty() {
+                return None;
+            }
+            let name = NameRight::new(segment.to_smolstr(), parent.low32() as usize);
+            parent = self.entity_by_name(&name)?;
+        }
+        Some(parent)
+    }
+
     pub fn is_component_empty(&self, component: Identifier) -> bool {
         !self
             .type_registry()
@@ -1156,6 +2355,7 @@ impl Archetypes {
 
     pub fn record_by_index(&self, index: usize) -> Ref<Option<EntityRecord>> {
         let records = self.records.borrow();
+        debug_assert!(index < records.len(), "record index out of bounds");
         Ref::map(records, |r| &r[index])
     }
 
@@ -1168,8 +2368,47 @@ impl Archetypes {
         records[low32 as usize]
     }
 
+    pub fn entity_table_row(&self, entity: Identifier) -> Option<TableRow> {
+        self.record(entity).map(|r| r.table_row)
+    }
+
+    //checks the moved entity and the one that was swapped into its old row
+    fn debug_check_table_rows(&self, entity: Identifier, old_table: &TableCell, old_row: TableRow) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        self.debug_check_table_row(entity.low32() as usize);
+        let swapped = old_table.borrow().entity_indices().get(old_row.0).copied();
+        if let Some(index) = swapped {
+            self.debug_check_table_row(index);
+        }
+    }
+
+    fn debug_check_table_row(&self, index: usize) {
+        let Some(record) = *self.record_by_index(index) else {
+            return;
+        };
+        let archetype = self.archetype_by_id(record.arhetype_id).borrow();
+        let table = archetype.table().borrow();
+        //rows of tables without storages are never read
+        if table.storages().is_empty() {
+            return;
+        }
+        debug_assert!(
+            record.table_row.0 < table.len(),
+            "table row {} of entity {index} is out of bounds (len {})",
+            record.table_row.0,
+            table.len()
+        );
+        debug_assert_eq!(
+            table.entity_indices()[record.table_row.0],
+            index,
+            "table row of entity {index} points to another entity"
+        );
+    }
+
     pub fn archetype_from_record(&self, record: &EntityRecord) -> Option<&ArchetypeCell> {
-        self.archetypes.get(record.arhetype_id.0)
+        self.archetypes.get(record.arhetype_id.0)?.as_ref()
     }
 
     pub fn entity_id(&mut self) -> Identifier {
@@ -1209,6 +2448,7 @@ impl Archetypes {
         let mut tables: Vec<_> = self
             .archetypes
             .iter()
+            .flatten()
             .map(|a| a.borrow().table().clone())
             .collect();
         tables.sort_by_key(|a| a.borrow().id());
@@ -1218,34 +2458,196 @@ impl Archetypes {
         }
     }
 
-    pub fn relationship_name(&self, _relationship: Identifier) -> Option<String> {
-        todo!()
-        // let relation = self.relation_entity(relationship)?;
-        // let target = self.target_entity(relationship)?;
-        // let registry = self.type_registry();
-        // let relation_name = registry
-        //     .type_names
-        //     .get(&relation.low32())
-        //     .map(|n| n.as_str())
-        //     .unwrap_or("Relation");
-        // let target_name = registry
-        //     .type_names
-        //     .get(&target.low32())
-        //     .map(|n| n.as_str())
-        //     .unwrap_or("Target");
-        // format!("({relation_name}, {target_name})").into()
+    pub fn relationship_name(&self, relationship: Identifier) -> Option<String> {
+        let relation = self.relation_entity(relationship)?;
+        let target = self.target_entity(relationship)?;
+        Some(format!(
+            "({0}, {1})",
+            self.debug_id_name(relation),
+            self.debug_id_name(target)
+        ))
+    }
+
+    pub fn reserve_storage(&mut self, component: Identifier, count: usize) {
+        {
+            let mut registry = self.type_registry.borrow_mut();
+            let hint = registry
+                .capacity_hints
+                .entry(component.stripped())
+                .or_default();
+            *hint = usize::max(*hint, count);
+        }
+        for table in self.tables() {
+            if let Some(storage) = table.borrow().storage(component) {
+                let mut storage = storage.borrow_mut();
+                let additional = count.saturating_sub(storage.len());
+                storage.0.reserve(additional);
+            }
+        }
+    }
+
+    /// Merges tables that ended up with the same components, returns the amount of removed tables.
+    pub fn defragment_tables(&mut self) -> Result<usize> {
+        if self.locked {
+            bail!("tables can't be merged while archetypes are locked")
+        }
+        let mut tables_by_components: HashMap<BTreeSet<Identifier>, Vec<TableCell>> =
+            HashMap::new();
+        for table in self.tables() {
+            let components = table.borrow().component_ids().clone();
+            if components.is_empty() {
+                continue;
+            }
+            tables_by_components
+                .entry(components)
+                .or_default()
+                .push(table.clone());
+        }
+
+        let mut removed = 0;
+        for tables in tables_by_components.into_values() {
+            let Some((survivor, duplicates)) = tables.split_first() else {
+                continue;
+            };
+            for duplicate in duplicates {
+                let moved = duplicate
+                    .borrow_mut()
+                    .drain_into(&mut survivor.borrow_mut());
+                for (index, table_row) in moved {
+                    self.modify_record_by_index(index, |r| {
+                        if let Some(r) = r {
+                            r.table_row = table_row;
+                        }
+                    });
+                }
+                let duplicate_id = duplicate.borrow().id();
+                for archetype in self.archetypes.iter().flatten() {
+                    let mut archetype = archetype.borrow_mut();
+                    if archetype.table().borrow().id() == duplicate_id {
+                        archetype.set_table(survivor.0.clone());
+                    }
+                }
+                for tables in self.tables_by_hashes.values_mut() {
+                    tables.retain(|t| t.borrow().id() != duplicate_id);
+                }
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &TableCell> {
+        self.tables_by_hashes.values().flatten()
+    }
+
+    //gives back the memory left over after mass despawns
+    pub fn compact(&mut self) -> Result<()> {
+        if self.locked {
+            bail!("archetypes can't be compacted while they are locked")
+        }
+        let sparse_tables = self.sparse_sets.values().map(|s| s.table());
+        for table in self.tables().chain(sparse_tables) {
+            table.borrow_mut().shrink_to_fit();
+        }
+        for archetype in self.archetypes.iter().flatten() {
+            archetype.borrow_mut().shrink_to_fit();
+        }
+        Ok(())
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.records.borrow().iter().flatten().count()
+    }
+
+    pub fn alive_entity_count(&self) -> usize {
+        self.records
+            .borrow()
+            .iter()
+            .flatten()
+            .filter(|record| self.is_entity_alive(record.entity))
+            .count()
+    }
+
+    pub fn archetype_count(&self) -> usize {
+        self.archetypes.iter().flatten().count()
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.tables().count()
+    }
+
+    pub fn stats(&self) -> WorldStats {
+        let mut total_component_bytes = 0;
+        for table in self.tables() {
+            for storage in table.borrow().storages() {
+                let storage = storage.borrow();
+                total_component_bytes += storage.capacity() * storage.0.layout().size();
+            }
+        }
+        WorldStats {
+            entity_count: self.entity_count(),
+            archetype_count: self.archetype_count(),
+            table_count: self.table_count(),
+            total_component_bytes,
+        }
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        let registry = self.type_registry.borrow();
+        let mut usage: HashMap<StrippedIdentifier, (usize, usize)> = HashMap::new();
+        let mut total_bytes = 0;
+        let mut table_count = 0;
+        let sparse_tables = self.sparse_sets.values().map(|s| s.table());
+        for table in self.tables().chain(sparse_tables) {
+            let table = table.borrow();
+            total_bytes += table.memory_bytes();
+            table_count += 1;
+            for id in table.component_ids().iter() {
+                let Some(storage) = table.storage(*id) else {
+                    continue;
+                };
+                let storage = storage.borrow();
+                let (bytes, count) = usage.entry(id.stripped()).or_default();
+                *bytes += storage.len() * storage.0.layout().size();
+                *count += storage.len();
+            }
+        }
+        let mut components: Vec<_> = usage
+            .into_iter()
+            .map(|(id, (bytes, count))| {
+                let name = registry
+                    .type_ids_data
+                    .get(&id)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_else(|| format!("{id:?}").into());
+                (name, bytes, count)
+            })
+            .collect();
+        components.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        MemoryReport {
+            total_bytes,
+            table_count,
+            components,
+        }
     }
 
     pub fn debug_print_archetypes(&self) {
-        println!("Amount: {}", self.archetypes.len());
-        for archetype in self.archetypes.iter() {
+        println!("Amount: {}", self.archetypes.iter().flatten().count());
+        for archetype in self.archetypes.iter().flatten() {
             archetype.borrow().debug_print(self);
         }
     }
     pub fn is_entity_alive(&self, entity: Identifier) -> bool {
         let id_unpacked = entity.unpack();
         if id_unpacked.high32.is_relationship {
-            return true;
+            //relationship ids keep only the indices of both sides, so a side counts as alive
+            //while its slot is in use
+            let records = self.records.borrow();
+            let side_alive = |index: u32, wildcard: u32| {
+                index == wildcard || records.get(index as usize).is_some_and(Option::is_some)
+            };
+            return side_alive(entity.low32(), WILDCARD_32)
+                && side_alive(entity.second(), WILDCARD_25);
         }
         let Some(record) = self.record(entity) else {
             return false;
@@ -1254,7 +2656,47 @@ impl Archetypes {
     }
 
     pub fn archetype_by_id(&self, id: ArchetypeId) -> &ArchetypeCell {
-        &self.archetypes[id.0]
+        self.archetypes[id.0]
+            .as_ref()
+            .expect("archetype should not be removed")
+    }
+
+    pub fn archetype_exists(&self, id: ArchetypeId) -> bool {
+        self.archetypes.get(id.0).is_some_and(Option::is_some)
+    }
+
+    pub fn remove_archetype(&mut self, id: ArchetypeId) -> Result<()> {
+        if id == self.entity_archetype().borrow().id() {
+            bail!("the entity archetype can't be removed")
+        }
+        let Some(archetype) = self.archetypes.get(id.0).cloned().flatten() else {
+            bail!("expected archetype to exist")
+        };
+        if !archetype.is_empty() {
+            bail!("expected archetype to be empty")
+        }
+        self.archetypes[id.0] = None;
+        for archetypes in self.archetypes_by_hashes.values_mut() {
+            archetypes.retain(|a| a.borrow().id() != id);
+        }
+        for archetypes in self.archetypes_by_ids.values_mut() {
+            archetypes.remove(&archetype);
+        }
+        for storage in self.query_storages.values() {
+            storage
+                .borrow_mut()
+                .archetypes
+                .retain(|a| a.borrow().id() != id);
+        }
+        //edges pointing to the removed archetype would otherwise be followed later
+        for other in self.archetypes.iter().flatten() {
+            other.borrow_mut().clear_edges_to(id);
+        }
+        self.match_cache
+            .borrow_mut()
+            .results
+            .retain(|(archetype, _), _| *archetype != id);
+        Ok(())
     }
 
     pub fn add_data_relationship(
@@ -1301,8 +2743,12 @@ impl Archetypes {
                     clone: T::clone_into,
                     serialize: T::serialize,
                     deserialize: T::deserialize,
+                    serialize_bin: T::serialize_bin,
+                    deserialize_bin: T::deserialize_bin,
                     as_reflect_ref: T::as_reflect_ref,
                     as_reflect_mut: T::as_reflect_mut,
+                    drop: std::mem::needs_drop::<T>().then_some(drop_ptr::<T> as DropFn),
+                    default: reflect_default_fn::<T>(),
                 },
             );
             type_registry
@@ -1344,6 +2790,14 @@ impl Archetypes {
         Ok(())
     }
 
+    pub fn cached_query(&self, type_id: TypeId) -> Option<Rc<dyn Any>> {
+        self.cached_queries.get(&type_id).cloned()
+    }
+
+    pub fn cache_query(&mut self, type_id: TypeId, query: Rc<dyn Any>) {
+        self.cached_queries.insert(type_id, query);
+    }
+
     pub fn query_storage(
         &mut self,
         ids: &RequiredIds,
@@ -1363,12 +2817,23 @@ impl Archetypes {
         ids: &RequiredIds,
         mask: &FilterMask,
     ) -> Rc<RefCell<QueryStorage>> {
-        let archetypes = match ids.values.first().copied() {
+        let sparse: Vec<_> = ids
+            .values
+            .iter()
+            .filter(|n| !n.is_optional() && self.is_sparse(n.value))
+            .map(|n| n.value)
+            .collect();
+        let first = ids
+            .values
+            .iter()
+            .find(|n| !n.is_optional() && !self.is_sparse(n.value))
+            .copied();
+        let archetypes = match first {
             Some(f) => {
                 let required_components: BTreeSet<_> = ids
                     .values
                     .iter()
-                    .filter(|n| !n.is_optional())
+                    .filter(|n| !n.is_optional() && !self.is_sparse(n.value))
                     .map(|n| n.value)
                     .collect();
                 let mut archetypes: Vec<_> = self
@@ -1394,24 +2859,30 @@ impl Archetypes {
                     })
                     .cloned()
                     .collect();
-                archetypes.retain(|a| mask.matches_archetype(self, a));
+                archetypes.retain(|a| self.matches_archetype(mask, a));
                 archetypes
             }
             None => {
                 //that's quite expensive, but should not happen that often
-                let mut archetypes: Vec<_> = self.archetypes.to_vec();
-                archetypes.retain(|a| mask.matches_archetype(self, a));
+                let mut archetypes: Vec<_> = self.archetypes.iter().flatten().cloned().collect();
+                archetypes.retain(|a| self.matches_archetype(mask, a));
                 archetypes
             }
         };
         let mut storage_mask = mask.clone();
-        for id in ids.values.iter() {
+        for id in ids
+            .values
+            .iter()
+            .filter(|id| !id.is_optional() && !self.is_sparse(id.value))
+        {
             storage_mask.has.push(id.value);
         }
         Rc::new(
             QueryStorage {
                 archetypes,
+                mask_id: self.match_cache.borrow_mut().mask_id(&storage_mask),
                 mask: storage_mask,
+                sparse,
             }
             .into(),
         )
@@ -1421,16 +2892,112 @@ impl Archetypes {
         let Some(record) = self.record(entity) else {
             return false;
         };
+        if let Some(sparse_set) = self.sparse_sets.get(&component.stripped()) {
+            return sparse_set.contains(entity.low32() as usize);
+        }
 
         let Some(archetypes) = self.archetypes_by_ids.get(&component.stripped()) else {
             return false;
         };
-        //TODO: only works if archetypes don't ever get replaced/removed
-        //solution: add archetypes_by_ids
         let archetype = self.archetype_by_id(record.arhetype_id);
         archetypes.contains::<ArchetypeCell>(archetype)
     }
 
+    //moves the entity back to the entity archetype, returning the removed ids so the caller
+    //can run remove callbacks. The COMPONENT_ID marker stays, children are left untouched
+    pub fn clear_components(&mut self, entity: Identifier) -> Result<Vec<Identifier>> {
+        let Some(record) = self.record(entity) else {
+            bail!("expected initialized record")
+        };
+        if !self.is_entity_alive(entity) {
+            bail!("expected entity to be alive")
+        }
+        let parent = self.name_parent(&record);
+        self.remove_entity_name((entity, parent).into());
+
+        let removed = self.clearable_components(entity);
+        for id in removed.iter().copied() {
+            let table_reusage = if self.is_component_empty(id) {
+                TableReusage::Reuse
+            } else {
+                TableReusage::New
+            };
+            self.remove_component(id, entity, table_reusage)?;
+        }
+        Ok(removed)
+    }
+
+    //everything clear_components would remove, sparse components included
+    pub fn clearable_components(&self, entity: Identifier) -> Vec<Identifier> {
+        let mut components: Vec<_> = self
+            .component_ids_of(entity)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|id| id.stripped() != COMPONENT_ID.stripped())
+            .collect();
+        components.extend(
+            self.sparse_sets
+                .iter()
+                .filter(|(_, set)| set.contains(entity.low32() as usize))
+                .map(|(id, _)| id.0),
+        );
+        components
+    }
+
+    //queues a copy of the value that is about to be dropped for the remove callbacks of its component
+    fn queue_remove_value_callbacks(&self, component: Identifier, entity: Identifier) {
+        if !self
+            .callbacks
+            .borrow()
+            .has_remove_value_callbacks(component)
+        {
+            return;
+        }
+        let (table, component, row) = match self.sparse_sets.get(&component.stripped()) {
+            Some(sparse_set) => {
+                let Some(row) = sparse_set.row(entity.low32() as usize) else {
+                    return;
+                };
+                (sparse_set.table().0.clone(), sparse_set.component(), row)
+            }
+            None => {
+                let Some(record) = self.record(entity) else {
+                    return;
+                };
+                let Some(archetype) = self.archetype_from_record(&record) else {
+                    return;
+                };
+                (
+                    archetype.borrow().table().clone(),
+                    component,
+                    record.table_row,
+                )
+            }
+        };
+        let table = table.borrow();
+        //tags have no values
+        let Some(storage) = table.storage(component) else {
+            return;
+        };
+        let storage = storage.borrow();
+        let Some(value) = storage.try_component(row) else {
+            return;
+        };
+        let type_registry = self.type_registry();
+        let functions = &type_registry.functions[&component.stripped()];
+        let layout = type_registry.layouts[&component.stripped()];
+        //SAFETY: the drop function is registered along with the component's layout
+        let copy = RefCell::new(Storage::from(unsafe {
+            BlobVec::new(layout, functions.drop, 1)
+        }));
+        (functions.clone)(value, copy.borrow_mut());
+        self.callbacks.borrow_mut().queue_remove_value_callbacks(
+            component,
+            entity,
+            copy.into_inner(),
+        );
+    }
+
     pub fn remove_component(
         &mut self,
         component: Identifier,
@@ -1447,11 +3014,17 @@ impl Archetypes {
         if !self.has_component(component, entity) {
             return Ok(());
         }
-
+        //deferred removals run the callbacks once they are applied
         if self.locked {
             self.add_operation(entity, OperationType::RemoveComponent(component));
             return Ok(());
         }
+        self.queue_remove_value_callbacks(component, entity);
+
+        if let Some(sparse_set) = self.sparse_sets.get_mut(&component.stripped()) {
+            sparse_set.remove(entity.low32() as usize);
+            return Ok(());
+        }
 
         let old_archetype = self.archetype_by_id(record.arhetype_id).clone();
         if old_archetype.borrow().components_ids().len() == 1 {
@@ -1478,6 +3051,9 @@ impl Archetypes {
                 arhetype_id: new_id,
                 entity,
             });
+            drop(new);
+            let old_table = old_archetype.borrow().table().clone().into();
+            self.debug_check_table_rows(entity, &old_table, record.table_row);
             return Ok(());
         }
         let (old_id, old_table, mut old_edge_cloned) = {
@@ -1485,8 +3061,14 @@ impl Archetypes {
             (old.id(), old.table().clone(), old.edge_cloned(component))
         };
         let reuse_table = matches!(table_reusage, TableReusage::Reuse);
-        let new_archetype = match old_edge_cloned.add {
-            Some(id) => self.archetype_by_id(id).clone(),
+        let new_archetype = match old_edge_cloned.remove {
+            Some(id) => {
+                debug_assert!(
+                    self.archetype_exists(id),
+                    "edge points to a removed archetype"
+                );
+                self.archetype_by_id(id).clone()
+            }
             None => {
                 let mut new_components = old_archetype.borrow().components_ids_set().clone();
                 new_components.remove(&component);
@@ -1507,7 +3089,12 @@ impl Archetypes {
             }
         };
         old_archetype.borrow_mut().edge_mut(component).remove = old_edge_cloned.remove;
-        let (new_achetype_row, new_table_row) = if !reuse_table {
+        //an existing archetype can have a different table, so entity has to be moved into it
+        let shares_table = Rc::ptr_eq(
+            old_archetype.borrow().table(),
+            new_archetype.borrow().table(),
+        );
+        let (new_achetype_row, new_table_row) = if !reuse_table || !shares_table {
             let old = old_archetype.borrow_mut();
             let new = new_archetype.borrow_mut();
             Table::move_entity(
@@ -1533,6 +3120,8 @@ impl Archetypes {
             arhetype_id: new_archetype.borrow().id(),
             entity,
         });
+        let old_table = old_archetype.borrow().table().clone().into();
+        self.debug_check_table_rows(entity, &old_table, record.table_row);
         Ok(())
     }
 
@@ -1554,9 +3143,15 @@ impl Archetypes {
             arhetype_id: entity_archetype_id,
             entity: id,
         };
-        self.records
-            .borrow_mut()
-            .insert(id.low32() as usize, Some(record));
+        {
+            let mut records = self.records.borrow_mut();
+            let index = id.low32() as usize;
+            if index >= records.len() {
+                let new_len = usize::max(records.len() * 2, index + 1);
+                records.resize(new_len, None);
+            }
+            records[index] = Some(record);
+        }
 
         if let EntityKind::Component(component) = kind {
             self.add_component_typed(COMPONENT_ID, id, component)
@@ -1606,14 +3201,62 @@ impl Archetypes {
                     clone: T::clone_into,
                     serialize: T::serialize,
                     deserialize: T::deserialize,
+                    serialize_bin: T::serialize_bin,
+                    deserialize_bin: T::deserialize_bin,
                     as_reflect_ref: T::as_reflect_ref,
                     as_reflect_mut: T::as_reflect_mut,
+                    drop: std::mem::needs_drop::<T>().then_some(drop_ptr::<T> as DropFn),
+                    default: reflect_default_fn::<T>(),
                 },
             );
         }
         if std::mem::size_of::<T>() == 0 {
             type_registry.tags.insert(id.into());
         }
+        //enum tags can be parsed and serialized before any of them is added
+        if let Some((variant_name, variant_id)) = T::enum_variants() {
+            type_registry
+                .enum_variant_names
+                .insert(id.stripped(), variant_name);
+            type_registry
+                .enum_variant_ids
+                .insert(id.stripped(), variant_id);
+        }
+    }
+
+    pub fn register_sparse_component<T: AbstractComponent>(&mut self) {
+        if !self
+            .type_registry()
+            .identifiers
+            .contains_key(&TypeId::of::<T>())
+        {
+            self.register_component::<T>();
+        }
+        let id = self.component_id::<T>();
+        if self
+            .get_archetypes_with_id(id)
+            .is_some_and(|a| !a.is_empty())
+        {
+            panic!(
+                "component {0} is already stored in archetypes and can't become sparse",
+                tynm::type_name::<T>()
+            );
+        }
+        self.sparse_sets
+            .entry(id.stripped())
+            .or_insert_with(|| SparseSet::new(id, self.type_registry.clone()));
+    }
+
+    pub fn has_sparse_sets(&self) -> bool {
+        !self.sparse_sets.is_empty()
+    }
+
+    pub fn is_sparse(&self, component: Identifier) -> bool {
+        self.sparse_sets.contains_key(&component.stripped())
+    }
+
+    pub fn sparse_set(&self, component: Identifier) -> Option<&SparseSet> {
+        self.sparse_sets.get(&component.stripped())
     }
 
     pub fn add_relationship(
@@ -1645,6 +3288,14 @@ impl Archetypes {
         *self.record_mut(relation) = Some(relation_record);
         *self.record_mut(target) = Some(target_record);
 
+        let is_exclusive = self
+            .type_registry()
+            .exclusive_relations
+            .contains(&relation.stripped());
+        if is_exclusive {
+            self.remove_other_targets(entity, relation, target)?;
+        }
+
         let relationship = Archetypes::relationship_id(relation, target);
         if matches!(table_reusage, TableReusage::Reuse) {
             self.type_registry
@@ -1653,10 +3304,41 @@ impl Archetypes {
                 .insert(relationship.into());
         }
 
-        self.add_component(relationship, entity, table_reusage)
+        let added = self.add_component(relationship, entity, table_reusage)?;
+        if matches!(added.1, ComponentAddState::New) {
+            self.queue_rel_add_callbacks(relationship, entity);
+        }
+        Ok(added)
+    }
+
+    fn queue_rel_add_callbacks(&self, relationship: Identifier, entity: Identifier) {
+        let (Some(relation), Some(target)) = (
+            self.relation_entity(relationship),
+            self.target_entity(relationship),
+        ) else {
+            return;
+        };
+        self.callbacks.borrow_mut().queue_rel_add_callbacks(
+            Self::wildcard_target_relationship(relation),
+            entity,
+            target,
+        );
     }
 
     pub fn add_component_tag(&mut self, entity: Identifier, tag: Identifier) -> Result<()> {
+        if self.is_sparse(tag) {
+            if !self.is_entity_alive(entity) {
+                bail!("expected entity to be alive")
+            }
+            //sparse sets can reallocate under live query items, so wait for the unlock
+            if self.locked {
+                self.add_operation(entity, OperationType::AddSparseTag(tag));
+                return Ok(());
+            }
+            let sparse_set = self.sparse_sets.get_mut(&tag.stripped()).unwrap();
+            sparse_set.insert(entity.low32() as usize, ());
+            return Ok(());
+        }
         self.add_entity_tag_inner(entity, tag, true)
     }
 
@@ -1743,6 +3425,9 @@ impl Archetypes {
                 self.debug_id_name(component),
             ));
         };
+        if let Some(sparse_set) = self.sparse_sets.get(&component.stripped()) {
+            return Ok(ComponentGetter::sparse(entity, component, self, sparse_set));
+        }
         let record = self
             .record(entity)
             .expect_fn(|| format!("expected entity {0:?} to have a valid record", entity));
@@ -1760,15 +3445,127 @@ impl Archetypes {
         ))
     }
 
-    pub fn add_enum_tag<T: EnumTag>(&mut self, entity: Identifier, value: T) -> Result<()> {
+    pub fn try_get_relationship_first<R: AbstractComponent, T: AbstractComponent>(
+        &self,
+        entity: Identifier,
+    ) -> Result<ComponentGetter<R>, GetComponentError> {
+        let relationship =
+            Self::relationship_id(self.component_id::<R>(), self.component_id::<T>());
+        self.get_component(relationship, entity)
+    }
+
+    pub fn try_get_relationship_second<R: AbstractComponent, T: AbstractComponent>(
+        &self,
+        entity: Identifier,
+    ) -> Result<ComponentGetter<T>, GetComponentError> {
+        let relationship =
+            Self::relationship_id(self.component_id::<R>(), self.component_id::<T>());
+        self.get_component(relationship, entity)
+    }
+
+    pub fn try_get_mixed_relationship<R: AbstractComponent>(
+        &self,
+        entity: Identifier,
+        target: Identifier,
+    ) -> Result<ComponentGetter<R>, GetComponentError> {
+        let relationship = Self::relationship_id(self.component_id::<R>(), target);
+        self.get_component(relationship, entity)
+    }
+
+    //overwrites the fields present in `patch`, leaving the rest of the component as is
+    pub fn patch_component(
+        &self,
+        entity: Identifier,
+        name: &str,
+        patch: &dyn Reflect,
+    ) -> std::result::Result<(), PatchError> {
+        let name = name.to_smolstr();
+        let component = self
+            .type_registry()
+            .identifiers_by_names
+            .get(&name)
+            .copied()
+            .or_else(|| self.entity_by_global_name(name.clone()))
+            .ok_or_else(|| PatchError::UnknownComponent(name.clone()))?;
+        if !self.is_entity_alive(entity) || !self.has_component(component, entity) {
+            return Err(PatchError::Missing(self.debug_id_name(entity), name));
+        }
+        let Some(as_reflect_mut) = self
+            .type_registry()
+            .functions
+            .get(&component.stripped())
+            .map(|functions| functions.as_reflect_mut)
+        else {
+            return Err(PatchError::NotReflected(name));
+        };
+        let record = self.record(entity).unwrap();
+        let (table, row) = match self.sparse_sets.get(&component.stripped()) {
+            Some(sparse_set) => (
+                sparse_set.table().0.clone(),
+                sparse_set.row(entity.low32() as usize).unwrap(),
+            ),
+            None => (
+                self.archetype_from_record(&record)
+                    .unwrap()
+                    .borrow()
+                    .table()
+                    .clone(),
+                record.table_row,
+            ),
+        };
+        let table = table.borrow();
+        let mut storage = table.storage(component).unwrap().borrow_mut();
+        let result = RefCell::new(None);
+        as_reflect_mut(storage.component_mut(row), &|value| {
+            *result.borrow_mut() = value.map(|value| value.try_apply(patch));
+        });
+        match result.into_inner() {
+            Some(result) => Ok(result?),
+            None => Err(PatchError::NotReflected(name)),
+        }
+    }
+
+    //makes the variant names known to serialization, so the tag can be parsed back
+    pub fn register_enum_tag<T: EnumTag>(&mut self) -> Identifier {
         let enum_type_id = self.component_id::<T>();
+        let mut type_registry = self.type_registry.borrow_mut();
+        type_registry
+            .enum_variant_names
+            .insert(enum_type_id.stripped(), T::variant_name);
+        type_registry
+            .enum_variant_ids
+            .insert(enum_type_id.stripped(), T::variant_id);
+        enum_type_id
+    }
+
+    pub fn add_enum_tag<T: EnumTag>(&mut self, entity: Identifier, value: T) -> Result<()> {
+        let enum_type_id = self.register_enum_tag::<T>();
+        self.add_enum_tag_by_id(entity, enum_type_id, value.id())
+    }
+
+    fn add_enum_tag_by_id(
+        &mut self,
+        entity: Identifier,
+        enum_type_id: Identifier,
+        value: EnumId,
+    ) -> Result<()> {
         let enum_tag_id = self.component_id::<EnumTagId>();
+        let relationship = Archetypes::relationship_id(enum_type_id, enum_tag_id);
+        let old = self
+            .get_component::<EnumTagId>(relationship, entity)
+            .ok()
+            .map(|old| old.get(|c| c.0));
         self.add_data_relationship_typed::<EnumTagId>(
             entity,
             enum_type_id,
             enum_tag_id,
-            EnumTagId(value.id()),
+            EnumTagId(value),
         )?;
+        if old != Some(value) {
+            self.callbacks
+                .borrow_mut()
+                .queue_enum_tag_callbacks(enum_type_id, entity, old, value);
+        }
         Ok(())
     }
 
@@ -1783,6 +3580,104 @@ impl Archetypes {
         T::from_id(enum_id.0)
     }
 
+    //type and variant names of an enum tag relationship on the entity
+    pub fn enum_tag_variant(
+        &self,
+        relationship: Identifier,
+        entity: Identifier,
+    ) -> Option<(SmolStr, &'static str)> {
+        let enum_type = self.relation_entity(relationship)?;
+        let enum_id = self
+            .get_component::<EnumTagId>(relationship, entity)
+            .ok()?
+            .get(|c| *c);
+        let registry = self.type_registry();
+        let variant_name = registry.enum_variant_names.get(&enum_type.stripped())?;
+        let type_name = registry.type_ids_data.get(&enum_type.stripped())?.1.clone();
+        Some((type_name, variant_name(enum_id.0)?))
+    }
+
+    //every component, tag and relationship on the entity, without the ENTITY_ID marker
+    pub fn component_ids_of(&self, entity: Identifier) -> Option<Vec<Identifier>> {
+        let record = self.record(entity)?;
+        let archetype = self.archetype_by_id(record.arhetype_id).borrow();
+        Some(
+            archetype
+                .components_ids_set()
+                .iter()
+                .copied()
+                .filter(|id| id.stripped() != ENTITY_ID.stripped())
+                .collect(),
+        )
+    }
+
+    //sums archetype sizes instead of visiting every child
+    pub fn children_of_count(&self, entity: Identifier) -> usize {
+        let relationship = Self::relationship_id(self.component_id::<ChildOf>(), entity);
+        self.get_archetypes_with_id(relationship)
+            .map(|set| set.iter().map(|a| a.borrow().len()).sum())
+            .unwrap_or_default()
+    }
+
+    pub fn nth_child_of(&self, entity: Identifier, mut n: usize) -> Option<Entity> {
+        let relationship = Self::relationship_id(self.component_id::<ChildOf>(), entity);
+        for archetype in self.get_archetypes_with_id(relationship)?.iter() {
+            let archetype = archetype.borrow();
+            if n < archetype.len() {
+                let index = archetype.entity_indices()[n];
+                return self.record_by_index(index).map(|r| r.entity.into());
+            }
+            n -= archetype.len();
+        }
+        None
+    }
+
+    pub fn enum_tags(&self, entity: Identifier) -> Vec<(SmolStr, &'static str)> {
+        self.enum_tag_relationships(entity)
+            .into_iter()
+            .filter_map(|id| self.enum_tag_variant(id, entity))
+            .collect()
+    }
+
+    //(enum type id, current variant) of every enum tag on the entity
+    pub fn enum_tag_ids(&self, entity: Identifier) -> Vec<(Identifier, EnumId)> {
+        self.enum_tag_relationships(entity)
+            .into_iter()
+            .filter_map(|id| {
+                let enum_type = self.relation_entity(id)?;
+                let enum_id = self
+                    .get_component::<EnumTagId>(id, entity)
+                    .ok()?
+                    .get(|c| c.0);
+                Some((enum_type, enum_id))
+            })
+            .collect()
+    }
+
+    fn enum_tag_relationships(&self, entity: Identifier) -> Vec<Identifier> {
+        let Some(record) = self.record(entity) else {
+            return vec![];
+        };
+        let components = self
+            .archetype_by_id(record.arhetype_id)
+            .borrow()
+            .components_ids_set_rc()
+            .clone();
+        components
+            .iter()
+            .copied()
+            .filter(|id| matches!(self.component_type(*id), Ok(ComponentType::EnumTag)))
+            .collect()
+    }
+
+    //name the type was registered with, None for plain entities
+    pub fn type_name(&self, id: Identifier) -> Option<SmolStr> {
+        self.type_registry()
+            .type_ids_data
+            .get(&id.stripped())
+            .map(|(_, name)| name.clone())
+    }
+
     pub fn remove_enum_tag<T: EnumTag>(&mut self, entity: Identifier) -> Result<()> {
         let enum_tag_id = self.component_id::<EnumTagId>();
         let enum_type_id = self.component_id::<T>();
@@ -1823,23 +3718,148 @@ impl Archetypes {
             );
             return Ok(());
         }
+        if self.is_sparse(component) {
+            if !self.is_entity_alive(entity) {
+                bail!("expected entity to be alive")
+            }
+            let sparse_set = self.sparse_sets.get_mut(&component.stripped()).unwrap();
+            let replaced = sparse_set.contains(entity.low32() as usize);
+            sparse_set.insert(entity.low32() as usize, value);
+            self.callbacks
+                .borrow_mut()
+                .queue_set_callbacks(component, entity, replaced);
+            return Ok(());
+        }
         let (archetype, add_state) = self.add_component(component, entity, TableReusage::New)?;
         let mut archetype = archetype.borrow_mut();
-        match add_state {
+        let replaced = match add_state {
             ComponentAddState::New => {
                 archetype.push_component::<T>(component, value);
+                false
             }
             ComponentAddState::AlreadyExisted => {
                 let table_mut = archetype.table().borrow_mut();
                 let mut storage = table_mut.storage(component).unwrap().borrow_mut();
                 storage.replace_unchecked(self.record(entity).unwrap().table_row.0, value);
+                true
             }
+        };
+        self.callbacks
+            .borrow_mut()
+            .queue_set_callbacks(component, entity, replaced);
+        Ok(())
+    }
+
+    //for editors picking the component at runtime, `value` is the component's json form
+    pub fn add_component_by_name(
+        &mut self,
+        entity: Identifier,
+        name: &str,
+        value: serde_json::Value,
+    ) -> Result<()> {
+        let (component, functions) = self.data_component_by_name(name)?;
+        self.add_component_with(entity, component, &functions, |storage| {
+            Ok((functions.deserialize)(value, storage)?)
+        })
+    }
+
+    //a new entity with the component's reflected default value
+    pub fn spawn_default_by_name(&mut self, name: &str) -> Result<Entity> {
+        let (component, functions) = self.data_component_by_name(name)?;
+        let Some(default) = functions.default else {
+            bail!("component {name} doesn't reflect Default")
+        };
+        let entity = self.add_entity(EntityKind::Regular);
+        self.add_component_with(entity, component, &functions, |storage| {
+            default(storage);
+            Ok(())
+        })?;
+        Ok(entity.into())
+    }
+
+    fn data_component_by_name(&self, name: &str) -> Result<(Identifier, Functions)> {
+        let Some(component) = self.type_registry().identifiers_by_names.get(name).copied() else {
+            bail!("unknown component {name}")
+        };
+        let Some(functions) = self
+            .type_registry()
+            .functions
+            .get(&component.stripped())
+            .cloned()
+        else {
+            bail!("component {name} is a tag and can't be added from a value")
+        };
+        Ok((component, functions))
+    }
+
+    //`fill` pushes exactly one value into the storage it's given
+    fn add_component_with(
+        &mut self,
+        entity: Identifier,
+        component: Identifier,
+        functions: &Functions,
+        fill: impl FnOnce(RefMut<Storage>) -> Result<()>,
+    ) -> Result<()> {
+        if !self.is_entity_alive(entity) {
+            bail!("expected entity to be alive")
+        }
+        let layout = self.type_registry().layouts[&component.stripped()];
+        //SAFETY: the drop function is registered along with the component's layout
+        let scratch = RefCell::new(Storage::from(unsafe {
+            BlobVec::new(layout, functions.drop, 1)
+        }));
+        fill(scratch.borrow_mut())?;
+        let mut scratch = scratch.into_inner();
+        //SAFETY: `fill` pushed exactly one value
+        let (value, _) = unsafe { scratch.swap_remove_and_forget(0) };
+
+        if self.locked {
+            //SAFETY: the temporary storage is created with the component's layout
+            unsafe {
+                self.temp_components
+                    .storage_with_layout(component, layout)
+                    .push_ptr(value)
+            };
+            self.add_operation(
+                entity,
+                OperationType::AddComponent {
+                    component_id: component,
+                    table_reusage: TableReusage::New,
+                },
+            );
+            return Ok(());
+        }
+        if self.is_sparse(component) {
+            let sparse_set = self.sparse_sets.get_mut(&component.stripped()).unwrap();
+            let replaced = sparse_set.contains(entity.low32() as usize);
+            sparse_set.insert_ptr(entity.low32() as usize, value);
+            self.callbacks
+                .borrow_mut()
+                .queue_set_callbacks(component, entity, replaced);
+            return Ok(());
         }
+        let (archetype, add_state) = self.add_component(component, entity, TableReusage::New)?;
+        let mut archetype = archetype.borrow_mut();
+        let replaced = match add_state {
+            ComponentAddState::New => {
+                archetype.push_component_ptr(component, value);
+                false
+            }
+            ComponentAddState::AlreadyExisted => {
+                let table_mut = archetype.table().borrow_mut();
+                let mut storage = table_mut.storage(component).unwrap().borrow_mut();
+                storage.replace_unchecked_ptr(self.record(entity).unwrap().table_row.0, value);
+                true
+            }
+        };
+        self.callbacks
+            .borrow_mut()
+            .queue_set_callbacks(component, entity, replaced);
         Ok(())
     }
 
     pub fn entity_archetype(&self) -> &ArchetypeCell {
-        &self.archetypes[0]
+        self.archetypes[0].as_ref().unwrap()
     }
 
     pub fn remove_entity(
@@ -1866,7 +3886,20 @@ impl Archetypes {
             return Ok(());
         }
 
+        let mut components = archetype.borrow().components_ids().to_vec();
+        components.extend(
+            self.sparse_sets
+                .iter()
+                .filter(|(_, set)| set.contains(entity.low32() as usize))
+                .map(|(_, set)| set.component()),
+        );
+        for component in components {
+            self.queue_remove_value_callbacks(component, entity);
+        }
         self.process_entity_deletion(&record, depth, entities_pool);
+        for sparse_set in self.sparse_sets.values_mut() {
+            sparse_set.remove(entity.low32() as usize);
+        }
         archetype
             .borrow_mut()
             .remove_drop(self, record.archetype_row, Some(record.table_row));
@@ -1875,12 +3908,177 @@ impl Archetypes {
         Ok(())
     }
 
+    /// Despawns every entity that isn't a registered type and reuses their ids lowest first.
+    /// Registrations, systems and resources are kept.
+    /// Handles to cleared entities stay dead, their slots keep their generations.
+    pub fn clear_entities(&mut self) -> Result<()> {
+        if self.locked {
+            bail!("entities can't be cleared while archetypes are locked")
+        }
+        let entities: Vec<_> = {
+            let registry = self.type_registry();
+            self.records
+                .borrow()
+                .iter()
+                .flatten()
+                .map(|record| record.entity)
+                .filter(|entity| !registry.type_ids_data.contains_key(&entity.stripped()))
+                .collect()
+        };
+        let cleared: HashSet<_> = entities.iter().map(|e| e.low32() as usize).collect();
+        let pool = self.entities_pool.clone();
+        for entity in entities {
+            //children and cascaded targets go with their parents
+            if self.is_entity_alive(entity) {
+                self.remove_entity(entity, 0.into(), &mut pool.borrow_mut())?;
+            }
+        }
+
+        self.unused_ids
+            .make_contiguous()
+            .sort_unstable_by_key(|id| Reverse(id.low32()));
+        let names: Vec<_> = self
+            .names
+            .left_values()
+            .filter(|n| cleared.contains(&n.entity_index) || cleared.contains(&n.parent_index))
+            .copied()
+            .collect();
+        for left in names {
+            self.remove_entity_name(left);
+        }
+        Ok(())
+    }
+
+    fn is_record_pinned(&self, index: usize) -> bool {
+        let Some(record) = *self.record_by_index(index) else {
+            return false;
+        };
+        let entity = record.entity;
+        if entity.is_relation() || entity.is_target() || entity.is_tag() {
+            return true;
+        }
+        if self
+            .type_registry()
+            .layouts
+            .contains_key(&entity.stripped())
+        {
+            return true;
+        }
+        let low32 = index as u32;
+        self.archetypes_by_ids.keys().any(|id| {
+            let id = id.0;
+            id.low32() == low32 || (id.is_relationship() && id.second() == low32)
+        }) || self.names.left_values().any(|n| n.parent_index == index)
+    }
+
+    /// Moves live records into the holes left by despawned entities and shrinks the records.
+    /// Entities referenced by ids (components, tags, relationships, name parents) keep their slots.
+    /// Returns the new identifiers of every moved entity, old handles to them become dead.
+    pub fn compact_records(&mut self) -> Result<HashMap<Identifier, Identifier>> {
+        if self.locked {
+            bail!("records can't be compacted while archetypes are locked")
+        }
+        let mut remap = HashMap::new();
+        let len = self.records.borrow().len();
+        let free_slots = self.records.borrow().iter().filter(|r| r.is_none()).count();
+        if free_slots == 0 || free_slots * RECORDS_COMPACT_RATIO < len {
+            return Ok(remap);
+        }
+        let mut free = 0;
+        let mut last = len;
+        loop {
+            while free < last && self.record_by_index(free).is_some() {
+                free += 1;
+            }
+            while last > free
+                && (self.record_by_index(last - 1).is_none() || self.is_record_pinned(last - 1))
+            {
+                last -= 1;
+            }
+            if free >= last {
+                break;
+            }
+            last -= 1;
+            self.move_record(last, free, &mut remap);
+        }
+
+        let new_len = self
+            .records
+            .borrow()
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |i| i + 1);
+        self.records.borrow_mut().truncate(new_len);
+        //the freed slots keep their generations so dead handles stay dead, the lowest is reused first
+        self.unused_ids
+            .make_contiguous()
+            .sort_unstable_by_key(|id| Reverse(id.low32()));
+        Ok(remap)
+    }
+
+    fn move_record(&mut self, from: usize, to: usize, remap: &mut HashMap<Identifier, Identifier>) {
+        let mut record = self.record_by_index(from).unwrap();
+        let old_entity = record.entity;
+        let mut new_entity = old_entity;
+        new_entity.set_low32(to as u32);
+        //the slot was used before, so the new id has to be newer than any dead handle to it
+        if let Some(position) = self
+            .unused_ids
+            .iter()
+            .position(|id| id.low32() as usize == to)
+        {
+            let dead = self.unused_ids.remove(position).unwrap();
+            new_entity.set_second(dead.second() + 1);
+        }
+        record.entity = new_entity;
+
+        let archetype = self.archetype_from_record(&record).unwrap().clone();
+        let mut archetype = archetype.borrow_mut();
+        archetype.set_entity_index(record.archetype_row, to);
+        let mut table = archetype.table().borrow_mut();
+        if table.entity_indices().get(record.table_row.0) == Some(&from) {
+            table.set_entity_index(record.table_row, to);
+        }
+        drop(table);
+        drop(archetype);
+        for sparse_set in self.sparse_sets.values_mut() {
+            sparse_set.move_entity(from, to);
+        }
+
+        let names: Vec<_> = self
+            .names
+            .left_values()
+            .filter(|n| n.entity_index == from)
+            .copied()
+            .collect();
+        for left in names {
+            let (_, right) = self.names.remove_by_left(&left).unwrap();
+            self.names
+                .insert(NameLeft::new(to, left.parent_index), right);
+        }
+
+        let mut records = self.records.borrow_mut();
+        records[from] = None;
+        records[to] = Some(record);
+        drop(records);
+        self.unused_ids.push_back(old_entity);
+        remap.insert(old_entity, new_entity);
+    }
+
     pub fn add_operation(&mut self, entity: Identifier, op_type: OperationType) {
         self.operations
             .borrow_mut()
             .push(ArchetypeOperation { entity, op_type });
     }
 
+    //the component stays on the entity until the queued removal runs at the unlock
+    pub fn is_removal_pending(&self, entity: Identifier, component: Identifier) -> bool {
+        self.operations.borrow().iter().any(|operation| {
+            operation.entity.low32() == entity.low32()
+                && matches!(operation.op_type, OperationType::RemoveComponent(id) if id == component)
+        })
+    }
+
     pub fn children_recursive(&self, entity: Identifier) -> ChildrenRecursiveIterRef<'_> {
         ChildrenRecursiveIterRef::new(entity, self.children_pool.clone(), self)
     }
@@ -1916,24 +4114,29 @@ impl Archetypes {
             registry.tags.contains(&entity.stripped())
         };
         if is_tag {
-            self.remove_from_entities(entity);
+            self.remove_from_entities(entity, entities_pool);
         }
         if entity.is_relation() {
             let componenet = Archetypes::relationship_id(entity, WILDCARD.0);
-            self.remove_from_entities(componenet);
+            self.remove_from_entities(componenet, entities_pool);
         }
         if entity.is_target() {
             let component = Archetypes::relationship_id(WILDCARD.0, entity);
-            self.remove_from_entities(component);
+            self.remove_from_entities(component, entities_pool);
         }
     }
 
-    pub fn remove_from_entities(&mut self, component: Identifier) {
+    pub fn remove_from_entities(
+        &mut self,
+        component: Identifier,
+        entities_pool: &mut Vec<Identifier>,
+    ) {
         let Some(archetypes) = self.get_archetypes_with_id(component) else {
             return;
         };
+        let mut deleted = vec![];
         let operations_pool = self.operatoins_pool.clone();
-        let operations_pool: &mut _ = &mut operations_pool.borrow_mut();
+        let mut operations_pool = operations_pool.borrow_mut();
         operations_pool.clear();
         for archetype in archetypes {
             for entity in archetype.borrow().entity_indices() {
@@ -1945,6 +4148,10 @@ impl Archetypes {
                     if component.0.low32() == self.component_id::<ChildOf>().low32() {
                         break;
                     }
+                    if self.on_target_delete(component.0) == OnTargetDelete::Delete {
+                        deleted.push(entity);
+                        break;
+                    }
                     operations_pool.push(ArchetypeOperation {
                         entity,
                         op_type: OperationType::RemoveComponent(component.0),
@@ -1965,6 +4172,82 @@ impl Archetypes {
             self.remove_component(component, op.entity, table_reusage)
                 .unwrap();
         }
+        //the operations pool is reused by the nested deletions
+        drop(operations_pool);
+        for entity in deleted {
+            //might be gone already, as a child of another deleted entity
+            let _ = self.remove_entity(entity, 0.into(), entities_pool);
+        }
+    }
+
+    //(holder, relationship) of every relationship pointing at `target`, whatever the relation
+    pub fn relationships_with_target(
+        &self,
+        target: Identifier,
+    ) -> impl Iterator<Item = (Identifier, Identifier)> {
+        let id = IdentifierUnpacked {
+            low32: WILDCARD_32,
+            high32: IdentifierHigh32 {
+                second: target.low32().into(),
+                is_relationship: true,
+                ..Default::default()
+            },
+        }
+        .pack()
+        .unwrap();
+        self.relationship_holders(id.into(), move |component| {
+            component.low32() != WILDCARD_32 && component.second() == target.low32()
+        })
+    }
+
+    //(relation, *)
+    pub fn wildcard_target_relationship(relation: Identifier) -> Identifier {
+        IdentifierUnpacked {
+            low32: relation.low32(),
+            high32: IdentifierHigh32 {
+                second: WILDCARD_25.into(),
+                is_relationship: true,
+                ..Default::default()
+            },
+        }
+        .pack()
+        .unwrap()
+        .into()
+    }
+
+    //(holder, relationship) of every relationship with `relation`, whatever the target
+    pub fn relationships_with_relation(
+        &self,
+        relation: Identifier,
+    ) -> impl Iterator<Item = (Identifier, Identifier)> {
+        let id = Self::wildcard_target_relationship(relation);
+        self.relationship_holders(id, move |component| {
+            component.second() != WILDCARD_25 && component.low32() == relation.low32()
+        })
+    }
+
+    fn relationship_holders(
+        &self,
+        wildcard: Identifier,
+        matches: impl Fn(Identifier) -> bool,
+    ) -> impl Iterator<Item = (Identifier, Identifier)> {
+        let mut holders = vec![];
+        for archetype in self.get_archetypes_with_id(wildcard).into_iter().flatten() {
+            let archetype = archetype.borrow();
+            let relationships = archetype
+                .components_ids()
+                .iter()
+                .copied()
+                .filter(|component| component.is_relationship() && matches(*component));
+            for relationship in relationships {
+                for index in archetype.entity_indices() {
+                    if let Some(record) = *self.record_by_index(*index) {
+                        holders.push((record.entity, relationship));
+                    }
+                }
+            }
+        }
+        holders.into_iter()
     }
 
     pub fn find_rels<R: AbstractComponent, T: AbstractComponent>(
@@ -2002,7 +4285,13 @@ impl Archetypes {
         }
         let reuse_table = matches!(table_reusage, TableReusage::Reuse);
         let new_archetype = match old_edge_cloned.add {
-            Some(id) => self.archetype_by_id(id).clone(),
+            Some(id) => {
+                debug_assert!(
+                    self.archetype_exists(id),
+                    "edge points to a removed archetype"
+                );
+                self.archetype_by_id(id).clone()
+            }
             None => {
                 let mut new_components = old_archetype.borrow().components_ids_set().clone();
                 new_components.insert(component);
@@ -2024,7 +4313,12 @@ impl Archetypes {
             }
         };
         old_archetype.borrow_mut().edge_mut(component).add = old_edge_cloned.add;
-        let (new_achetype_row, new_table_row) = if !reuse_table {
+        //an existing archetype can have a different table, so entity has to be moved into it
+        let shares_table = Rc::ptr_eq(
+            old_archetype.borrow().table(),
+            new_archetype.borrow().table(),
+        );
+        let (new_achetype_row, new_table_row) = if !reuse_table || !shares_table {
             let old = old_archetype.borrow_mut();
             let new = new_archetype.borrow_mut();
             Table::move_entity(
@@ -2050,11 +4344,14 @@ impl Archetypes {
             arhetype_id: new_archetype.borrow().id(),
             entity,
         });
+        let old_table = old_archetype.borrow().table().clone().into();
+        self.debug_check_table_rows(entity, &old_table, record.table_row);
         Ok((new_archetype.clone(), ComponentAddState::New))
     }
 
     pub fn record_mut_by_index(&mut self, index: usize) -> RefMut<Option<EntityRecord>> {
         let records = self.records.borrow_mut();
+        debug_assert!(index < records.len(), "record index out of bounds");
         RefMut::map(records, |r| &mut r[index])
     }
 
@@ -2101,8 +4398,11 @@ impl Archetypes {
     ) -> ArchetypeCell {
         let regular_hash = components.regular_hash();
         let table_hash = components.table_hash(self);
-        let archetype: ArchetypeCell = Archetype::new(table.clone().0, components.clone()).into();
-        self.archetypes.push(archetype.clone());
+        //ids index into self.archetypes, so they are per world
+        let id = self.archetypes.len().into();
+        let archetype: ArchetypeCell =
+            Archetype::new(id, table.clone().0, components.clone()).into();
+        self.archetypes.push(Some(archetype.clone()));
 
         self.add_archetype_by_hash(archetype.clone(), regular_hash);
         self.add_table_by_hash(table.clone(), table_hash);
@@ -2151,7 +4451,7 @@ impl Archetypes {
         }
         for storage in self.query_storages.values() {
             let mut storage = storage.borrow_mut();
-            if storage.mask.matches_archetype(self, &archetype) {
+            if self.matches_interned(storage.mask_id, &storage.mask, &archetype) {
                 storage.archetypes.push(archetype.clone());
             }
         }
@@ -2159,6 +4459,38 @@ impl Archetypes {
         archetype
     }
 
+    pub fn matches_archetype(&self, mask: &FilterMask, archetype: &ArchetypeCell) -> bool {
+        let mask_id = self.match_cache.borrow_mut().mask_id(mask);
+        self.matches_interned(mask_id, mask, archetype)
+    }
+
+    //`mask_id` has to be the interned id of `mask`
+    fn matches_interned(
+        &self,
+        mask_id: usize,
+        mask: &FilterMask,
+        archetype: &ArchetypeCell,
+    ) -> bool {
+        let key = (archetype.borrow().id(), mask_id);
+        let cached = self.match_cache.borrow().results.get(&key).copied();
+        if let Some(matches) = cached {
+            self.match_cache.borrow_mut().hits += 1;
+            return matches;
+        }
+        let matches = mask.matches_archetype(self, archetype);
+        let mut cache = self.match_cache.borrow_mut();
+        cache.misses += 1;
+        if cache.results.len() >= MAX_CACHED_MATCHES {
+            cache.clear();
+        }
+        cache.results.insert(key, matches);
+        matches
+    }
+
+    pub fn match_cache(&self) -> Ref<'_, MatchCache> {
+        self.match_cache.borrow()
+    }
+
     pub fn archetypes_with_id(&mut self, id: Identifier) -> &mut ArchetypeSet {
         self.archetypes_by_ids.entry(id.stripped()).or_default()
     }
@@ -2169,7 +4501,11 @@ impl Archetypes {
 
     pub fn add_table_by_hash(&mut self, table: TableCell, hash: u64) {
         if let Some(tables) = self.tables_by_hashes.get_mut(&hash) {
-            tables.push(Into::into(table.clone()));
+            //tables are shared between archetypes that differ only in tags
+            let table_id = table.borrow().id();
+            if tables.iter().all(|t| t.borrow().id() != table_id) {
+                tables.push(table.clone());
+            }
             return;
         }
 
@@ -2224,4 +4560,24 @@ mod tests {
         assert!(!stripped.high32.is_active);
         assert!(stripped.high32.is_relationship);
     }
+
+    #[test]
+    fn match_cache_is_bounded() {
+        let mask = |i: u32| {
+            let mut id = Identifier([0; 8]);
+            id.set_low32(i);
+            let mut mask = FilterMask::new();
+            mask.push_has(id);
+            mask
+        };
+        let mut cache = MatchCache::default();
+        let first = cache.mask_id(&mask(0));
+        assert_eq!(cache.mask_id(&mask(0)), first);
+        for i in 1..MAX_INTERNED_MASKS as u32 * 2 {
+            cache.mask_id(&mask(i));
+        }
+        assert!(cache.mask_ids.len() <= MAX_INTERNED_MASKS);
+        //ids aren't reused after a clear, so a stale id can't see another mask's results
+        assert_ne!(cache.mask_id(&mask(0)), first);
+    }
 }
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+//column-wise world snapshot encoded with bincode. Entities are referred to by their index
+//and get fresh ids when the snapshot is loaded
+#[derive(Serialize, Deserialize, Default)]
+pub struct WorldSnapshot {
+    pub archetypes: Vec<ArchetypeSnapshot>,
+    pub sparse_sets: Vec<SparseSetSnapshot>,
+    pub names: Vec<(u32, String)>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ArchetypeSnapshot {
+    pub entities: Vec<u32>,
+    pub ids: Vec<IdSnapshot>,
+    //the values of the id at the same index in entity order, empty for tags
+    pub columns: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SparseSetSnapshot {
+    pub component: String,
+    pub entities: Vec<u32>,
+    pub column: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum IdSnapshot {
+    Single(IdPart),
+    Pair {
+        relation: IdPart,
+        target: IdPart,
+        has_data: bool,
+    },
+}
+
+//types are stored by name, entities by their index
+#[derive(Serialize, Deserialize)]
+pub enum IdPart {
+    Type(String),
+    Entity(u32),
+}
@@ -2,6 +2,7 @@ use std::{
     alloc::{handle_alloc_error, Layout},
     cell::UnsafeCell,
     num::NonZeroUsize,
+    ops::Range,
     ptr::NonNull,
 };
 
@@ -142,6 +143,37 @@ impl BlobVec {
         }
     }
 
+    /// Reallocates the vector so that its capacity matches its length, freeing the memory entirely
+    /// when it is empty. Does nothing for ZSTs, whose capacity is always `usize::MAX`.
+    pub fn shrink_to_fit(&mut self) {
+        if self.item_layout.size() == 0 || self.capacity == self.len {
+            return;
+        }
+        let old_layout =
+            array_layout(&self.item_layout, self.capacity).expect("array layout should be valid");
+        if self.len == 0 {
+            // SAFETY: the capacity is non-zero and the item size is non-zero, so the data was
+            // allocated with `old_layout`
+            unsafe {
+                std::alloc::dealloc(self.get_ptr_mut().as_ptr(), old_layout);
+            }
+            let align = NonZeroUsize::new(self.item_layout.align()).expect("alignment must be > 0");
+            self.data = bevy_ptr::dangling_with_align(align);
+            self.capacity = 0;
+            return;
+        }
+        let new_layout =
+            array_layout(&self.item_layout, self.len).expect("array layout should be valid");
+        // SAFETY:
+        // - ptr was allocated via this allocator with `old_layout`
+        // - `len > 0` and the item size is non-zero, so the new size is non-zero
+        let new_data = unsafe {
+            std::alloc::realloc(self.get_ptr_mut().as_ptr(), old_layout, new_layout.size())
+        };
+        self.data = NonNull::new(new_data).unwrap_or_else(|| handle_alloc_error(new_layout));
+        self.capacity = self.len;
+    }
+
     /// Grows the capacity by `increment` elements.
     ///
     /// # Panics
@@ -265,6 +297,26 @@ impl BlobVec {
         self.initialize_unchecked(index, value);
     }
 
+    /// Appends every element of `values` to the back of the vector, reserving space for all of
+    /// them at once. `len()` is only used as a hint, so an iterator that reports a wrong
+    /// length still can't write out of bounds.
+    ///
+    /// # Safety
+    /// Every value must match the [`layout`](`BlobVec::layout`) of the elements in the [`BlobVec`].
+    pub unsafe fn extend_from_ptrs<'a>(
+        &mut self,
+        values: impl ExactSizeIterator<Item = OwningPtr<'a>>,
+    ) {
+        self.reserve(values.len());
+        for value in values {
+            // a no-op unless the iterator yielded more values than its `len()`
+            self.reserve(1);
+            let index = self.len;
+            self.len += 1;
+            self.initialize_unchecked(index, value);
+        }
+    }
+
     /// Forces the length of the vector to `len`.
     ///
     /// # Safety
@@ -346,6 +398,28 @@ impl BlobVec {
         }
     }
 
+    /// Returns a [`Ptr`] to the element at `index`, or `None` if `index` is out of bounds.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<Ptr<'_>> {
+        if index >= self.len() {
+            return None;
+        }
+        let size = self.item_layout.size();
+        // SAFETY: `index` was checked to fit in this vector, see `get_checked`.
+        Some(unsafe { self.get_ptr().byte_add(index * size) })
+    }
+
+    /// Returns a [`PtrMut`] to the element at `index`, or `None` if `index` is out of bounds.
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<PtrMut<'_>> {
+        if index >= self.len() {
+            return None;
+        }
+        let size = self.item_layout.size();
+        // SAFETY: `index` was checked to fit in this vector, see `get_checked_mut`.
+        Some(unsafe { self.get_ptr_mut().byte_add(index * size) })
+    }
+
     /// Returns a reference to the element at `index`, with bounds checking.
     /// # Safety
     #[inline]
@@ -387,6 +461,26 @@ impl BlobVec {
         unsafe { PtrMut::new(self.data) }
     }
 
+    /// Returns an iterator over [`Ptr`]s to every element in the vector, in order.
+    pub fn iter(&self) -> impl Iterator<Item = Ptr<'_>> + '_ {
+        self.iter_range(0..self.len)
+    }
+
+    /// Returns an iterator over [`Ptr`]s to the elements in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn iter_range(&self, range: Range<usize>) -> impl Iterator<Item = Ptr<'_>> + '_ {
+        assert!(range.start <= range.end && range.end <= self.len);
+        let size = self.item_layout.size();
+        // SAFETY:
+        // - `range` was checked to fit in this vector.
+        // - `size` is a multiple of the erased type's alignment,
+        //  so adding a multiple of `size` will preserve alignment.
+        range.map(move |index| unsafe { self.get_ptr().byte_add(index * size) })
+    }
+
     /// Get a reference to the entire [`BlobVec`] as if it were an array with elements of type `T`
     ///
     /// # Safety
@@ -624,6 +718,95 @@ mod tests {
         assert_eq!(*drop_counter.borrow(), 6);
     }
 
+    #[test]
+    fn get_out_of_bounds() {
+        let item_layout = Layout::new::<usize>();
+        // SAFETY: `drop` fn is `None`, usize doesn't need dropping
+        let mut blob_vec = unsafe { BlobVec::new(item_layout, None, 4) };
+        assert!(blob_vec.get(0).is_none());
+        // SAFETY: `i` is a usize, i.e. the type corresponding to `item_layout`
+        unsafe {
+            for i in 0..3 {
+                push(&mut blob_vec, i as usize);
+            }
+        }
+        // SAFETY: the value at index 2 is a usize
+        assert_eq!(unsafe { *blob_vec.get(2).unwrap().deref::<usize>() }, 2);
+        assert!(blob_vec.get(3).is_none());
+        assert!(blob_vec.get(usize::MAX / 2).is_none());
+        assert!(blob_vec.get_mut(3).is_none());
+    }
+
+    #[test]
+    fn extend_and_replace_drops_once() {
+        let drop_counter = Rc::new(RefCell::new(0));
+        let foo = |a: u8| Foo {
+            a,
+            b: a.to_string(),
+            drop_counter: drop_counter.clone(),
+        };
+        {
+            // SAFETY: drop is able to drop a value of its `item_layout`
+            let mut blob_vec =
+                unsafe { BlobVec::new(Layout::new::<Foo>(), Some(drop_ptr::<Foo>), 0) };
+            let mut values = std::mem::ManuallyDrop::new((0..8).map(foo).collect::<Vec<_>>());
+            // SAFETY: every pointer points to an initialized `Foo`, ownership is moved into the
+            // blob vec and the source vec never drops its elements
+            unsafe {
+                let ptrs = values
+                    .iter_mut()
+                    .map(|v| OwningPtr::new(std::ptr::NonNull::from(v).cast()));
+                blob_vec.extend_from_ptrs(ptrs);
+                values.set_len(0);
+                std::mem::ManuallyDrop::drop(&mut values);
+            }
+            assert_eq!(blob_vec.len(), 8);
+            assert_eq!(*drop_counter.borrow(), 0);
+
+            // SAFETY: every index is in range and the values are of type `Foo`
+            unsafe {
+                for i in 0..4 {
+                    OwningPtr::make(foo(100 + i as u8), |ptr| blob_vec.replace_unchecked(i, ptr));
+                }
+                assert_eq!(*drop_counter.borrow(), 4);
+                assert_eq!(get_mut::<Foo>(&mut blob_vec, 0).a, 100);
+                assert_eq!(get_mut::<Foo>(&mut blob_vec, 7).a, 7);
+            }
+        }
+        assert_eq!(*drop_counter.borrow(), 12);
+    }
+
+    #[test]
+    fn extend_with_wrong_len() {
+        //claims to be empty but yields every value
+        struct Lying<I>(I);
+        impl<I: Iterator> Iterator for Lying<I> {
+            type Item = I::Item;
+            fn next(&mut self) -> Option<I::Item> {
+                self.0.next()
+            }
+        }
+        impl<I: Iterator> ExactSizeIterator for Lying<I> {
+            fn len(&self) -> usize {
+                0
+            }
+        }
+
+        // SAFETY: no drop is correct drop for `usize`
+        let mut blob_vec = unsafe { BlobVec::new(Layout::new::<usize>(), None, 0) };
+        let mut values: Vec<usize> = (0..100).collect();
+        // SAFETY: every pointer points to an initialized `usize`, which is `Copy`
+        unsafe {
+            let ptrs = values
+                .iter_mut()
+                .map(|v| OwningPtr::new(std::ptr::NonNull::from(v).cast()));
+            blob_vec.extend_from_ptrs(Lying(ptrs));
+        }
+        assert_eq!(blob_vec.len(), 100);
+        assert!(blob_vec.capacity() >= 100);
+        assert_eq!(unsafe { *blob_vec.get(99).unwrap().deref::<usize>() }, 99);
+    }
+
     #[test]
     fn blob_vec_drop_empty_capacity() {
         let item_layout = Layout::new::<Foo>();
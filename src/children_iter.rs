@@ -7,19 +7,36 @@ use crate::{
     world::archetypes,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraversalOrder {
+    #[default]
+    DepthFirst,
+    BreadthFirst,
+}
+
 pub struct ChildrenRecursiveIter {
     pub entity: Identifier,
     index: usize,
+    order: TraversalOrder,
     children: Rc<RefCell<Vec<(Entity, Depth)>>>,
 }
 
 impl ChildrenRecursiveIter {
     pub fn new(entity: Identifier, children_pool: Rc<RefCell<Vec<(Entity, Depth)>>>) -> Self {
+        Self::ordered(entity, children_pool, TraversalOrder::DepthFirst)
+    }
+
+    pub fn ordered(
+        entity: Identifier,
+        children_pool: Rc<RefCell<Vec<(Entity, Depth)>>>,
+        order: TraversalOrder,
+    ) -> Self {
         children_pool.borrow_mut().clear();
 
         Self {
             entity,
             index: 0,
+            order,
             children: children_pool.clone(),
         }
     }
@@ -40,6 +57,41 @@ impl From<Depth> for u32 {
     }
 }
 
+fn push_children(
+    entity: Identifier,
+    archetypes: &Archetypes,
+    children: &mut Vec<(Entity, Depth)>,
+    depth: Depth,
+) {
+    let relation = archetypes.component_id::<ChildOf>();
+    let relationship = Archetypes::relationship_id(relation, entity);
+    let Some(archetypes_set) = archetypes.get_archetypes_with_id(relationship) else {
+        return;
+    };
+
+    for archetype in archetypes_set.iter() {
+        for entity_index in archetype.borrow().entity_indices() {
+            let record = archetypes.record_by_index(*entity_index).unwrap();
+            children.push((record.entity.into(), depth));
+        }
+    }
+}
+
+//the vec itself serves as the queue, so no extra allocation is needed
+pub fn get_children_breadth_first(
+    entity: Identifier,
+    archetypes: &Archetypes,
+    children: &mut Vec<(Entity, Depth)>,
+) {
+    push_children(entity, archetypes, children, 0.into());
+    let mut index = 0;
+    while index < children.len() {
+        let (child, depth) = children[index];
+        push_children(child.0, archetypes, children, (depth.0 + 1).into());
+        index += 1;
+    }
+}
+
 pub fn get_children_recursive(
     entity: Identifier,
     archetypes: &Archetypes,
@@ -73,8 +125,13 @@ impl Iterator for ChildrenRecursiveIter {
     fn next(&mut self) -> Option<Self::Item> {
         let children: &mut _ = &mut self.children.borrow_mut();
         if children.is_empty() {
-            archetypes(|a| {
-                get_children_recursive(self.entity, a, children, 0.into());
+            archetypes(|a| match self.order {
+                TraversalOrder::DepthFirst => {
+                    get_children_recursive(self.entity, a, children, 0.into())
+                }
+                TraversalOrder::BreadthFirst => {
+                    get_children_breadth_first(self.entity, a, children)
+                }
             });
         }
         if children.is_empty() || self.index == children.len() {
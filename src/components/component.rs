@@ -1,5 +1,5 @@
 use bevy_ptr::Ptr;
-use bevy_reflect::Reflect;
+use bevy_reflect::{GetTypeRegistration, Reflect};
 use std::{any::Any, cell::RefMut, fmt::Debug};
 
 use crate::{systems::EnumId, table::Storage};
@@ -18,8 +18,8 @@ macro_rules! impl_component {
             ),*
         $(,)? );
     ) => {
-        $( #[$meta] )*
         #[derive(Clone, bevy_reflect::Reflect, serde::Serialize, serde::Deserialize)]
+        $( #[$meta] )*
         $vis struct $name (
             $(
                 $( #[$field_meta] )*
@@ -56,6 +56,20 @@ macro_rules! impl_component {
                 storage.push(value);
                 Ok(())
             }
+
+            fn serialize_bin(value: bevy_ptr::Ptr<'_>, bytes: &mut Vec<u8>) -> bincode::Result<()> {
+                let value = unsafe { value.deref::<$name>() };
+                bincode::serialize_into(bytes, value)
+            }
+
+            fn deserialize_bin(
+                bytes: &mut &[u8],
+                mut storage: std::cell::RefMut<$crate::table::Storage>,
+            ) -> bincode::Result<()> {
+                let value = bincode::deserialize_from::<_, Self>(bytes)?;
+                storage.push(value);
+                Ok(())
+            }
         }
     };
     {
@@ -67,8 +81,8 @@ macro_rules! impl_component {
             ),*
         $(,)? }
     } => {
-        $( #[$meta] )*
         #[derive(Clone, bevy_reflect::Reflect, serde::Serialize, serde::Deserialize)]
+        $( #[$meta] )*
         $vis struct $name {
             $(
                 $( #[$field_meta] )*
@@ -106,6 +120,20 @@ macro_rules! impl_component {
                 storage.push(value);
                 Ok(())
             }
+
+            fn serialize_bin(value: bevy_ptr::Ptr<'_>, bytes: &mut Vec<u8>) -> bincode::Result<()> {
+                let value = unsafe { value.deref::<$name>() };
+                bincode::serialize_into(bytes, value)
+            }
+
+            fn deserialize_bin(
+                bytes: &mut &[u8],
+                mut storage: std::cell::RefMut<$crate::table::Storage>,
+            ) -> bincode::Result<()> {
+                let value = bincode::deserialize_from::<_, Self>(bytes)?;
+                storage.push(value);
+                Ok(())
+            }
         }
     }
 }
@@ -174,6 +202,28 @@ macro_rules! enum_tag {
                 storage.push(value);
                 Ok(())
             }
+
+            fn serialize_bin(value: bevy_ptr::Ptr<'_>, bytes: &mut Vec<u8>) -> bincode::Result<()> {
+                let value = unsafe { value.deref::<$name>() };
+                bincode::serialize_into(bytes, value)
+            }
+
+            fn deserialize_bin(
+                bytes: &mut &[u8],
+                mut storage: std::cell::RefMut<$crate::table::Storage>,
+            ) -> bincode::Result<()> {
+                let value = bincode::deserialize_from::<_, Self>(bytes)?;
+                storage.push(value);
+                Ok(())
+            }
+
+            fn enum_variants() -> Option<(
+                fn($crate::systems::EnumId) -> Option<&'static str>,
+                fn(&str) -> Option<$crate::systems::EnumId>,
+            )> {
+                use $crate::components::component::EnumTag;
+                Some((Self::variant_name, Self::variant_id))
+            }
         }
 
         impl $crate::components::component::EnumTag for $name {
@@ -193,10 +243,31 @@ macro_rules! enum_tag {
                     _ => None
                 }
             }
+
+            fn variant_name(id: $crate::systems::EnumId) -> Option<&'static str> {
+                Self::from_id(id).map(|variant| match variant {
+                    $(
+                        $name::$vname => stringify!($vname),
+                    )*
+                })
+            }
+
+            fn variant_names() -> &'static [&'static str] {
+                &[$(stringify!($vname)),*]
+            }
+
+            fn variant_id(name: &str) -> Option<$crate::systems::EnumId> {
+                match name {
+                    $(
+                        stringify!($vname) => Some($name::$vname as $crate::systems::EnumId),
+                    )*
+                    _ => None
+                }
+            }
         }
     };
 }
-pub trait AbstractComponent: 'static + Sized {
+pub trait AbstractComponent: 'static + Sized + GetTypeRegistration {
     fn clone_into(value: Ptr<'_>, storage: RefMut<Storage>);
     fn as_reflect_ref(value: bevy_ptr::Ptr<'_>, f: &dyn Fn(Option<&dyn Reflect>));
     fn as_reflect_mut(value: bevy_ptr::PtrMut<'_>, f: &dyn Fn(Option<&mut dyn Reflect>));
@@ -205,8 +276,18 @@ pub trait AbstractComponent: 'static + Sized {
         value: serde_json::Value,
         storage: RefMut<Storage>,
     ) -> serde_json::Result<()>;
+    fn serialize_bin(value: bevy_ptr::Ptr<'_>, bytes: &mut Vec<u8>) -> bincode::Result<()>;
+    fn deserialize_bin(bytes: &mut &[u8], storage: RefMut<Storage>) -> bincode::Result<()>;
+    //name lookups of enum tag variants, registered together with the type
+    #[allow(clippy::type_complexity)]
+    fn enum_variants() -> Option<(fn(EnumId) -> Option<&'static str>, fn(&str) -> Option<EnumId>)> {
+        None
+    }
 }
 pub trait EnumTag: AbstractComponent + 'static {
     fn id(&self) -> EnumId;
     fn from_id(id: EnumId) -> Option<Self>;
+    fn variant_name(id: EnumId) -> Option<&'static str>;
+    fn variant_names() -> &'static [&'static str];
+    fn variant_id(name: &str) -> Option<EnumId>;
 }
@@ -72,7 +72,7 @@ impl<T: AbstractComponent> ComponentBundle for Option<T> {
             (id, archetypes.callbacks().clone())
         });
         archetypes_mut(|a| a.lock());
-        callbacks.borrow().run_add_callback(id, entity.into());
+        callbacks.borrow().run_remove_callback(id, entity.into());
         archetypes_mut(|a| a.unlock());
     }
 }
@@ -99,7 +99,7 @@ impl<T: AbstractComponent> ComponentBundle for T {
             (id, archetypes.callbacks().clone())
         });
         archetypes_mut(|a| a.lock());
-        callbacks.borrow().run_add_callback(id, entity.into());
+        callbacks.borrow().run_remove_callback(id, entity.into());
         archetypes_mut(|a| a.unlock());
     }
 }
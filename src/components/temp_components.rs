@@ -26,14 +26,17 @@ impl TempComponentsStorage {
 
     pub fn remove_comp(&mut self, component: Identifier) -> OwningPtr {
         let storage = self.get_storage(component);
-        unsafe { storage.0.swap_remove_and_forget_unchecked(0) }
+        unsafe { storage.swap_remove_and_forget(0).0 }
     }
 
     pub fn storage<T: AbstractComponent>(&mut self, component: Identifier) -> &mut Storage {
-        let layout = Layout::new::<T>();
+        self.storage_with_layout(component, Layout::new::<T>())
+    }
+
+    pub fn storage_with_layout(&mut self, component: Identifier, layout: Layout) -> &mut Storage {
         self.storages
             .entry(component)
-            .or_insert(unsafe { Storage(BlobVec::new(layout, None, TEMP_CAPACITY)) })
+            .or_insert(unsafe { BlobVec::new(layout, None, TEMP_CAPACITY).into() })
     }
 
     pub fn get_storage(&mut self, component: Identifier) -> &mut Storage {
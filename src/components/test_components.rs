@@ -1,6 +1,8 @@
+use bevy_reflect::std_traits::ReflectDefault;
 use macro_rules_attribute::apply;
 #[apply(impl_component!)]
 #[derive(Copy, Debug, Default)]
+#[reflect(Default)]
 pub struct Position {
     #[serde(default)]
     pub x: i32,
@@ -1,14 +1,17 @@
+use std::collections::HashSet;
 pub use std::{fmt::Debug, hash::Hash, os::unix::process::parent_id};
 
-use smol_str::SmolStr;
+use bevy_reflect::Reflect;
+use smol_str::{SmolStr, ToSmolStr};
 
 use crate::{
     archetypes::{
         self, Archetypes, ChildOf, ComponentGetter, EntityNameGetter, EntityRecord,
-        GetComponentError, InstanceOf, NameLeft, TableReusage, TryGetComponent, Wildcard,
+        GetComponentError, InstanceOf, NameError, NameExistsError, NameHandle, NameLeft, NameRight,
+        Overridden, PatchError, Prefab, ReparentError, TableReusage, TryGetComponent, Wildcard,
         WILDCARD_RELATIONSHIP,
     },
-    children_iter::ChildrenRecursiveIter,
+    children_iter::{ChildrenRecursiveIter, TraversalOrder},
     components::{
         component::{AbstractComponent, EnumTag},
         component_bundle::ComponentBundle,
@@ -18,6 +21,7 @@ use crate::{
     identifier::Identifier,
     query::{Query, QueryState},
     relationship::{FindRelationshipsIter, Relationship, RelationshipsIter},
+    systems::EnumId,
     world::{archetypes, archetypes_mut},
 };
 
@@ -90,6 +94,26 @@ impl Entity {
         archetypes(|archetypes| archetypes.debug_id_name(self.0))
     }
 
+    pub fn component_ids(&self) -> Vec<Identifier> {
+        archetypes(|archetypes| archetypes.component_ids_of(self.0)).unwrap_or_default()
+    }
+
+    pub fn component_names(&self) -> Vec<SmolStr> {
+        archetypes(|archetypes| {
+            let ids = archetypes.component_ids_of(self.0).unwrap_or_default();
+            ids.into_iter()
+                .map(|id| archetypes.debug_component_name_on(id, self.0))
+                .collect()
+        })
+    }
+
+    pub fn display_name(&self) -> SmolStr {
+        match self.get_name() {
+            Some(name) => name.get(|name| name.to_smolstr()),
+            None => self.debug_name(),
+        }
+    }
+
     pub fn name_parent(&self) -> Entity {
         if let Some(parent) = self.parent() {
             parent
@@ -101,6 +125,35 @@ impl Entity {
         self.name().set(name);
         *self
     }
+    pub fn try_set_name(&self, name: &str) -> Result<Self, NameError> {
+        let parent = self.name_parent();
+        archetypes_mut(|archetypes| {
+            archetypes.set_entity_name((self.0, parent.0).into(), name.to_smolstr())
+        })?;
+        Ok(*self)
+    }
+    pub fn rename(&self, new_name: &str) -> Result<Entity, NameExistsError> {
+        let parent = self.name_parent();
+        archetypes_mut(|archetypes| {
+            archetypes.change_entity_name((self.0, parent.0).into(), new_name.to_smolstr())
+        })?;
+        Ok(*self)
+    }
+    pub fn set_name_unique(&self, base: &str) -> Entity {
+        let parent = self.name_parent();
+        archetypes_mut(|archetypes| {
+            let left = (self.0, parent.0).into();
+            if archetypes
+                .name_by_entity(&left)
+                .is_some_and(|name| name == base)
+            {
+                return;
+            }
+            let name = archetypes.free_name(parent.0, base);
+            archetypes.change_entity_name(left, name).unwrap();
+        });
+        *self
+    }
     pub fn has_name(&self) -> bool {
         let parent = self.name_parent();
         archetypes(|archetypes| archetypes.entity_has_name(&NameLeft::from_ids(self.0, parent.0)))
@@ -121,9 +174,92 @@ impl Entity {
         let parent = self.name_parent();
         EntityNameGetter::new((self.0, parent.0).into())
     }
+    //looks the name up in the scope this entity is named in
+    pub fn find_sibling(&self, name: &str) -> Option<Entity> {
+        let parent = self.name_parent();
+        let name = NameRight::new(name.to_smolstr(), parent.0.low32() as usize);
+        archetypes(|archetypes| archetypes.entity_by_name(&name)).map(Entity)
+    }
+    pub fn find_child_named(&self, name: &str) -> Option<Entity> {
+        let name = NameRight::new(name.to_smolstr(), self.0.low32() as usize);
+        archetypes(|archetypes| archetypes.entity_by_name(&name)).map(Entity)
+    }
+    pub fn name_handle(&self) -> NameHandle {
+        NameHandle::new(*self)
+    }
+    //slash-joined names from the root down to this entity. None if any of them is unnamed,
+    //a partial path would point at a different entity in entity_by_path
+    pub fn path(&self) -> Option<String> {
+        let name_of = |entity: Entity| {
+            let parent = entity.name_parent();
+            archetypes(|archetypes| {
+                archetypes
+                    .name_by_entity(&NameLeft::from_ids(entity.0, parent.0))
+                    .cloned()
+            })
+        };
+        let mut names = std::iter::once(*self)
+            .chain(self.ancestors())
+            .map(name_of)
+            .collect::<Option<Vec<_>>>()?;
+        names.reverse();
+        Some(names.join("/"))
+    }
     pub fn parent(&self) -> Option<Entity> {
         self.find_rel::<ChildOf, Wildcard>().map(|r| r.target())
     }
+
+    //parent, grandparent and so on as live handles with their current generation. Stops at a
+    //dead parent, or if an id repeats so a cyclic hierarchy can't loop forever
+    pub fn ancestors(&self) -> impl Iterator<Item = Entity> {
+        let mut visited = HashSet::from([self.0.low32()]);
+        let mut current = *self;
+        std::iter::from_fn(move || {
+            let parent = current.parent().filter(Entity::is_alive)?;
+            if !visited.insert(parent.0.low32()) {
+                return None;
+            }
+            current = parent;
+            Some(parent)
+        })
+    }
+
+    pub fn root(&self) -> Entity {
+        self.ancestors().last().unwrap_or(*self)
+    }
+
+    pub fn is_descendant_of(&self, ancestor: &Entity) -> bool {
+        self.ancestors()
+            .any(|entity| entity.0.low32() == ancestor.0.low32())
+    }
+
+    pub fn is_ancestor_of(&self, descendant: &Entity) -> bool {
+        descendant.is_descendant_of(self)
+    }
+
+    //parents left once deferred removals run. Reparenting inside a query only queues the
+    //removal of the old ChildOf, so until the unlock an entity can have two of them
+    fn pending_parents(&self) -> Vec<Entity> {
+        self.find_rels::<ChildOf, Wildcard>()
+            .filter(|rel| !archetypes(|archetypes| archetypes.is_removal_pending(self.0, rel.0)))
+            .map(|rel| rel.target())
+            .collect()
+    }
+
+    fn would_cycle(&self, new_parent: Entity) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![new_parent];
+        while let Some(entity) = stack.pop() {
+            if entity.0.low32() == self.0.low32() {
+                return true;
+            }
+            if visited.insert(entity.0.low32()) {
+                stack.extend(entity.pending_parents());
+            }
+        }
+        false
+    }
+
     pub fn find_mixed_rels<R: AbstractComponent>(&self, target: Entity) -> FindRelationshipsIter {
         archetypes_mut(|archetypes| {
             let relation = archetypes.component_id::<R>();
@@ -166,7 +302,19 @@ impl Entity {
     pub fn has_relationship(&self, relationship: Relationship) -> bool {
         archetypes(|archetypes| archetypes.has_component(relationship.0, self.0))
     }
+    //logs a warning and does nothing if parent is this entity or one of its descendants,
+    //try_add_child_of reports it instead
     pub fn add_child_of(&self, parent: Entity) -> Self {
+        if let Err(err) = self.try_add_child_of(parent) {
+            log::warn!("{err}");
+        }
+        *self
+    }
+
+    pub fn try_add_child_of(&self, parent: Entity) -> Result<Self, ReparentError> {
+        if self.would_cycle(parent) {
+            return Err(ReparentError::Cycle(self.0.low32()));
+        }
         let name_parent = self.name_parent();
         let old_entity_and_parent = NameLeft::from_ids(self.into(), name_parent.into());
         self.add_mixed_tag_rel::<ChildOf>(parent);
@@ -178,13 +326,38 @@ impl Entity {
                     .to_owned();
                 let entity_and_parent = NameLeft::from_ids(self.into(), parent.into());
                 archetypes.remove_entity_name(old_entity_and_parent);
-                archetypes.set_entity_name(entity_and_parent, name);
+                archetypes.set_entity_name(entity_and_parent, name).unwrap();
             }
         });
         if !parent.is_active() {
             self.diactivate();
         }
-        *self
+        Ok(*self)
+    }
+
+    //moves the ChildOf relationship and the name scope together. Nothing changes if the
+    //name is already taken under the new parent or the new parent is inside this entity's subtree
+    pub fn reparent(&self, new_parent: Entity) -> Result<Self, ReparentError> {
+        if self.would_cycle(new_parent) {
+            return Err(ReparentError::Cycle(self.0.low32()));
+        }
+        let old_left = NameLeft::from_ids(self.0, self.name_parent().0);
+        let name = archetypes(|archetypes| archetypes.name_by_entity(&old_left).cloned());
+        if let Some(name) = &name {
+            let new_right = NameRight::new(name.clone(), new_parent.0.low32() as usize);
+            let existing = archetypes(|archetypes| archetypes.entity_by_name(&new_right));
+            if existing.is_some_and(|e| e.low32() != self.0.low32()) {
+                return Err(NameExistsError(name.clone()).into());
+            }
+        }
+        self.add_mixed_tag_rel::<ChildOf>(new_parent);
+        if name.is_some() {
+            archetypes_mut(|archetypes| archetypes.set_entity_name_parent(old_left, new_parent.0))?;
+        }
+        if !new_parent.is_active() {
+            self.diactivate();
+        }
+        Ok(*self)
     }
 
     pub fn is_child_of(&self, parent: Entity) -> bool {
@@ -202,11 +375,29 @@ impl Entity {
                     .to_owned();
                 let entity_and_parent = NameLeft::from_ids(self.into(), WILDCARD.into());
                 archetypes.remove_entity_name(old_entity_and_parent);
-                archetypes.set_entity_name(entity_and_parent, name);
+                archetypes.set_entity_name(entity_and_parent, name).unwrap();
             }
         })
     }
 
+    //strips every component, tag and relationship and the name, keeping the entity alive
+    pub fn clear(&self) -> Entity {
+        //remove callbacks run first so they can still read the values
+        let (removed, callbacks) = archetypes(|archetypes| {
+            (
+                archetypes.clearable_components(self.0),
+                archetypes.callbacks().clone(),
+            )
+        });
+        archetypes_mut(|a| a.lock());
+        for id in removed {
+            callbacks.borrow().run_remove_callback(id, self.0);
+        }
+        archetypes_mut(|a| a.unlock());
+        archetypes_mut(|archetypes| archetypes.clear_components(self.0).unwrap());
+        *self
+    }
+
     pub fn remove_all_child_of_rels(&self) {
         archetypes_mut(|archetypes| {
             for rel in self.find_rels::<ChildOf, Wildcard>() {
@@ -220,6 +411,11 @@ impl Entity {
         ChildrenRecursiveIter::new(self.0, children_pool)
     }
 
+    pub fn children_recursive_ordered(&self, order: TraversalOrder) -> ChildrenRecursiveIter {
+        let children_pool = archetypes(|a| a.children_pool().clone());
+        ChildrenRecursiveIter::ordered(self.0, children_pool, order)
+    }
+
     pub fn children(&self) -> Query<&Entity> {
         QueryState::<&Entity, ()>::new()
             .with_rel::<ChildOf, Wildcard>()
@@ -285,6 +481,20 @@ impl Entity {
         archetypes_mut(|archetypes| archetypes.get_enum_tag::<T>(self.0))
     }
 
+    pub fn enum_tag_or<T: EnumTag>(&self, default: T) -> T {
+        self.get_enum_tag::<T>().unwrap_or(default)
+    }
+
+    //(type name, variant name) of every enum tag on the entity
+    pub fn iter_enum_tags(&self) -> impl Iterator<Item = (SmolStr, &'static str)> {
+        archetypes(|archetypes| archetypes.enum_tags(self.0)).into_iter()
+    }
+
+    //(enum type id, variant id) of every enum tag, `World::type_name` resolves the type
+    pub fn enum_tags(&self) -> impl Iterator<Item = (Identifier, EnumId)> {
+        archetypes(|archetypes| archetypes.enum_tag_ids(self.0)).into_iter()
+    }
+
     pub fn has_any_enum_tag<T: EnumTag>(&self) -> bool {
         self.get_enum_tag::<T>().is_some()
     }
@@ -328,22 +538,40 @@ impl Entity {
     }
 
     pub fn rel_second<R: AbstractComponent, T: AbstractComponent>(&self) -> ComponentGetter<T> {
+        self.try_rel_second::<R, T>().unwrap()
+    }
+
+    pub fn rel_second_mut<R: AbstractComponent, T: AbstractComponent>(&self) -> ComponentGetter<T> {
+        self.try_rel_second::<R, T>().unwrap()
+    }
+
+    pub fn try_rel_second<R: AbstractComponent, T: AbstractComponent>(
+        &self,
+    ) -> Option<ComponentGetter<T>> {
         assert!(std::mem::size_of::<R>() == 0);
         assert!(std::mem::size_of::<T>() > 0);
 
-        archetypes_mut(|archetypes| {
-            let relationship = archetypes.relationship_id_typed::<R, T>();
-            archetypes.get_component(relationship, self.0).unwrap()
-        })
+        archetypes(|archetypes| archetypes.try_get_relationship_second::<R, T>(self.0).ok())
     }
 
-    pub fn rel_second_mut<R: AbstractComponent, T: AbstractComponent>(&self) -> ComponentGetter<T> {
+    pub fn get_rel_second_opt<R: AbstractComponent, T: AbstractComponent, U>(
+        &self,
+        f: impl FnOnce(&T) -> U,
+    ) -> Option<U> {
         assert!(std::mem::size_of::<R>() == 0);
         assert!(std::mem::size_of::<T>() > 0);
 
         archetypes_mut(|archetypes| {
             let relationship = archetypes.relationship_id_typed::<R, T>();
-            archetypes.get_component(relationship, self.0).unwrap()
+            if !archetypes.has_component(relationship, self.0) {
+                return None;
+            }
+            Some(
+                archetypes
+                    .get_component(relationship, self.0)
+                    .unwrap()
+                    .get(f),
+            )
         })
     }
 
@@ -363,25 +591,43 @@ impl Entity {
     }
 
     pub fn rel_first<R: AbstractComponent, T: AbstractComponent>(&self) -> ComponentGetter<R> {
+        self.try_rel_first::<R, T>().unwrap()
+    }
+
+    pub fn try_rel_first<R: AbstractComponent, T: AbstractComponent>(
+        &self,
+    ) -> Option<ComponentGetter<R>> {
         assert!(std::mem::size_of::<R>() > 0);
         assert!(std::mem::size_of::<T>() == 0);
 
-        archetypes_mut(|archetypes| {
-            let relationship = archetypes.relationship_id_typed::<R, T>();
-            archetypes.get_component(relationship, self.0).unwrap()
-        })
+        archetypes(|archetypes| archetypes.try_get_relationship_first::<R, T>(self.0).ok())
     }
 
-    pub fn rel_first_mut<R: AbstractComponent, T: AbstractComponent>(&self) -> ComponentGetter<R> {
+    pub fn get_rel_first_opt<R: AbstractComponent, T: AbstractComponent, U>(
+        &self,
+        f: impl FnOnce(&R) -> U,
+    ) -> Option<U> {
         assert!(std::mem::size_of::<R>() > 0);
         assert!(std::mem::size_of::<T>() == 0);
 
         archetypes_mut(|archetypes| {
             let relationship = archetypes.relationship_id_typed::<R, T>();
-            archetypes.get_component(relationship, self.0).unwrap()
+            if !archetypes.has_component(relationship, self.0) {
+                return None;
+            }
+            Some(
+                archetypes
+                    .get_component(relationship, self.0)
+                    .unwrap()
+                    .get(f),
+            )
         })
     }
 
+    pub fn rel_first_mut<R: AbstractComponent, T: AbstractComponent>(&self) -> ComponentGetter<R> {
+        self.try_rel_first::<R, T>().unwrap()
+    }
+
     pub fn has_mixed_rel<R: AbstractComponent>(&self, target: Entity) -> bool {
         archetypes_mut(|archetypes| {
             let relation_id = archetypes.component_id::<R>();
@@ -417,20 +663,22 @@ impl Entity {
     }
 
     pub fn mixed_rel<R: AbstractComponent>(&self, target: Entity) -> ComponentGetter<R> {
-        assert!(std::mem::size_of::<R>() > 0);
-        archetypes_mut(|archetypes| {
-            let relation_id = archetypes.component_id::<R>();
-            let relationship = Archetypes::relationship_id(relation_id, target.0);
-            archetypes.get_component(relationship, self.0).unwrap()
-        })
+        self.try_mixed_rel(target).unwrap()
     }
 
     pub fn mixed_rel_mut<R: AbstractComponent>(&self, target: Entity) -> ComponentGetter<R> {
+        self.try_mixed_rel(target).unwrap()
+    }
+
+    pub fn try_mixed_rel<R: AbstractComponent>(
+        &self,
+        target: Entity,
+    ) -> Option<ComponentGetter<R>> {
         assert!(std::mem::size_of::<R>() > 0);
-        archetypes_mut(|archetypes| {
-            let relation_id = archetypes.component_id::<R>();
-            let relationship = Archetypes::relationship_id(relation_id, target.0);
-            archetypes.get_component(relationship, self.0).unwrap()
+        archetypes(|archetypes| {
+            archetypes
+                .try_get_mixed_relationship::<R>(self.0, target.0)
+                .ok()
         })
     }
 
@@ -466,6 +714,19 @@ impl Entity {
         })
     }
 
+    pub fn toggle_tag<T: AbstractComponent>(&self) -> Entity {
+        let present = self.has_tag::<T>();
+        self.set_tag::<T>(!present)
+    }
+
+    pub fn set_tag<T: AbstractComponent>(&self, present: bool) -> Entity {
+        match (present, self.has_tag::<T>()) {
+            (true, false) => self.add_tag::<T>(),
+            (false, true) => self.remove_tag::<T>(),
+            _ => *self,
+        }
+    }
+
     pub fn add_ent_tag(&self, tag: Entity) -> Entity {
         archetypes_mut(|archetypes| {
             archetypes.add_entity_tag(self.0, tag.0).unwrap();
@@ -536,6 +797,15 @@ impl Entity {
         archetypes_mut(|archetypes| Self(archetypes.clone_entity(self.0).unwrap()))
     }
 
+    pub fn add_comp_by_name(&self, name: &str, value: serde_json::Value) -> anyhow::Result<Self> {
+        archetypes_mut(|archetypes| archetypes.add_component_by_name(self.0, name, value))?;
+        Ok(*self)
+    }
+
+    pub fn patch_comp_by_name(&self, name: &str, patch: &dyn Reflect) -> Result<(), PatchError> {
+        archetypes(|archetypes| archetypes.patch_component(self.0, name, patch))
+    }
+
     pub fn has_rel<R: AbstractComponent, T: AbstractComponent>(&self) -> bool {
         archetypes_mut(|archetypes| {
             let relationship = archetypes.relationship_id_typed::<R, T>();
@@ -664,10 +934,15 @@ impl Entity {
     }
 
     pub fn has_children(&self) -> bool {
-        !QueryState::<(), ()>::new()
-            .with_children_of(*self)
-            .build()
-            .is_empty()
+        self.child_count() > 0
+    }
+
+    pub fn child_count(&self) -> usize {
+        archetypes(|a| a.children_of_count(self.0))
+    }
+
+    pub fn nth_child(&self, n: usize) -> Option<Entity> {
+        archetypes(|a| a.nth_child_of(self.0, n))
     }
 
     pub fn remove(self) {
@@ -679,11 +954,57 @@ impl Entity {
     }
 
     pub fn instance_of(&self, prefab: Entity) -> Entity {
-        let entity = prefab.cloned();
-        entity.add_mixed_tag_rel::<InstanceOf>(prefab);
+        prefab.instantiate(&mut vec![])
+    }
+
+    //clones the prefab with its ChildOf subtree. Children that are instances of other prefabs
+    //are instantiated again from those prefabs, so they pick up their current state, and then
+    //get the components and overrides they added on top
+    fn instantiate(&self, prefabs: &mut Vec<Entity>) -> Entity {
+        assert!(
+            prefabs.iter().all(|p| p.0.low32() != self.0.low32()),
+            "prefab reference cycle through {}",
+            self.debug_name()
+        );
+        prefabs.push(*self);
+        let entity = self.cloned();
+        entity.add_mixed_tag_rel::<InstanceOf>(*self);
+        if entity.has_tag::<Prefab>() {
+            entity.remove_tag::<Prefab>();
+        }
+
+        let children: Vec<_> = self
+            .children_recursive()
+            .filter(|(_, depth)| depth.0 == 0)
+            .map(|(child, _)| child)
+            .collect();
+        for child in children {
+            let instance = match child.find_rel::<InstanceOf, Wildcard>() {
+                Some(rel) => {
+                    let instance = rel.target().instantiate(prefabs);
+                    archetypes_mut(|a| a.copy_instance_overrides(child.0, instance.0)).unwrap();
+                    instance
+                }
+                None => child.instantiate(prefabs),
+            };
+            if let Some(parent) = instance.parent() {
+                instance.remove_child_of(parent);
+            }
+            instance.add_child_of(entity);
+            if let Some(name) = child.get_name() {
+                instance.set_name(&name.get(|name| name.to_owned()));
+            }
+        }
+        prefabs.pop();
         entity
     }
 
+    //keeps the component's value when the instance is resynced with its prefab
+    pub fn mark_overridden<T: AbstractComponent>(&self) -> Entity {
+        let component = archetypes_mut(|archetypes| Entity(archetypes.component_id::<T>()));
+        self.add_mixed_tag_rel::<Overridden>(component)
+    }
+
     pub fn is_active(&self) -> bool {
         archetypes_mut(|archetypes| {
             let record: &Option<EntityRecord> = &archetypes.record_mut(self.0);
@@ -747,7 +1068,7 @@ mod tests {
     use crate::{
         query::QueryComoponentId,
         query_structs::{Not, With, WithRelation},
-        systems::{SystemStage, SystemsData},
+        systems::{AbstractSystemsWithStateData, SystemStage, SystemsData},
         world::World,
     };
 
@@ -843,7 +1164,6 @@ mod tests {
         fn send_event(world: &World) {
             world.send_event(MyEvent::new(15));
         }
-        //TODO: add global event reader
         world.add_event_type::<MyEvent>().add_systems(
             (read_event_before, send_event, read_event_after),
             SystemStage::Update,
@@ -853,6 +1173,108 @@ mod tests {
         world.run();
     }
 
+    #[test]
+    fn global_event_reader() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct MyEvent(i32);
+
+        let mut world = World::new();
+        world.add_event_type::<MyEvent>();
+        let reader = world.global_event_reader::<MyEvent>();
+        let frame = Rc::new(Cell::new(1));
+        let received = Rc::new(Cell::new(0));
+
+        world.add_systems(
+            {
+                let frame = frame.clone();
+                (move |world: &World| world.send_event(MyEvent(15)))
+                    .run_if(move |_: &World| frame.get() == 1)
+            },
+            SystemStage::Update,
+        );
+        world.add_systems(
+            {
+                let frame = frame.clone();
+                let received = received.clone();
+                (move |_: &World| {
+                    for event in reader.borrow().read() {
+                        received.set(received.get() + event.0);
+                    }
+                })
+                .run_if(move |_: &World| frame.get() > 1)
+            },
+            SystemStage::Update,
+        );
+
+        world.run();
+        assert_eq!(received.get(), 0);
+        frame.set(2);
+        world.run();
+        assert_eq!(received.get(), 15);
+        frame.set(3);
+        world.run();
+        assert_eq!(received.get(), 15);
+    }
+
+    #[test]
+    fn event_retention() {
+        struct MyEvent;
+
+        let mut world = World::new();
+        world.add_event_type::<MyEvent>();
+        let unread = |world: &World| {
+            world
+                .global_event_reader::<MyEvent>()
+                .borrow()
+                .read()
+                .count()
+        };
+
+        world.send_event(MyEvent);
+        assert_eq!(unread(&world), 1);
+        world.run();
+        assert_eq!(unread(&world), 1);
+        world.run();
+        assert_eq!(unread(&world), 0);
+    }
+
+    #[test]
+    fn iter_entities() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let first = world.add_entity().add_comp(Position { x: 1, y: 0 });
+        let second = world.add_entity().add_comp(Position { x: 2, y: 0 });
+        world.add_entity().add_comp(Velocity { x: 3, y: 0 });
+
+        let mut query = world.query::<&mut Position>().build();
+        let mut matched = vec![];
+        for (entity, mut position) in query.iter().enumerate_entities() {
+            position.x *= 10;
+            matched.push((entity.0.low32(), position.x));
+        }
+        matched.sort();
+        assert_eq!(matched, vec![(first.0.low32(), 10), (second.0.low32(), 20)]);
+        assert_eq!(query.iter_entities().count(), 2);
+    }
+
+    #[test]
+    fn query_len() {
+        let world = World::new();
+        world.register_components::<Position>();
+        let entities: Vec<_> = (0..4)
+            .map(|_| world.add_entity().add_comp(Position::default()))
+            .collect();
+        entities[0].diactivate();
+        entities[1].diactivate();
+
+        let mut query = world.query::<&Position>().build();
+        assert_eq!(query.len(), 4);
+        assert_eq!(query.count_active(), 2);
+        assert_eq!(query.count_active(), query.iter().count());
+    }
+
     #[test]
     pub fn resources() {
         #[derive(Debug)]
@@ -871,6 +1293,69 @@ mod tests {
         });
         world.resources::<(&ResourceOne, &mut ResourceTwo)>(|(r1, r2)| {});
     }
+
+    #[test]
+    fn optional_resources() {
+        struct Score(i32);
+
+        let mut world = World::new();
+        world.add_resource(Score(1));
+        world.get_resource_mut::<Score, _>(|score| score.unwrap().0 += 1);
+        assert_eq!(
+            world.get_resource::<Score, _>(|score| score.map(|s| s.0)),
+            Some(2)
+        );
+
+        world.add_systems(
+            |world: &World| {
+                world.remove_resource::<Score>();
+            },
+            SystemStage::Update,
+        );
+        world.run();
+        assert!(!world.has_resource::<Score>());
+        assert!(world.get_resource::<Score, _>(|score| score.is_none()));
+        assert!(world.get_resource_mut::<Score, _>(|score| score.is_none()));
+    }
+    #[test]
+    fn run_once() {
+        struct Runs(i32);
+
+        let mut world = World::new();
+        world.add_resource(Runs(0));
+        world.run_once(|world: &World| {
+            world.get_resource_mut::<Runs, _>(|runs| runs.unwrap().0 += 1);
+        });
+        world.run();
+        assert_eq!(world.get_resource::<Runs, _>(|runs| runs.unwrap().0), 1);
+
+        world.run_once(
+            (|world: &World| {
+                world.get_resource_mut::<Runs, _>(|runs| runs.unwrap().0 += 1);
+            })
+            .run_if(|_: &World| false),
+        );
+        assert_eq!(world.get_resource::<Runs, _>(|runs| runs.unwrap().0), 1);
+    }
+    #[test]
+    fn system_ordering() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut world = World::new();
+        let order = Rc::new(RefCell::new(vec![]));
+        let system = |name: &'static str| {
+            let order = order.clone();
+            move |_: &World| order.borrow_mut().push(name)
+        };
+
+        world.add_systems(system("d").after("b").after("c"), SystemStage::Update);
+        world.add_systems(system("c").labeled("c").after("a"), SystemStage::Update);
+        world.add_systems(system("b").labeled("b").after("a"), SystemStage::Update);
+        world.add_systems(system("a").labeled("a"), SystemStage::Update);
+        world.run();
+        assert_eq!(*order.borrow(), vec!["a", "c", "b", "d"]);
+    }
     #[test]
     pub fn ecs_ub_test() {
         impl_component! {
@@ -1291,6 +1776,77 @@ mod tests {
         world.run();
     }
 
+    #[test]
+    fn state_transitions() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        enum GameState {
+            InMainMenu,
+            InGame,
+        }
+        impl_system_states!(GameState);
+
+        let mut world = World::new();
+        let log = Rc::new(RefCell::new(vec![]));
+        let system = |name: &'static str| {
+            let log = log.clone();
+            move |_: &World| log.borrow_mut().push(name)
+        };
+        world
+            .add_systems_on_enter(GameState::InMainMenu, system("enter menu"))
+            .add_systems_on_exit(GameState::InMainMenu, system("exit menu"))
+            .add_systems_on_enter(GameState::InGame, system("enter game"))
+            .set_state(GameState::InMainMenu);
+
+        world.run();
+        world.run();
+        assert_eq!(*log.borrow(), vec!["enter menu"]);
+
+        world.set_state(GameState::InGame);
+        world.run();
+        world.set_state(GameState::InGame);
+        world.run();
+        assert_eq!(*log.borrow(), vec!["enter menu", "exit menu", "enter game"]);
+    }
+
+    #[test]
+    fn remove_system() {
+        use std::cell::{Cell, RefCell};
+        use std::rc::Rc;
+
+        let mut world = World::new();
+        let log = Rc::new(RefCell::new(vec![]));
+        let removed_id = Rc::new(Cell::new(None));
+        world
+            .add_systems(
+                {
+                    let log = log.clone();
+                    let removed_id = removed_id.clone();
+                    move |world: &World| {
+                        removed_id.set(world.current_system_id());
+                        log.borrow_mut().push("removed");
+                    }
+                },
+                SystemStage::Update,
+            )
+            .add_systems(
+                {
+                    let log = log.clone();
+                    move |_: &World| log.borrow_mut().push("kept")
+                },
+                SystemStage::Update,
+            );
+        world.run();
+
+        let id = removed_id.get().unwrap();
+        assert!(world.remove_system(id));
+        assert!(!world.remove_system(id));
+        log.borrow_mut().clear();
+        world.run();
+        assert_eq!(*log.borrow(), vec!["kept"]);
+    }
+
     #[test]
     fn children() {
         let world = World::new();
@@ -1327,6 +1883,59 @@ mod tests {
         assert!(!child2.is_alive());
     }
     #[test]
+    fn children_traversal_order() {
+        let world = World::new();
+        let root = world.add_entity();
+        let a = world.add_entity().add_child_of(root);
+        let b = world.add_entity().add_child_of(root);
+        let a1 = world.add_entity().add_child_of(a);
+        let b1 = world.add_entity().add_child_of(b);
+
+        let visit = |order| {
+            root.children_recursive_ordered(order)
+                .map(|(e, depth)| (e.0.low32(), depth.0))
+                .collect::<Vec<_>>()
+        };
+        let ids = |e: Entity, depth: u32| (e.0.low32(), depth);
+        assert_eq!(
+            visit(TraversalOrder::DepthFirst),
+            vec![ids(a, 0), ids(a1, 1), ids(b, 0), ids(b1, 1)]
+        );
+        assert_eq!(
+            visit(TraversalOrder::BreadthFirst),
+            vec![ids(a, 0), ids(b, 0), ids(a1, 1), ids(b1, 1)]
+        );
+    }
+    #[test]
+    fn child_count() {
+        let world = World::new();
+        world.register_components::<Position>();
+        let parent = world.add_entity();
+        assert_eq!(parent.child_count(), 0);
+        assert!(parent.nth_child(0).is_none());
+
+        let first = world.add_entity().add_child_of(parent);
+        let second = world
+            .add_entity()
+            .add_child_of(parent)
+            .add_comp(Position::default());
+        let grand_child = world.add_entity().add_child_of(first);
+        assert_eq!(parent.child_count(), 2);
+        assert_eq!(first.child_count(), 1);
+
+        let mut children = (0..parent.child_count())
+            .map(|n| parent.nth_child(n).unwrap().0.low32())
+            .collect::<Vec<_>>();
+        children.sort();
+        assert_eq!(children, vec![first.0.low32(), second.0.low32()]);
+
+        assert!(parent.nth_child(2).is_none());
+        assert_eq!(first.nth_child(0).unwrap().0.low32(), grand_child.0.low32());
+
+        second.remove();
+        assert_eq!(parent.child_count(), 1);
+    }
+    #[test]
     fn find_relationships() {
         let world = World::new();
         world.register_components::<(Velocity, Position, IsCool, Likes, Apples, Owes, Begin)>();
@@ -1478,410 +2087,3269 @@ mod tests {
     }
 
     #[test]
-    fn filtered_queries() {
+    fn or_queries() {
+        use crate::query_structs::Or;
+
         let world = World::new();
-        world.register_components::<(Position, IsCool, Velocity, Likes, Apples)>();
-        let e1 = world.add_entity().add_comp(Position { x: 1, y: 2 });
-        e1.add_comp(Velocity { x: 1, y: 0 }).add_tag::<IsCool>();
+        world.register_components::<(Position, IsCool, Apples, Oranges)>();
         world
             .add_entity()
-            .add_comp(Position { x: 3, y: 4 })
-            .add_rel::<Likes, Apples>();
+            .add_comp(Position { x: 1, y: 0 })
+            .add_tag::<IsCool>();
+        world
+            .add_entity()
+            .add_comp(Position { x: 10, y: 0 })
+            .add_tag::<Apples>();
+        world
+            .add_entity()
+            .add_comp(Position { x: 100, y: 0 })
+            .add_tag::<IsCool>()
+            .add_tag::<Apples>();
+        world
+            .add_entity()
+            .add_comp(Position { x: 1000, y: 0 })
+            .add_tag::<Oranges>();
 
         let mut query = world
-            .query_filtered::<&Position, WithRelation<Wildcard, Apples>>()
+            .query_filtered::<&Position, Or<(With<IsCool>, With<Apples>)>>()
             .build();
+        let sum: i32 = query.iter().map(|p| p.x).sum();
+        assert_eq!(sum, 111);
 
-        let sum: i32 = query.iter().map(|p| p.x + p.y).sum();
-        assert_eq!(sum, 7);
+        let mut query = world
+            .query_filtered::<&Position, Not<Or<(With<IsCool>, With<Apples>)>>>()
+            .build();
+        let sum: i32 = query.iter().map(|p| p.x).sum();
+        assert_eq!(sum, 1000);
+
+        let mut query = world
+            .query_filtered::<&Position, (With<Oranges>, Or<(With<IsCool>, Not<With<Apples>>)>)>()
+            .build();
+        let sum: i32 = query.iter().map(|p| p.x).sum();
+        assert_eq!(sum, 1000);
+
+        let first = world
+            .query_filtered::<&Position, Or<(With<IsCool>, With<Apples>)>>()
+            .build();
+        let second = world
+            .query_filtered::<&Position, Or<(With<IsCool>, With<Apples>)>>()
+            .build();
+        assert!(std::rc::Rc::ptr_eq(&first.storage, &second.storage));
     }
+
     #[test]
-    fn queries() {
+    fn change_detection() {
+        use crate::query_structs::{Added, Changed};
+
         let world = World::new();
         world.register_components::<(Position, Velocity)>();
-        let e1 = world.add_entity().add_comp(Position { x: 1, y: 2 });
-        e1.add_comp(Velocity { x: 1, y: 0 });
-        let e2 = world.add_entity().add_comp(Position { x: 3, y: 4 });
-
-        let mut query_ref = world.query::<&Position>().build();
-        let mut counter = 0;
-        for pos in query_ref.iter() {
-            counter += pos.x + pos.y;
+        let first = world.add_entity().add_comp(Position { x: 0, y: 0 });
+        world.add_entity().add_comp(Position { x: 0, y: 0 });
+
+        let changed = || {
+            world
+                .query_filtered::<&Entity, Changed<Position>>()
+                .build()
+                .iter()
+                .map(|e| e.0.low32())
+                .collect::<Vec<_>>()
+        };
+        let added = || {
+            world
+                .query_filtered::<&Entity, Added<Position>>()
+                .build()
+                .iter()
+                .count()
+        };
+        assert_eq!(changed().len(), 2);
+        assert_eq!(added(), 2);
+        assert!(changed().is_empty());
+        assert_eq!(added(), 0);
+
+        first.comp_mut::<Position>(|p| p.x = 1);
+        first.comp::<Position>(|p| assert_eq!(p.x, 1));
+        assert_eq!(changed(), [first.0.low32()]);
+        assert_eq!(added(), 0);
+
+        //reading through a mutable query term doesn't count as a change
+        for position in world.query::<&mut Position>().build().iter() {
+            assert!(position.y == 0);
         }
-        assert_eq!(counter, 10);
-
-        let mut query_mut = world.query::<(&mut Position, &Entity)>().build();
-        for (mut pos, _) in query_mut.iter() {
-            pos.x = 10;
-            pos.y = 1;
+        assert!(changed().is_empty());
+        for mut position in world.query::<&mut Position>().build().iter() {
+            position.y = 1;
         }
+        assert_eq!(changed().len(), 2);
 
-        let pos_sum_1 = e1.comp_ret(|p: &Position| p.x + p.y);
-        let pos_sum_2 = e2.comp_ret(|p: &Position| p.x + p.y);
-        assert_eq!(pos_sum_1 + pos_sum_2, 22);
+        let third = world.add_entity().add_comp(Position { x: 0, y: 0 });
+        third.add_comp(Velocity { x: 0, y: 0 });
+        assert_eq!(added(), 1);
+        assert_eq!(changed(), [third.0.low32()]);
+    }
 
-        let combined_query = world.query::<(&Position, &Velocity)>();
-        let sum: i32 = combined_query
+    #[test]
+    fn world_serialization() {
+        let world = World::new();
+        world.register_components::<(Position, Owes, IsCool)>();
+        let bob = world
+            .add_entity_named("Bob")
+            .add_comp(Position { x: 1, y: 2 });
+        let alice = world
+            .add_entity()
+            .add_tag::<IsCool>()
+            .add_child_of(bob)
+            .set_name("Alice")
+            .add_mixed_rel(bob, Owes { amount: 10 });
+        world.add_entity().add_ent_tag(alice);
+
+        let json = world.serialize_world();
+        bob.remove();
+        assert!(!alice.is_alive());
+        let entities = world.deserialize_world(&json).unwrap();
+        assert_eq!(entities.len(), 3);
+
+        let bob = world.entity_by_global_name("Bob").unwrap();
+        assert_eq!(bob.get_comp_ret::<Position, _>(|p| p.unwrap().y), 2);
+        let alice = world.entity_by_path("Bob/Alice").unwrap();
+        assert!(alice.is_child_of(bob));
+        assert!(alice.has_tag::<IsCool>());
+        assert_eq!(alice.mixed_rel::<Owes>(bob).get(|owes| owes.amount), 10);
+        let tagged = world
+            .query::<&Entity>()
+            .with_ent_tag(alice)
             .build()
             .iter()
-            .map(|(pos, vel)| vel.x + vel.y + pos.x + pos.y)
-            .sum();
-        assert_eq!(sum, 12);
-        let mut count = 0;
-        let mut sum = 0;
-        let mut optional_query = world.query::<(&Position, Option<&Velocity>)>().build();
-        for (pos, vel) in optional_query.iter() {
-            count += 1;
-            let vel = vel.map(|v| *v).unwrap_or(Velocity { x: 0, y: 0 });
-            sum += pos.x + pos.y + vel.x + vel.y;
+            .count();
+        assert_eq!(tagged, 1);
+    }
+
+    #[test]
+    fn binary_snapshot() {
+        let world = World::new().register_sparse_component::<Velocity>();
+        world.register_components::<(Position, Owes, IsCool)>();
+        let bob = world
+            .add_entity_named("Bob")
+            .add_comp(Position { x: -3, y: 7 });
+        world.add_entity_named("Arm").add_child_of(bob);
+        world
+            .add_entity_named("Alice")
+            .add_mixed_rel(bob, Owes { amount: 5 })
+            .add_tag::<IsCool>()
+            .add_comp(Velocity::new(1, 2));
+
+        let bytes = world.save_binary();
+        assert!(bytes.len() < world.serialize_world().len());
+        assert!(world.load_binary(&bytes[..bytes.len() - 1]).is_err());
+
+        //ids are remapped, so the components can be registered in another order
+        let other = World::new().register_sparse_component::<Velocity>();
+        other.register_components::<(IsCool, Owes, Position)>();
+        other.add_entity();
+        other.load_binary(&bytes).unwrap();
+        let bob = other.entity_by_global_name("Bob").unwrap();
+        let alice = other.entity_by_global_name("Alice").unwrap();
+        assert_eq!(bob.get_comp_ret::<Position, _>(|p| p.unwrap().x), -3);
+        assert_eq!(alice.mixed_rel::<Owes>(bob).get(|owes| owes.amount), 5);
+        assert!(alice.has_tag::<IsCool>());
+        alice.comp::<Velocity>(|vel| assert_eq!(vel.y, 2));
+        let arm = other.entity_by_path("Bob/Arm").unwrap();
+        assert!(arm.is_child_of(bob));
+    }
+
+    //run with --ignored --nocapture to compare with the json path
+    #[test]
+    #[ignore]
+    fn binary_snapshot_benchmark() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity, IsCool)>();
+        for i in 0..10_000 {
+            let entity = world.add_entity().add_comp(Position::new(i, i));
+            if i % 2 == 0 {
+                entity.add_comp(Velocity::new(1, 1)).add_tag::<IsCool>();
+            }
         }
-        assert_eq!(count, 2);
-        assert_eq!(sum, 23);
+
+        let start = std::time::Instant::now();
+        let json = world.serialize_world();
+        world.deserialize_world(&json).unwrap();
+        println!("json: {:?}, {} bytes", start.elapsed(), json.len());
+
+        let start = std::time::Instant::now();
+        let bytes = world.save_binary();
+        world.load_binary(&bytes).unwrap();
+        println!("binary: {:?}, {} bytes", start.elapsed(), bytes.len());
     }
 
     #[test]
-    fn prefab() {
+    fn relationship_name() {
         let world = World::new();
-        world.register_components::<(Velocity, Likes, Oranges)>();
-        let prefab = world
-            .add_prefab()
-            .add_comp(Velocity { x: 10, y: 20 })
-            .add_rel::<Likes, Oranges>();
+        world.register_components::<(Likes, Apples)>();
+        let bob = world.add_entity_named("Bob");
+        let (typed, mixed) = archetypes_mut(|a| {
+            let typed = a.relationship_id_typed::<Likes, Apples>();
+            let likes = a.component_id::<Likes>();
+            (typed, Archetypes::relationship_id(likes, bob.0))
+        });
 
-        let instance = world.add_entity().instance_of(prefab);
-        assert!(instance.has_mixed_rel::<InstanceOf>(prefab));
-        instance.comp::<Velocity>(|p| {
-            assert_eq!(p.x, 10);
-            assert_eq!(p.y, 20);
+        archetypes(|a| {
+            assert_eq!(
+                a.relationship_name(typed).as_deref(),
+                Some("(Likes, Apples)")
+            );
+            assert_eq!(a.relationship_name(mixed).as_deref(), Some("(Likes, Bob)"));
         });
-        assert!(instance.has_rel::<Likes, Oranges>());
+        bob.remove();
+        archetypes(|a| assert_eq!(a.relationship_name(mixed), None));
     }
 
     #[test]
-    fn everything_at_once_cloned() {
+    fn exclusive_relations() {
         let world = World::new();
-        world.register_components::<(Velocity, Position, IsCool, Likes, Apples, Owes, Begin)>();
-        let bob = world.add_entity();
-        let hates = world.add_entity();
-        let idkwhat = world.add_entity();
-        let entity_tag = world.add_entity();
+        world.register_components::<(Likes, Apples, Oranges)>();
+        let first = world.add_entity();
+        let second = world.add_entity();
+        let child = world.add_entity().add_child_of(first).add_child_of(second);
+        assert!(!child.is_child_of(first));
+        assert!(child.is_child_of(second));
+        assert_eq!(child.find_rels::<ChildOf, Wildcard>().count(), 1);
+
+        world.register_exclusive_relation::<Likes>();
         let entity = world
             .add_entity()
-            .add_comp(Position { x: 10, y: 20 })
-            .add_comp(Position::new(3, 4))
-            .add_tag::<IsCool>()
-            .add_ent_tag(entity_tag)
-            .add_ent_rel(hates, bob)
             .add_rel::<Likes, Apples>()
-            .add_rel_first::<Owes, Apples>(Owes { amount: 10 })
-            .add_rel_second::<Begin, Position>(Position::new(1, 2))
-            .add_mixed_rel(idkwhat, Position::new(5, 6));
+            .add_rel::<Likes, Oranges>();
+        assert!(!entity.has_rel::<Likes, Apples>());
+        assert!(entity.has_rel::<Likes, Oranges>());
+    }
 
-        let entity = entity.cloned();
+    #[test]
+    fn clear() {
+        use std::cell::Cell;
+        use std::rc::Rc;
 
-        assert!(entity.has_comp::<Position>());
-        assert!(entity.has_tag::<IsCool>());
-        assert!(entity.has_ent_tag(entity_tag));
-        assert!(entity.has_rel::<Likes, Apples>());
-        assert!(entity.has_rel::<Owes, Apples>());
-        assert!(entity.has_rel::<Begin, Position>());
-        assert!(entity.has_ent_rel(hates, bob));
-        assert!(entity.has_mixed_rel::<Position>(idkwhat));
+        let world = World::new();
+        world.register_components::<(Position, Velocity, IsCool, Likes)>();
+        let removed = Rc::new(Cell::new(0));
+        world.on_comp_remove::<Position>({
+            let removed = removed.clone();
+            move |_, _| removed.set(removed.get() + 1)
+        });
+        let parent = world.add_entity_named("parent");
+        let friend = world.add_entity();
+        let entity = world
+            .add_entity()
+            .add_comp(Position::new(1, 2))
+            .add_comp(Velocity::new(3, 4))
+            .add_tag::<IsCool>()
+            .add_mixed_tag_rel::<Likes>(friend)
+            .add_child_of(parent)
+            .set_name("entity");
+        let child = world.add_entity().add_child_of(entity);
+        assert!(world.entity_by_path("parent/entity").is_some());
+
+        entity.clear();
+        assert!(entity.is_alive());
+        assert!(entity.component_ids().is_empty());
+        assert!(!entity.has_name());
+        assert!(!parent.has_children());
+        assert!(world.entity_by_path("parent/entity").is_none());
+        assert_eq!(removed.get(), 1);
+        assert!(child.is_alive());
+        assert!(child.is_child_of(entity));
+
+        entity.add_comp(Position::new(5, 6));
+        entity.comp::<Position>(|pos| assert_eq!(pos.x, 5));
+    }
+
+    #[test]
+    fn entity_from_raw() {
+        let world = World::new();
+        let entity = world.add_entity();
+        let raw = u64::from(entity.0);
+        assert_eq!(world.entity_from_raw(raw), Some(entity));
+
+        entity.remove();
+        assert!(world.entity_from_raw(raw).is_none());
+        let recycled = world.add_entity();
+        assert_eq!(recycled.0.low32(), entity.0.low32());
+        assert!(world.entity_from_raw(raw).is_none());
+        assert_eq!(world.entity_from_raw(recycled.0.into()), Some(recycled));
+    }
+
+    #[test]
+    fn optional_rel_getters() {
+        let world = World::new();
+        world.register_components::<(Owes, Apples, Oranges, Begin, End, Position)>();
+        let entity = world
+            .add_entity()
+            .add_rel_first::<Owes, Apples>(Owes { amount: 10 })
+            .add_rel_second::<Begin, Position>(Position::new(1, 2));
+
+        assert_eq!(
+            entity.get_rel_first_opt::<Owes, Apples, _>(|owes| owes.amount),
+            Some(10)
+        );
+        assert_eq!(
+            entity.get_rel_second_opt::<Begin, Position, _>(|pos| pos.y),
+            Some(2)
+        );
+        assert_eq!(
+            entity.get_rel_first_opt::<Owes, Oranges, _>(|owes| owes.amount),
+            None
+        );
+        assert_eq!(
+            entity.get_rel_second_opt::<End, Position, _>(|pos| pos.y),
+            None
+        );
+    }
 
+    #[test]
+    fn add_comp_by_name() {
+        let world = World::new();
+        world.register_components::<(Position, IsCool)>();
+        let entity = world.add_entity();
+        entity
+            .add_comp_by_name("Position", serde_json::json!({"x": 1, "y": 2}))
+            .unwrap();
         entity.comp::<Position>(|pos| {
-            assert_eq!(pos.x, 3);
-            assert_eq!(pos.y, 4);
-        });
-        // entity.rel_first::<Owes, Begin>().try_get(|stuff| {});
-        entity.rel_first::<Owes, Apples>().get(|ows| {
-            assert_eq!(ows.amount, 10);
-        });
-        entity.rel_second::<Begin, Position>().get(|pos| {
             assert_eq!(pos.x, 1);
             assert_eq!(pos.y, 2);
         });
-        entity.mixed_rel::<Position>(idkwhat).get(|pos| {
-            assert_eq!(pos.x, 5);
-            assert_eq!(pos.y, 6);
+
+        world
+            .add_comp_by_name(entity, "Position", serde_json::json!({"x": 3}))
+            .unwrap();
+        entity.comp::<Position>(|pos| assert_eq!((pos.x, pos.y), (3, 0)));
+        assert!(entity
+            .add_comp_by_name("Acceleration", serde_json::json!({}))
+            .is_err());
+        assert!(entity
+            .add_comp_by_name("IsCool", serde_json::json!(null))
+            .is_err());
+        assert!(entity
+            .add_comp_by_name("Position", serde_json::json!("not a position"))
+            .is_err());
+    }
+
+    #[test]
+    fn spawn_default_by_name() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity, IsCool)>();
+        let entity = world.spawn_default_by_name("Position").unwrap();
+        entity.comp::<Position>(|pos| assert_eq!((pos.x, pos.y), (0, 0)));
+
+        assert!(world.spawn_default_by_name("Velocity").is_err());
+        assert!(world.spawn_default_by_name("IsCool").is_err());
+        assert!(world.spawn_default_by_name("Acceleration").is_err());
+    }
+
+    #[test]
+    fn patch_comp_by_name() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let entity = world.add_entity().add_comp(Position::new(1, 2));
+
+        let mut patch = DynamicStruct::default();
+        patch.insert("y", 10);
+        entity.patch_comp_by_name("Position", &patch).unwrap();
+        entity.comp::<Position>(|pos| {
+            assert_eq!(pos.x, 1);
+            assert_eq!(pos.y, 10);
         });
-        entity.remove_comp::<Position>();
-        assert!(!entity.has_comp::<Position>());
-        entity.remove_tag::<IsCool>();
-        assert!(!entity.has_tag::<IsCool>());
-        entity.remove_ent_tag(entity_tag);
-        assert!(!entity.has_ent_tag(entity_tag));
-        entity.remove_ent_rel(hates, bob);
-        assert!(!entity.has_ent_rel(hates, bob));
-        entity.remove_rel::<Likes, Apples>();
-        assert!(!entity.has_rel::<Likes, Apples>());
-        entity.remove_rel::<Owes, Apples>();
-        assert!(!entity.has_rel::<Owes, Apples>());
-        entity.remove_rel::<Begin, Position>();
-        assert!(!entity.has_rel::<Begin, Position>());
-        entity.remove_mixed_rel::<Position>(idkwhat);
-        assert!(!entity.has_mixed_rel::<Position>(idkwhat));
-        entity.remove_mixed_rel::<Likes>(bob);
-        assert!(!entity.has_mixed_rel::<Likes>(bob));
+
+        assert!(matches!(
+            entity.patch_comp_by_name("Velocity", &patch),
+            Err(PatchError::Missing(..))
+        ));
+        assert!(matches!(
+            entity.patch_comp_by_name("Acceleration", &patch),
+            Err(PatchError::UnknownComponent(..))
+        ));
+        let mut mismatched = DynamicStruct::default();
+        mismatched.insert("x", 1.5f32);
+        assert!(matches!(
+            entity.patch_comp_by_name("Position", &mismatched),
+            Err(PatchError::Apply(..))
+        ));
+        entity.comp::<Position>(|pos| assert_eq!(pos.x, 1));
     }
 
     #[test]
-    fn wildcard() {
+    fn set_name_renames() {
         let world = World::new();
-        world.register_components::<(Likes, Apples)>();
-        let entity = world.add_entity();
-        entity.add_rel::<Likes, Apples>();
-        assert!(entity.has_rel::<Likes, Wildcard>());
-        assert!(entity.has_rel::<Wildcard, Apples>());
+        let entity = world.add_entity().set_name("first");
+        entity.set_name("second");
+        entity.set_name("third");
+        entity.set_name("third");
+        assert!(entity.name().get(|name| name == "third"));
+        assert!(world.entity_by_global_name("first").is_none());
+        assert!(world.entity_by_global_name("second").is_none());
 
-        entity.remove_rel::<Likes, Apples>();
-        let is = world.add_entity();
-        let helicopter = world.add_entity();
+        let other = world.add_entity().set_name("first");
+        assert_eq!(world.entity_by_global_name("first"), Some(other));
+        assert_eq!(world.entity_by_global_name("third"), Some(entity));
+        assert!(other.try_set_name("third").is_err());
+        assert!(other.name().get(|name| name == "first"));
+    }
 
-        entity.add_ent_rel(is, helicopter);
-        assert!(entity.has_ent_rel(WILDCARD, helicopter));
-        assert!(entity.has_ent_rel(is, WILDCARD));
+    #[test]
+    fn find_sibling() {
+        let world = World::new();
+        let parent = world.add_entity_named("Parent");
+        let left = world.add_entity().add_child_of(parent).set_name("Left");
+        let right = world.add_entity().add_child_of(parent).set_name("Right");
+        let global = world.add_entity_named("Global");
+
+        assert_eq!(left.find_sibling("Right"), Some(right));
+        assert_eq!(right.find_sibling("Left"), Some(left));
+        assert_eq!(parent.find_child_named("Left"), Some(left));
+        assert_eq!(parent.find_child_named("Right"), Some(right));
+        assert!(left.find_sibling("Global").is_none());
+        assert!(parent.find_child_named("Global").is_none());
+        assert_eq!(parent.find_sibling("Global"), Some(global));
     }
+
     #[test]
-    fn everything_at_once() {
+    fn search_entities() {
+        use crate::world::archetypes;
+
         let world = World::new();
-        world.register_components::<(Velocity, Position, IsCool, Likes, Apples, Owes, Begin)>();
-        let bob = world.add_entity();
-        let hates = world.add_entity();
-        let idkwhat = world.add_entity();
-        let entity_tag = world.add_entity();
+        let player = world.add_entity_named("Player");
+        let player_camera = world.add_entity_named("PlayerCamera");
+        let enemy = world.add_entity_named("EnemyPlayerProxy");
+        world.add_entity_named("Tree");
+        let removed = world.add_entity_named("PlayerGhost");
+        removed.remove();
+
+        let prefixed = archetypes(|a| a.find_entities_by_name_prefix("Player"));
+        assert_eq!(prefixed, vec![player.0, player_camera.0]);
+        assert_eq!(
+            world.search_entities("player"),
+            vec![player, player_camera, enemy]
+        );
+        assert_eq!(world.search_entities("CAMERA"), vec![player_camera]);
+        assert!(world.search_entities("rock").is_empty());
+    }
 
+    #[test]
+    fn query_sorted_by() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let mut seed = 12345u32;
+        for _ in 0..64 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let x = (seed >> 16) as i32 % 1000;
+            world.add_entity().add_comp(Position::new(x, 0));
+        }
         world
             .add_entity()
-            .add_comp::<Velocity>(Velocity { x: 1, y: 2 });
-        let mut entity = None;
-        //also testing adding stuff inside a query
-        for _ in world.empty_query().with_comp::<Velocity>().build().iter() {
-            entity = Some(
-                world
-                    .add_entity()
-                    .add_comp(Position::new(3, 4))
-                    .add_tag::<IsCool>()
-                    .add_ent_tag(entity_tag)
-                    .add_ent_rel(hates, bob)
-                    .add_rel::<Likes, Apples>()
-                    .add_rel_first::<Owes, Apples>(Owes { amount: 10 })
-                    .add_rel_second::<Begin, Position>(Position::new(1, 2))
-                    .add_mixed_rel(idkwhat, Position::new(5, 6))
-                    .add_mixed_tag_rel::<Likes>(bob),
-            )
-        }
+            .add_comp(Position::new(-1, 0))
+            .diactivate();
 
-        let entity = entity.unwrap();
+        let mut query = world.query::<&Position>().build();
+        let xs: Vec<_> = query.sorted_by(|pos| pos.x).map(|pos| pos.x).collect();
+        assert_eq!(xs.len(), 64);
+        assert!(xs.windows(2).all(|pair| pair[0] <= pair[1]));
 
-        assert!(entity.has_comp::<Position>());
-        assert!(entity.has_tag::<IsCool>());
-        assert!(entity.has_ent_tag(entity_tag));
-        assert!(entity.has_rel::<Likes, Apples>());
-        assert!(entity.has_rel::<Owes, Apples>());
+        let mut query = world.query::<&mut Position>().build();
+        for mut pos in query.sorted_by(|pos| std::cmp::Reverse(pos.x)) {
+            pos.y = 1;
+        }
+        assert!(world
+            .query::<&Position>()
+            .build()
+            .iter()
+            .all(|pos| pos.y == 1));
+    }
+
+    #[test]
+    fn query_pairs() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let entities: Vec<_> = (0..4)
+            .map(|x| world.add_entity().add_comp(Position::new(x, 0)))
+            .collect();
+        world.add_entity().add_comp(Velocity::new(0, 0));
+
+        let mut query = world.query::<&Position>().build();
+        let pairs: Vec<_> = query
+            .pairs()
+            .map(|((a, pos_a), (b, pos_b))| {
+                assert_ne!(a.0.low32(), b.0.low32());
+                (pos_a.x, pos_b.x)
+            })
+            .collect();
+        assert_eq!(pairs.len(), 6);
+        assert!(pairs.iter().all(|(a, b)| a < b));
+
+        entities[0].remove();
+        assert_eq!(query.pairs().count(), 3);
+    }
+
+    #[test]
+    fn query_cached() {
+        use std::rc::Rc;
+
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        world.add_entity().add_comp(Position::new(1, 0));
+
+        let query = world.query_cached::<&Position, ()>();
+        assert!(Rc::ptr_eq(&query, &world.query_cached::<&Position, ()>()));
+        assert_eq!(query.borrow_mut().iter().count(), 1);
+
+        //archetypes created after the query was cached are still matched
+        world
+            .add_entity()
+            .add_comp(Position::new(2, 0))
+            .add_comp(Velocity::new(0, 0));
+        let sum: i32 = world
+            .query_cached::<&Position, ()>()
+            .borrow_mut()
+            .iter()
+            .map(|pos| pos.x)
+            .sum();
+        assert_eq!(sum, 3);
+    }
+
+    #[test]
+    #[ignore]
+    fn query_cached_benchmark() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        for i in 0..100 {
+            world
+                .add_entity()
+                .add_comp(Position::new(i, i))
+                .add_comp(Velocity::new(1, 1));
+        }
+
+        let start = std::time::Instant::now();
+        for _ in 0..10_000 {
+            let mut query = world.query::<(&mut Position, &Velocity)>().build();
+            for (mut pos, vel) in query.iter() {
+                pos.x += vel.x;
+            }
+        }
+        println!("built every run: {:?}", start.elapsed());
+
+        let start = std::time::Instant::now();
+        for _ in 0..10_000 {
+            let query = world.query_cached::<(&mut Position, &Velocity), ()>();
+            for (mut pos, vel) in query.borrow_mut().iter() {
+                pos.x += vel.x;
+            }
+        }
+        println!("cached: {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn enum_tag_ids() {
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum Mood {
+                Happy,
+                Grumpy,
+            }
+        }
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum Stance {
+                Standing,
+                Crouching,
+            }
+        }
+
+        let world = World::new();
+        world.register_components::<(Mood, Stance)>();
+        let entity = world
+            .add_entity()
+            .add_enum_tag(Mood::Grumpy)
+            .add_enum_tag(Stance::Crouching);
+        assert_eq!(world.add_entity().enum_tags().count(), 0);
+
+        let mut tags: Vec<_> = entity
+            .enum_tags()
+            .map(|(type_id, variant)| {
+                let type_name = world.type_name(type_id).unwrap();
+                let type_name = type_name.rsplit("::").next().unwrap().to_string();
+                (type_name, variant)
+            })
+            .collect();
+        tags.sort();
+        assert_eq!(
+            tags,
+            [
+                ("Mood".to_string(), Mood::Grumpy.id()),
+                ("Stance".to_string(), Stance::Crouching.id())
+            ]
+        );
+    }
+
+    #[test]
+    fn relationship_is_tag() {
+        let world = World::new();
+        world.register_components::<(Likes, Begin, Owes, Apples, Position)>();
+        let friend = world.add_entity_named("Friend");
+        let entity = world
+            .add_entity()
+            .add_mixed_tag_rel::<Likes>(friend)
+            .add_rel_second::<Begin, Position>(Position::new(1, 2))
+            .add_rel_first::<Owes, Apples>(Owes { amount: 3 });
+
+        let mut tags = vec![];
+        let mut data = vec![];
+        for rel in entity.rels() {
+            let relation = rel.relation().debug_name();
+            if rel.is_tag() {
+                tags.push((relation, rel.target().debug_name()));
+            } else {
+                data.push(relation);
+            }
+        }
+        data.sort();
+        assert_eq!(tags, [("Likes".into(), "Friend".into())]);
+        assert_eq!(data, ["Begin", "Owes"]);
+    }
+
+    #[test]
+    fn on_target_delete() {
+        use crate::archetypes::OnTargetDelete;
+
+        let world = World::new();
+        world.register_components::<(Likes, Begin)>();
+        world.set_on_target_delete::<Likes>(OnTargetDelete::Delete);
+        let target = world.add_entity();
+        let fan = world.add_entity().add_mixed_tag_rel::<Likes>(target);
+        let other_fan = world
+            .add_entity()
+            .add_mixed_tag_rel::<Begin>(target)
+            .add_mixed_tag_rel::<Likes>(target);
+        let fan_child = world.add_entity().add_child_of(fan);
+        let holder = world.add_entity().add_mixed_tag_rel::<Begin>(target);
+
+        target.remove();
+        assert!(!fan.is_alive());
+        assert!(!other_fan.is_alive());
+        assert!(!fan_child.is_alive());
+        assert!(holder.is_alive());
+        assert_eq!(holder.rels().count(), 0);
+    }
+
+    #[test]
+    fn relationship_alive() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<(Owes, Likes)>();
+        let target = world.add_entity();
+        let entity = world
+            .add_entity()
+            .add_mixed_rel::<Owes>(target, Owes { amount: 5 });
+        let relationship = Relationship::new_mixed::<Owes>(target).id();
+        let wildcard = Relationship::new_mixed::<Owes>(WILDCARD).id();
+        assert!(archetypes(|a| a.is_entity_alive(relationship)));
+
+        target.remove();
+        assert!(!archetypes(|a| a.is_entity_alive(relationship)));
+        assert!(archetypes(|a| a.is_entity_alive(wildcard)));
+        assert!(!entity.has_mixed_rel::<Owes>(target));
+    }
+
+    #[test]
+    fn flush() {
+        use crate::world::{archetypes, archetypes_mut};
+
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let entity = world.add_entity().add_comp(Velocity::new(1, 1));
+
+        archetypes_mut(|a| {
+            a.lock();
+            a.lock();
+        });
+        entity.add_comp(Position::new(1, 2));
+        entity.remove_comp::<Velocity>();
+        assert!(!entity.has_comp::<Position>());
+        assert!(entity.has_comp::<Velocity>());
+
+        world.flush().unwrap();
+        assert!(!archetypes(|a| a.is_locked()));
+        assert!(entity.has_comp::<Position>());
+        assert!(!entity.has_comp::<Velocity>());
+        entity.comp::<Position>(|pos| assert_eq!(pos.y, 2));
+
+        world.flush().unwrap();
+        assert!(!archetypes(|a| a.is_locked()));
+
+        for _ in world.query::<&Position>().build().iter() {
+            entity.remove_comp::<Position>();
+            assert!(world.flush().is_err());
+            assert!(entity.has_comp::<Position>());
+        }
+        assert!(!entity.has_comp::<Position>());
+    }
+
+    #[test]
+    fn unlock_without_lock() {
+        use crate::world::{archetypes, archetypes_mut};
+
+        let world = World::new();
+        world.register_components::<(Position,)>();
+        archetypes_mut(|a| a.unlock());
+        assert!(!archetypes(|a| a.is_locked()));
+
+        archetypes_mut(|a| {
+            a.lock();
+            a.unlock();
+            a.unlock();
+            a.lock();
+        });
+        let entity = world.add_entity().add_comp(Position::new(1, 2));
+        assert!(archetypes(|a| a.is_locked()));
+        assert!(!entity.has_comp::<Position>());
+        archetypes_mut(|a| a.unlock());
+        assert!(entity.has_comp::<Position>());
+    }
+
+    #[test]
+    fn toggle_tag() {
+        let world = World::new();
+        world.register_components::<(Position, IsCool)>();
+        let entity = world.add_entity().add_comp(Position::new(1, 2));
+
+        entity.toggle_tag::<IsCool>();
+        assert!(entity.has_tag::<IsCool>());
+        entity.toggle_tag::<IsCool>();
+        assert!(!entity.has_tag::<IsCool>());
+
+        entity.set_tag::<IsCool>(true).set_tag::<IsCool>(true);
+        assert!(entity.has_tag::<IsCool>());
+        assert!(entity.has_comp::<Position>());
+        entity.set_tag::<IsCool>(false);
+        assert!(!entity.has_tag::<IsCool>());
+    }
+
+    #[test]
+    fn take_entity_serialized() {
+        let world = World::new();
+        world.register_components::<(Position, IsCool)>();
+        let entity = world
+            .add_entity()
+            .add_comp(Position::new(1, 2))
+            .add_tag::<IsCool>();
+
+        let json = world.take_entity_serialized(entity).unwrap();
+        assert!(!entity.is_alive());
+        assert!(world.take_entity_serialized(entity).is_none());
+
+        let spawned = world.deserialize_entity(&json).unwrap();
+        assert!(spawned.has_tag::<IsCool>());
+        spawned.comp::<Position>(|pos| assert_eq!((pos.x, pos.y), (1, 2)));
+    }
+
+    #[test]
+    fn transfer_between_worlds() {
+        use crate::{entity_parser::ParseError, world::transfer};
+
+        let first = World::new();
+        first.register_components::<(Position, IsCool)>();
+        let entity = first
+            .add_entity()
+            .add_comp(Position::new(1, 2))
+            .add_tag::<IsCool>();
+        let second = World::new();
+        second.register_components::<(Position, IsCool)>();
+        let third = World::new();
+        let third_count = third.entity_count();
+
+        let err = transfer(&first, &third, entity).unwrap_err();
+        match err.downcast_ref::<ParseError>() {
+            Some(ParseError::UnregisteredTypes(names)) => {
+                let mut names = names.clone();
+                names.sort();
+                assert_eq!(names, ["IsCool", "Position"]);
+            }
+            _ => panic!("expected unregistered types, got {err}"),
+        }
+        assert!(first.scope(|| entity.is_alive()));
+        assert_eq!(third.scope(|| third.entity_count()), third_count);
+
+        let moved = transfer(&first, &second, entity).unwrap();
+        assert!(!first.scope(|| entity.is_alive()));
+        second.scope(|| {
+            assert!(moved.has_tag::<IsCool>());
+            moved.comp::<Position>(|pos| assert_eq!((pos.x, pos.y), (1, 2)));
+        });
+        assert!(transfer(&first, &second, entity).is_err());
+    }
+
+    #[test]
+    fn add_callbacks_get_the_active_world() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let first = World::new();
+        first.register_components::<Position>();
+        let seen = Rc::new(Cell::new(false));
+        first.on_comp_add::<Position>({
+            let seen = seen.clone();
+            move |entity, world| {
+                assert!(world.is_active());
+                seen.set(world.scope(|| entity.has_comp::<Position>()));
+            }
+        });
+        let _second = World::new();
+        first.scope(|| first.add_entity().add_comp(Position::new(1, 2)));
+        assert!(seen.get());
+    }
+
+    #[test]
+    fn world_scope() {
+        let first = World::new();
+        first.register_components::<(Position, IsCool)>();
+        let entity = first.add_entity().add_comp(Position::new(1, 2));
+        first.add_entity().add_comp(Position::new(3, 4));
+
+        let second = World::new();
+        second.register_components::<(Position, IsCool)>();
+        assert!(second.is_active() && !first.is_active());
+        assert_eq!(second.query::<&Position>().build().iter().count(), 0);
+        second.add_entity().add_tag::<IsCool>();
+
+        let count = first.scope(|| {
+            assert!(first.is_active());
+            entity.comp::<Position>(|pos| assert_eq!(pos.y, 2));
+            assert_eq!(
+                first
+                    .query_filtered::<&Entity, With<IsCool>>()
+                    .build()
+                    .iter()
+                    .count(),
+                0
+            );
+            second.scope(|| {
+                second
+                    .query_filtered::<&Entity, With<IsCool>>()
+                    .build()
+                    .iter()
+                    .count()
+            }) + first.query::<&Position>().build().iter().count()
+        });
+        assert_eq!(count, 3);
+
+        assert!(second.is_active());
+        assert_eq!(second.query::<&Position>().build().iter().count(), 0);
+        assert_eq!(
+            second
+                .query_filtered::<&Entity, With<IsCool>>()
+                .build()
+                .iter()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn try_rel_getters() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<(Likes, Apples, Owes, Position)>();
+        let target = world.add_entity();
+        let entity = world.add_entity().add_comp(Position::new(1, 2));
+
+        assert!(entity.try_rel_first::<Owes, Apples>().is_none());
+        assert!(entity.try_rel_second::<Likes, Position>().is_none());
+        assert!(entity.try_mixed_rel::<Owes>(target).is_none());
+        assert!(matches!(
+            archetypes(|a| a.try_get_relationship_first::<Owes, Apples>(entity.0)),
+            Err(GetComponentError::NoComponent(..))
+        ));
+
+        entity
+            .add_rel_first::<Owes, Apples>(Owes { amount: 5 })
+            .add_mixed_rel(target, Owes { amount: 3 });
+        entity
+            .try_rel_first::<Owes, Apples>()
+            .unwrap()
+            .get(|owes| assert_eq!(owes.amount, 5));
+        entity
+            .try_mixed_rel::<Owes>(target)
+            .unwrap()
+            .get(|owes| assert_eq!(owes.amount, 3));
+
+        target.remove();
+        assert!(entity.try_mixed_rel::<Owes>(target).is_none());
+        entity.remove();
+        assert!(matches!(
+            archetypes(|a| a.try_get_relationship_first::<Owes, Apples>(entity.0)),
+            Err(GetComponentError::EntityNotAlive(..))
+        ));
+    }
+
+    #[test]
+    fn world_counts() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity, IsCool)>();
+        let (entities, archetypes, tables) = (
+            world.entity_count(),
+            world.archetype_count(),
+            world.table_count(),
+        );
+
+        for i in 0..10 {
+            world.add_entity().add_comp(Position::new(i, i));
+        }
+        for i in 0..5 {
+            world
+                .add_entity()
+                .add_comp(Position::new(i, i))
+                .add_comp(Velocity::new(i, i));
+        }
+        let tagged = world
+            .add_entity()
+            .add_comp(Position::new(0, 0))
+            .add_tag::<IsCool>();
+
+        assert_eq!(world.entity_count(), entities + 16);
+        assert_eq!(world.archetype_count(), archetypes + 3);
+        assert_eq!(world.table_count(), tables + 3);
+        assert_eq!(world.stats().archetype_count, world.archetype_count());
+
+        tagged.remove();
+        assert_eq!(world.entity_count(), entities + 15);
+    }
+
+    #[test]
+    fn table_rows_stress() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity, IsCool)>();
+        let entities: Vec<_> = (0..100)
+            .map(|i| world.add_entity().add_comp(Position::new(i, -i)))
+            .collect();
+        let mut expected = vec![(true, false, false); entities.len()];
+
+        //xorshift, to keep the sequence reproducible
+        let mut seed = 0x2545_f491_u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize
+        };
+        for _ in 0..5000 {
+            let i = random() % entities.len();
+            let (entity, value) = (entities[i], i as i32);
+            let (position, velocity, cool) = &mut expected[i];
+            match random() % 3 {
+                0 => {
+                    match *position {
+                        true => entity.remove_comp::<Position>(),
+                        false => entity.add_comp(Position::new(value, -value)),
+                    };
+                    *position = !*position;
+                }
+                1 => {
+                    match *velocity {
+                        true => entity.remove_comp::<Velocity>(),
+                        false => entity.add_comp(Velocity::new(-value, value)),
+                    };
+                    *velocity = !*velocity;
+                }
+                _ => {
+                    entity.toggle_tag::<IsCool>();
+                    *cool = !*cool;
+                }
+            }
+        }
+
+        for (i, (entity, (position, velocity, cool))) in entities.iter().zip(expected).enumerate() {
+            let value = i as i32;
+            assert_eq!(entity.has_comp::<Position>(), position);
+            assert_eq!(entity.has_comp::<Velocity>(), velocity);
+            assert_eq!(entity.has_tag::<IsCool>(), cool);
+            if position {
+                entity.comp::<Position>(|pos| assert_eq!((pos.x, pos.y), (value, -value)));
+            }
+            if velocity {
+                entity.comp::<Velocity>(|vel| assert_eq!((vel.x, vel.y), (-value, value)));
+            }
+        }
+    }
+
+    #[test]
+    fn clear_entities() {
+        let world = World::new();
+        world.register_components::<(Position, IsCool, Likes)>();
+        let first = world.add_entity();
+        let parent = world.add_entity().add_comp(Position::new(1, 2));
+        parent.set_name("Parent");
+        world
+            .add_entity()
+            .add_tag::<IsCool>()
+            .add_mixed_tag_rel::<Likes>(first)
+            .add_child_of(parent);
+        world.comp_entity::<Position>().set_name("PositionType");
+        let count = world.entity_count();
+
+        for _ in world.query::<&Position>().build().iter() {
+            assert!(world.clear_entities().is_err());
+        }
+        assert!(parent.is_alive());
+        world.clear_entities().unwrap();
+        assert_eq!(world.entity_count(), count - 3);
+        assert!(!first.is_alive() && !parent.is_alive());
+        assert!(world.entity_by_global_name("Parent").is_none());
+        assert!(world.entity_by_global_name("PositionType").is_some());
+        assert_eq!(world.query::<&Position>().build().iter().count(), 0);
+
+        let entity = world
+            .add_entity()
+            .add_comp(Position::new(3, 4))
+            .add_tag::<IsCool>();
+        assert_eq!(entity.0.low32(), first.0.low32());
+        assert!(!first.is_alive() && !parent.is_alive());
+        entity.comp::<Position>(|pos| assert_eq!(pos.y, 4));
+        world.add_entity().set_name("Parent");
+        assert!(world.entity_by_global_name("Parent").is_some());
+    }
+
+    #[test]
+    fn relationships_with_target() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<(Likes, Owes)>();
+        let target = world.add_entity();
+        let other = world.add_entity();
+        let likes = world.add_entity().add_mixed_tag_rel::<Likes>(target);
+        let owes = world.add_entity().add_mixed_rel(target, Owes { amount: 1 });
+        let child = world.add_entity().add_child_of(target);
+        let likes_other = world.add_entity().add_mixed_tag_rel::<Likes>(other);
+
+        let (mut holders, likes_holders) = archetypes(|a| {
+            let holders: Vec<_> = a
+                .relationships_with_target(target.0)
+                .map(|(holder, relationship)| {
+                    assert_eq!(relationship.second(), target.0.low32());
+                    holder
+                })
+                .collect();
+            let relation = a.component_id::<Likes>();
+            let mut likes_holders: Vec<_> = a
+                .relationships_with_relation(relation)
+                .map(|(holder, _)| holder)
+                .collect();
+            likes_holders.sort_by_key(|h| h.low32());
+            (holders, likes_holders)
+        });
+        holders.sort_by_key(|h| h.low32());
+        assert_eq!(holders, vec![likes.0, owes.0, child.0]);
+        assert_eq!(likes_holders, vec![likes.0, likes_other.0]);
+    }
+
+    #[test]
+    fn on_comp_remove() {
+        use crate::world::archetypes_mut;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let world = World::new().register_sparse_component::<Velocity>();
+        world.register_components::<(Position, IsCool)>();
+        let removed = Rc::new(RefCell::new(vec![]));
+        world.on_comp_remove::<Position>({
+            let removed = removed.clone();
+            move |_, pos| removed.borrow_mut().push(pos.x)
+        });
+        world.on_comp_remove::<Velocity>({
+            let removed = removed.clone();
+            move |entity, vel| {
+                //the component is already gone when the callback runs
+                assert!(!entity.is_alive() || !entity.has_comp::<Velocity>());
+                removed.borrow_mut().push(vel.x)
+            }
+        });
+
+        let entity = world
+            .add_entity()
+            .add_comp(Position::new(1, 0))
+            .add_comp(Velocity::new(2, 0))
+            .add_tag::<IsCool>();
+        entity.remove_comp::<Position>();
+        entity.remove_comp::<Position>();
+        entity.remove_comp::<Velocity>();
+        assert_eq!(*removed.borrow(), vec![1, 2]);
+
+        entity.add_comp(Position::new(3, 0));
+        archetypes_mut(|a| a.lock());
+        entity.remove_comp::<Position>();
+        assert_eq!(removed.borrow().len(), 2);
+        archetypes_mut(|a| a.unlock());
+        assert_eq!(*removed.borrow(), vec![1, 2, 3]);
+
+        entity
+            .add_comp(Position::new(4, 0))
+            .add_comp(Velocity::new(5, 0))
+            .remove();
+        removed.borrow_mut().sort();
+        assert_eq!(*removed.borrow(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn on_rel_add() {
+        use crate::world::archetypes_mut;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let world = World::new();
+        world.register_components::<(Likes, Owes)>();
+        let added = Rc::new(RefCell::new(vec![]));
+        world.on_rel_add::<ChildOf>({
+            let added = added.clone();
+            move |entity, parent| {
+                assert_eq!(entity.parent().map(|p| p.0.low32()), Some(parent.0.low32()));
+                added
+                    .borrow_mut()
+                    .push((entity.0.low32(), parent.0.low32()))
+            }
+        });
+        let owes = Rc::new(RefCell::new(0));
+        world.on_rel_add::<Owes>({
+            let owes = owes.clone();
+            move |_, _| *owes.borrow_mut() += 1
+        });
+
+        let parent = world.add_entity();
+        let other_parent = world.add_entity();
+        let child = world.add_entity().add_child_of(parent);
+        child.add_child_of(parent);
+        world
+            .add_entity()
+            .add_mixed_tag_rel::<Likes>(parent)
+            .add_mixed_rel(parent, Owes { amount: 1 });
+        child.add_child_of(other_parent);
+        archetypes_mut(|a| a.lock());
+        world.add_entity().add_mixed_rel(parent, Owes { amount: 2 });
+        assert_eq!(*owes.borrow(), 1);
+        archetypes_mut(|a| a.unlock());
+
+        assert_eq!(
+            *added.borrow(),
+            vec![
+                (child.0.low32(), parent.0.low32()),
+                (child.0.low32(), other_parent.0.low32())
+            ]
+        );
+        assert_eq!(*owes.borrow(), 2);
+    }
+
+    #[test]
+    fn enum_tag_debug_name() {
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum Weather {
+                Sunny,
+                Rainy,
+            }
+        }
+
+        let world = World::new();
+        world.register_components::<Weather>();
+        let entity = world.add_entity().add_enum_tag(Weather::Rainy);
+        let relationship = entity
+            .component_ids()
+            .into_iter()
+            .find(|id| id.is_relationship())
+            .unwrap();
+        let name = Entity(relationship).debug_name();
+        assert!(name.contains("Weather"));
+        assert!(!name.contains("TODO"));
+        assert!(entity
+            .component_names()
+            .iter()
+            .any(|name| name.ends_with("Weather::Rainy")));
+    }
+
+    #[test]
+    fn ancestors() {
+        let world = World::new();
+        let top = world.add_entity();
+        let middle = world.add_entity().add_child_of(top);
+        let bottom = world.add_entity().add_child_of(middle);
+
+        assert_eq!(bottom.ancestors().count(), 2);
+        let ancestors: Vec<_> = bottom.ancestors().map(|e| e.id().stripped()).collect();
+        assert_eq!(ancestors, vec![middle.id().stripped(), top.id().stripped()]);
+        assert!(bottom.ancestors().all(|e| e.is_alive()));
+        assert_eq!(bottom.root().id().stripped(), top.id().stripped());
+        assert_eq!(top.root().id().stripped(), top.id().stripped());
+        assert_eq!(top.ancestors().count(), 0);
+    }
+
+    #[test]
+    fn parent_under_descendant() {
+        let world = World::new();
+        let top = world.add_entity();
+        let middle = world.add_entity().add_child_of(top);
+        let bottom = world.add_entity().add_child_of(middle);
+        let other = world.add_entity();
+
+        assert!(bottom.is_descendant_of(&top));
+        assert!(top.is_ancestor_of(&bottom));
+        assert!(!top.is_descendant_of(&bottom));
+        assert!(!other.is_descendant_of(&top));
+
+        assert!(matches!(top.reparent(bottom), Err(ReparentError::Cycle(_))));
+        assert!(top.reparent(top).is_err());
+        top.add_child_of(bottom);
+        assert!(top.parent().is_none());
+        assert!(top.try_add_child_of(bottom).is_err());
+        assert!(world.add_entity().try_add_child_of(top).is_ok());
+        assert_eq!(bottom.root().id().low32(), top.id().low32());
+
+        bottom.reparent(other).unwrap();
+        assert!(bottom.is_descendant_of(&other));
+        assert!(!bottom.is_descendant_of(&top));
+        assert!(other.reparent(bottom).is_err());
+    }
+
+    #[test]
+    fn deferred_parenting_cycle() {
+        let world = World::new();
+        world.register_components::<Position>();
+        let old = world.add_entity();
+        let first = world
+            .add_entity()
+            .add_comp(Position::new(0, 0))
+            .add_child_of(old);
+        let second = world.add_entity().add_comp(Position::new(1, 1));
+        for _ in world.query::<&Position>().build().iter().take(1) {
+            first.add_child_of(second);
+            assert!(matches!(
+                second.try_add_child_of(first),
+                Err(ReparentError::Cycle(_))
+            ));
+        }
+        assert!(first.is_child_of(second) && !first.is_child_of(old));
+        assert!(second.parent().is_none());
+    }
+
+    #[test]
+    fn clear_then_add() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        world
+            .add_entity()
+            .add_comp(Position::new(1, 2))
+            .add_comp(Velocity::new(3, 4))
+            .clear();
+        //the emptied archetypes must not reuse tables that still hold the removed columns
+        let other = world.add_entity().add_comp(Velocity::new(7, 8));
+        other.add_comp(Position::new(9, 10));
+        other.comp::<Position>(|pos| assert_eq!(pos.x, 9));
+        other.comp::<Velocity>(|vel| assert_eq!(vel.x, 7));
+    }
+
+    #[test]
+    fn change_ticks_per_query() {
+        use crate::query_structs::Changed;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut world = World::new();
+        world.register_components::<Position>();
+        let entity = world.add_entity().add_comp(Position::new(0, 0));
+
+        let mut first = world.query_filtered::<&Entity, Changed<Position>>().build();
+        let mut second = world.query_filtered::<&Entity, Changed<Position>>().build();
+        assert_eq!(first.iter().count(), 1);
+        assert_eq!(second.iter().count(), 1);
+        assert_eq!(first.iter().count(), 0);
+
+        entity.comp_mut::<Position>(|pos| pos.x = 1);
+        assert_eq!(first.iter().count(), 1);
+        assert_eq!(second.iter().count(), 1);
+
+        //every system keeps its own tick, even for the same query
+        let seen = Rc::new(Cell::new(0));
+        for _ in 0..2 {
+            let seen = seen.clone();
+            world.add_systems(
+                move |world: &World| {
+                    let mut query = world.query_filtered::<&Entity, Changed<Position>>().build();
+                    seen.set(seen.get() + query.iter().count());
+                },
+                SystemStage::Update,
+            );
+        }
+        world.run();
+        assert_eq!(seen.get(), 2);
+        world.run();
+        assert_eq!(seen.get(), 2);
+        entity.comp_mut::<Position>(|pos| pos.x = 2);
+        world.run();
+        assert_eq!(seen.get(), 4);
+    }
+
+    #[test]
+    fn sorted_by_changed() {
+        use crate::query_structs::Changed;
+
+        let world = World::new();
+        world.register_components::<Position>();
+        for x in [3, 1, 2] {
+            world.add_entity().add_comp(Position::new(x, 0));
+        }
+        let mut query = world
+            .query_filtered::<&Position, Changed<Position>>()
+            .build();
+        let sorted: Vec<_> = query.sorted_by(|pos| pos.x).map(|pos| pos.x).collect();
+        assert_eq!(sorted, [1, 2, 3]);
+        assert_eq!(query.sorted_by(|pos| pos.x).count(), 0);
+    }
+
+    #[test]
+    fn pairs_changed() {
+        use crate::query_structs::{Added, Changed};
+
+        let world = World::new();
+        world.register_components::<Position>();
+        for x in 0..3 {
+            world.add_entity().add_comp(Position::new(x, 0));
+        }
+        let mut changed = world
+            .query_filtered::<&Position, Changed<Position>>()
+            .build();
+        assert_eq!(changed.pairs().count(), 3);
+        assert_eq!(changed.pairs().count(), 0);
+        let mut added = world.query_filtered::<&Position, Added<Position>>().build();
+        assert_eq!(added.pairs().count(), 3);
+    }
+
+    #[test]
+    fn or_changed() {
+        use crate::query_structs::{Changed, Or};
+
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let position = world.add_entity().add_comp(Position::new(0, 0));
+        let velocity = world.add_entity().add_comp(Velocity::new(0, 0));
+        world
+            .add_entity()
+            .add_comp(Position::new(0, 0))
+            .add_comp(Velocity::new(0, 0));
+
+        let mut query = world
+            .query_filtered::<&Entity, Or<(Changed<Position>, Changed<Velocity>)>>()
+            .build();
+        assert_eq!(query.iter().count(), 3);
+        assert_eq!(query.iter().count(), 0);
+
+        position.comp_mut::<Position>(|pos| pos.x = 1);
+        velocity.comp_mut::<Velocity>(|vel| vel.x = 1);
+        let mut changed: Vec<_> = query.iter().map(|e| e.id().low32()).collect();
+        changed.sort();
+        assert_eq!(changed, [position.id().low32(), velocity.id().low32()]);
+    }
+
+    #[test]
+    fn sparse_filters_and_locks() {
+        use crate::query_structs::Or;
+
+        let world = World::new()
+            .register_sparse_component::<Velocity>()
+            .register_sparse_component::<IsCool>();
+        world.register_components::<Position>();
+        let entities: Vec<_> = (0..3)
+            .map(|i| world.add_entity().add_comp(Position::new(i, 0)))
+            .collect();
+        entities[0]
+            .add_comp(Velocity::new(1, 2))
+            .add_tag::<IsCool>();
+        entities[1].add_comp(Velocity::new(3, 4));
+
+        assert_eq!(
+            world
+                .query_filtered::<&Position, With<Velocity>>()
+                .build()
+                .iter()
+                .count(),
+            2
+        );
+        assert_eq!(
+            world
+                .query_filtered::<&Position, Not<With<IsCool>>>()
+                .build()
+                .iter()
+                .count(),
+            2
+        );
+        assert_eq!(
+            world
+                .query::<&Position>()
+                .with_comp::<Velocity>()
+                .build()
+                .iter()
+                .count(),
+            2
+        );
+        assert_eq!(
+            world
+                .query::<&Position>()
+                .with_tag::<IsCool>()
+                .build()
+                .iter()
+                .count(),
+            1
+        );
+        assert_eq!(
+            world
+                .query_filtered::<&Position, Or<(With<IsCool>, Not<With<Velocity>>)>>()
+                .build()
+                .iter()
+                .count(),
+            2
+        );
+
+        //sparse edits wait for the query to finish
+        for entity in world
+            .query::<&Entity>()
+            .with_comp::<Position>()
+            .build()
+            .iter()
+        {
+            if entity.id().low32() == entities[0].id().low32() {
+                entity.remove_comp::<Velocity>();
+                assert!(entity.has_comp::<Velocity>());
+            } else if entity.id().low32() == entities[1].id().low32() {
+                entity.add_tag::<IsCool>();
+                assert!(!entity.has_tag::<IsCool>());
+            } else {
+                entity.add_comp(Velocity::new(5, 6));
+                assert!(!entity.has_comp::<Velocity>());
+            }
+        }
+        assert!(!entities[0].has_comp::<Velocity>());
+        assert!(entities[1].has_tag::<IsCool>());
+        entities[2].comp::<Velocity>(|vel| assert_eq!(vel.x, 5));
+
+        let clone = entities[1].cloned();
+        assert!(clone.has_tag::<IsCool>());
+        clone.comp::<Velocity>(|vel| assert_eq!(vel.y, 4));
+        world
+            .add_entity()
+            .add_comp(Velocity::new(7, 8))
+            .cloned()
+            .comp::<Velocity>(|vel| assert_eq!(vel.x, 7));
+
+        let json = entities[1].serialize().unwrap();
+        assert!(json.contains("Velocity") && json.contains("IsCool"));
+        let copy = world.deserialize_entity(&json).unwrap();
+        assert!(copy.has_tag::<IsCool>());
+        copy.comp::<Velocity>(|vel| assert_eq!(vel.x, 3));
+        assert_eq!(world.query::<&Velocity>().build().iter().count(), 6);
+    }
+
+    #[test]
+    fn sparse_clone_while_locked() {
+        let world = World::new().register_sparse_component::<Velocity>();
+        world.register_components::<Position>();
+        world.add_entity().add_comp(Position::new(0, 0));
+        let source = world.add_entity().add_comp(Velocity::new(1, 2));
+
+        let mut clones = vec![];
+        for _ in world.query::<&Position>().build().iter() {
+            let clone = source.cloned();
+            assert!(!clone.has_comp::<Velocity>());
+            clones.push(clone);
+        }
+        clones[0].comp::<Velocity>(|vel| assert_eq!(vel.y, 2));
+    }
+
+    #[test]
+    fn component_ids() {
+        let world = World::new();
+        world.register_components::<(Position, IsCool, Likes, Apples)>();
+        let entity = world
+            .add_entity()
+            .add_comp(Position::new(1, 2))
+            .add_tag::<IsCool>()
+            .add_rel::<Likes, Apples>();
+
+        let ids = entity.component_ids();
+        assert_eq!(ids.len(), 3);
+        assert!(!ids.iter().any(|id| id.stripped() == ENTITY_ID.stripped()));
+        assert!(ids.iter().any(|id| id.is_relationship()));
+        let names = entity.component_names();
+        for name in ["Position", "IsCool", "(Likes, Apples)"] {
+            assert!(
+                names.iter().any(|n| n == name),
+                "{name} missing in {names:?}"
+            );
+        }
+        assert!(world.add_entity().component_ids().is_empty());
+    }
+
+    #[test]
+    fn despawn_all_matching() {
+        let world = World::new();
+        world.register_components::<(Position, IsCool)>();
+        let parent = world
+            .add_entity()
+            .add_comp(Position { x: 0, y: 0 })
+            .add_tag::<IsCool>();
+        let child = world
+            .add_entity()
+            .add_comp(Position { x: 1, y: 0 })
+            .add_tag::<IsCool>()
+            .add_child_of(parent);
+        let other = world.add_entity().add_comp(Position { x: 2, y: 0 });
+
+        world.despawn_all_matching::<&Position, With<IsCool>>();
+        assert!(!parent.is_alive());
+        assert!(!child.is_alive());
+        assert!(other.is_alive());
+
+        let cool = world.add_entity().add_tag::<IsCool>();
+        for _ in world.query::<&Position>().build().iter() {
+            world.despawn_all_matching::<(), With<IsCool>>();
+            assert!(cool.is_alive());
+        }
+        assert!(!cool.is_alive());
+        assert!(other.is_alive());
+    }
+
+    #[test]
+    fn filtered_queries() {
+        let world = World::new();
+        world.register_components::<(Position, IsCool, Velocity, Likes, Apples)>();
+        let e1 = world.add_entity().add_comp(Position { x: 1, y: 2 });
+        e1.add_comp(Velocity { x: 1, y: 0 }).add_tag::<IsCool>();
+        world
+            .add_entity()
+            .add_comp(Position { x: 3, y: 4 })
+            .add_rel::<Likes, Apples>();
+
+        let mut query = world
+            .query_filtered::<&Position, WithRelation<Wildcard, Apples>>()
+            .build();
+
+        let sum: i32 = query.iter().map(|p| p.x + p.y).sum();
+        assert_eq!(sum, 7);
+    }
+    #[test]
+    fn queries() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let e1 = world.add_entity().add_comp(Position { x: 1, y: 2 });
+        e1.add_comp(Velocity { x: 1, y: 0 });
+        let e2 = world.add_entity().add_comp(Position { x: 3, y: 4 });
+
+        let mut query_ref = world.query::<&Position>().build();
+        let mut counter = 0;
+        for pos in query_ref.iter() {
+            counter += pos.x + pos.y;
+        }
+        assert_eq!(counter, 10);
+
+        let mut query_mut = world.query::<(&mut Position, &Entity)>().build();
+        for (mut pos, _) in query_mut.iter() {
+            pos.x = 10;
+            pos.y = 1;
+        }
+
+        let pos_sum_1 = e1.comp_ret(|p: &Position| p.x + p.y);
+        let pos_sum_2 = e2.comp_ret(|p: &Position| p.x + p.y);
+        assert_eq!(pos_sum_1 + pos_sum_2, 22);
+
+        let combined_query = world.query::<(&Position, &Velocity)>();
+        let sum: i32 = combined_query
+            .build()
+            .iter()
+            .map(|(pos, vel)| vel.x + vel.y + pos.x + pos.y)
+            .sum();
+        assert_eq!(sum, 12);
+        let mut count = 0;
+        let mut sum = 0;
+        let mut optional_query = world.query::<(&Position, Option<&Velocity>)>().build();
+        for (pos, vel) in optional_query.iter() {
+            count += 1;
+            let vel = vel.map(|v| *v).unwrap_or(Velocity { x: 0, y: 0 });
+            sum += pos.x + pos.y + vel.x + vel.y;
+        }
+        assert_eq!(count, 2);
+        assert_eq!(sum, 23);
+    }
+
+    #[test]
+    fn prefab() {
+        let world = World::new();
+        world.register_components::<(Velocity, Likes, Oranges)>();
+        let prefab = world
+            .add_prefab()
+            .add_comp(Velocity { x: 10, y: 20 })
+            .add_rel::<Likes, Oranges>();
+
+        let instance = world.add_entity().instance_of(prefab);
+        assert!(instance.has_mixed_rel::<InstanceOf>(prefab));
+        instance.comp::<Velocity>(|p| {
+            assert_eq!(p.x, 10);
+            assert_eq!(p.y, 20);
+        });
+        assert!(instance.has_rel::<Likes, Oranges>());
+    }
+
+    #[test]
+    fn nested_prefabs() {
+        let world = World::new();
+        world.register_components::<(Velocity, Position, Owes)>();
+        let wheel_prefab = world.add_prefab().add_comp(Velocity { x: 1, y: 0 });
+        world
+            .add_prefab()
+            .add_comp(Position { x: 5, y: 5 })
+            .add_child_of(wheel_prefab)
+            .set_name("Hub");
+
+        let car_prefab = world.add_prefab().add_comp(Owes { amount: 3 });
+        world
+            .add_entity()
+            .instance_of(wheel_prefab)
+            .add_child_of(car_prefab)
+            .set_name("Wheel");
+        wheel_prefab.comp_mut::<Velocity>(|v| v.x = 10);
+
+        let car = world.add_entity().instance_of(car_prefab);
+        assert!(car.has_mixed_rel::<InstanceOf>(car_prefab));
+        assert!(!car.has_tag::<Prefab>());
+        assert_eq!(car.comp_ret::<Owes, _>(|o| o.amount), 3);
+
+        let children: Vec<_> = car.children_recursive().collect();
+        assert_eq!(children.len(), 2);
+        let wheel = children[0].0;
+        assert_eq!(wheel.name().get(|n| n.to_owned()), "Wheel");
+        assert!(wheel.has_mixed_rel::<InstanceOf>(wheel_prefab));
+        assert_eq!(wheel.comp_ret::<Velocity, _>(|v| v.x), 10);
+        let hub = children[1].0;
+        assert!(hub.is_child_of(wheel));
+        assert_eq!(hub.name().get(|n| n.to_owned()), "Hub");
+        assert_eq!(hub.comp_ret::<Position, _>(|p| p.x), 5);
+    }
+
+    #[test]
+    fn nested_prefab_overrides() {
+        let world = World::new();
+        world.register_components::<(Velocity, Position, IsCool)>();
+        let wheel_prefab = world
+            .add_prefab()
+            .add_comp(Velocity { x: 1, y: 1 })
+            .add_comp(Position { x: 1, y: 1 });
+        let car_prefab = world.add_prefab();
+        world
+            .add_entity()
+            .instance_of(wheel_prefab)
+            .add_child_of(car_prefab)
+            .add_tag::<IsCool>()
+            .add_comp(Velocity { x: 3, y: 3 })
+            .mark_overridden::<Velocity>()
+            .add_comp(Position { x: 4, y: 4 });
+        wheel_prefab.comp_mut::<Position>(|p| p.x = 10);
+
+        let car = world.add_entity().instance_of(car_prefab);
+        let wheel = car.nth_child(0).unwrap();
+        assert!(wheel.has_mixed_rel::<InstanceOf>(wheel_prefab));
+        assert!(wheel.has_tag::<IsCool>());
+        assert_eq!(wheel.comp_ret::<Velocity, _>(|v| v.x), 3);
+        assert_eq!(wheel.comp_ret::<Position, _>(|p| p.x), 10);
+
+        wheel_prefab.comp_mut::<Velocity>(|v| v.x = 20);
+        world.resync_instance(wheel).unwrap();
+        assert_eq!(wheel.comp_ret::<Velocity, _>(|v| v.x), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "prefab reference cycle")]
+    fn prefab_reference_cycle() {
+        let world = World::new();
+        let prefab = world.add_prefab();
+        world
+            .add_prefab()
+            .add_mixed_tag_rel::<InstanceOf>(prefab)
+            .add_child_of(prefab);
+        world.add_entity().instance_of(prefab);
+    }
+
+    #[test]
+    fn prefab_overrides() {
+        let world = World::new();
+        world.register_components::<(Velocity, Position)>();
+        let prefab = world
+            .add_prefab()
+            .add_comp(Velocity { x: 1, y: 1 })
+            .add_comp(Position { x: 1, y: 1 });
+        let instance = world.add_entity().instance_of(prefab);
+        instance
+            .add_comp(Velocity { x: 5, y: 5 })
+            .mark_overridden::<Velocity>();
+
+        prefab.comp_mut::<Velocity>(|v| v.x = 2);
+        prefab.comp_mut::<Position>(|p| p.x = 2);
+        for _ in world.query::<&Position>().build().iter().take(1) {
+            assert!(world.resync_instance(instance).is_err());
+        }
+        assert_eq!(instance.comp_ret::<Position, _>(|p| p.x), 1);
+        world.resync_instance(instance).unwrap();
+
+        assert_eq!(instance.comp_ret::<Velocity, _>(|v| v.x), 5);
+        assert_eq!(instance.comp_ret::<Position, _>(|p| p.x), 2);
+        assert!(instance.has_mixed_rel::<InstanceOf>(prefab));
+        assert!(!instance.has_tag::<Prefab>());
+    }
+
+    #[test]
+    fn query_get() {
+        let world = World::new();
+        world.register_components::<(Velocity, Position)>();
+        let moving = world
+            .add_entity()
+            .add_comp(Velocity { x: 1, y: 2 })
+            .add_comp(Position { x: 3, y: 4 });
+        let still = world.add_entity().add_comp(Position { x: 0, y: 0 });
+        let mut query = world.query::<(&Position, Option<&mut Velocity>)>().build();
+
+        let (position, velocity) = query.get(moving).unwrap();
+        assert_eq!(position.x, 3);
+        velocity.unwrap().x = 10;
+        let (_, velocity) = query.get(still).unwrap();
+        assert!(velocity.is_none());
+        assert_eq!(moving.comp_ret::<Velocity, _>(|v| v.x), 10);
+
+        let mut velocities = world.query::<&Velocity>().build();
+        assert!(velocities.get(still).is_none());
+        moving.diactivate();
+        assert!(velocities.get(moving).is_none());
+        moving.activate();
+        moving.remove();
+        assert!(velocities.get(moving).is_none());
+    }
+
+    #[test]
+    fn query_single() {
+        use crate::query::QuerySingleError;
+
+        let world = World::new();
+        world.register_components::<(Velocity, Position)>();
+        let mut query = world.query::<&Velocity>().build();
+        assert_eq!(query.get_single().err(), Some(QuerySingleError::NoEntities));
+
+        world.add_entity().add_comp(Velocity { x: 7, y: 0 });
+        assert_eq!(query.single().x, 7);
+
+        world.add_entity().add_comp(Velocity { x: 1, y: 0 });
+        world.add_entity().add_comp(Velocity { x: 2, y: 0 });
+        assert_eq!(
+            query.get_single().err(),
+            Some(QuerySingleError::MultipleEntities(3))
+        );
+    }
+
+    #[test]
+    fn cloned_relationship_data_is_independent() {
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum Mood {
+                Calm,
+                Angry,
+            }
+        }
+
+        let world = World::new();
+        world.register_components::<(Position, Owes, Apples, Begin, Mood)>();
+        let entity = world
+            .add_entity()
+            .add_rel_first::<Owes, Apples>(Owes { amount: 10 })
+            .add_rel_second::<Begin, Position>(Position::new(1, 2))
+            .add_enum_tag(Mood::Calm);
+        let clone = entity.cloned();
+
+        entity
+            .rel_second_mut::<Begin, Position>()
+            .get_mut(|pos| pos.x = 100);
+        entity
+            .rel_first_mut::<Owes, Apples>()
+            .get_mut(|owes| owes.amount = 0);
+        entity.add_enum_tag(Mood::Angry);
+
+        clone
+            .rel_second::<Begin, Position>()
+            .get(|pos| assert_eq!(pos.x, 1));
+        clone
+            .rel_first::<Owes, Apples>()
+            .get(|owes| assert_eq!(owes.amount, 10));
+        assert_eq!(clone.get_enum_tag::<Mood>(), Some(Mood::Calm));
+        assert_eq!(entity.get_enum_tag::<Mood>(), Some(Mood::Angry));
+    }
+
+    #[test]
+    fn everything_at_once_cloned() {
+        let world = World::new();
+        world.register_components::<(Velocity, Position, IsCool, Likes, Apples, Owes, Begin)>();
+        let bob = world.add_entity();
+        let hates = world.add_entity();
+        let idkwhat = world.add_entity();
+        let entity_tag = world.add_entity();
+        let entity = world
+            .add_entity()
+            .add_comp(Position { x: 10, y: 20 })
+            .add_comp(Position::new(3, 4))
+            .add_tag::<IsCool>()
+            .add_ent_tag(entity_tag)
+            .add_ent_rel(hates, bob)
+            .add_rel::<Likes, Apples>()
+            .add_rel_first::<Owes, Apples>(Owes { amount: 10 })
+            .add_rel_second::<Begin, Position>(Position::new(1, 2))
+            .add_mixed_rel(idkwhat, Position::new(5, 6));
+
+        let entity = entity.cloned();
+
+        assert!(entity.has_comp::<Position>());
+        assert!(entity.has_tag::<IsCool>());
+        assert!(entity.has_ent_tag(entity_tag));
+        assert!(entity.has_rel::<Likes, Apples>());
+        assert!(entity.has_rel::<Owes, Apples>());
+        assert!(entity.has_rel::<Begin, Position>());
+        assert!(entity.has_ent_rel(hates, bob));
+        assert!(entity.has_mixed_rel::<Position>(idkwhat));
+
+        entity.comp::<Position>(|pos| {
+            assert_eq!(pos.x, 3);
+            assert_eq!(pos.y, 4);
+        });
+        // entity.rel_first::<Owes, Begin>().try_get(|stuff| {});
+        entity.rel_first::<Owes, Apples>().get(|ows| {
+            assert_eq!(ows.amount, 10);
+        });
+        entity.rel_second::<Begin, Position>().get(|pos| {
+            assert_eq!(pos.x, 1);
+            assert_eq!(pos.y, 2);
+        });
+        entity.mixed_rel::<Position>(idkwhat).get(|pos| {
+            assert_eq!(pos.x, 5);
+            assert_eq!(pos.y, 6);
+        });
+        entity.remove_comp::<Position>();
+        assert!(!entity.has_comp::<Position>());
+        entity.remove_tag::<IsCool>();
+        assert!(!entity.has_tag::<IsCool>());
+        entity.remove_ent_tag(entity_tag);
+        assert!(!entity.has_ent_tag(entity_tag));
+        entity.remove_ent_rel(hates, bob);
+        assert!(!entity.has_ent_rel(hates, bob));
+        entity.remove_rel::<Likes, Apples>();
+        assert!(!entity.has_rel::<Likes, Apples>());
+        entity.remove_rel::<Owes, Apples>();
+        assert!(!entity.has_rel::<Owes, Apples>());
+        entity.remove_rel::<Begin, Position>();
+        assert!(!entity.has_rel::<Begin, Position>());
+        entity.remove_mixed_rel::<Position>(idkwhat);
+        assert!(!entity.has_mixed_rel::<Position>(idkwhat));
+        entity.remove_mixed_rel::<Likes>(bob);
+        assert!(!entity.has_mixed_rel::<Likes>(bob));
+    }
+
+    #[test]
+    fn wildcard() {
+        let world = World::new();
+        world.register_components::<(Likes, Apples)>();
+        let entity = world.add_entity();
+        entity.add_rel::<Likes, Apples>();
+        assert!(entity.has_rel::<Likes, Wildcard>());
+        assert!(entity.has_rel::<Wildcard, Apples>());
+
+        entity.remove_rel::<Likes, Apples>();
+        let is = world.add_entity();
+        let helicopter = world.add_entity();
+
+        entity.add_ent_rel(is, helicopter);
+        assert!(entity.has_ent_rel(WILDCARD, helicopter));
+        assert!(entity.has_ent_rel(is, WILDCARD));
+    }
+    #[test]
+    fn everything_at_once() {
+        let world = World::new();
+        world.register_components::<(Velocity, Position, IsCool, Likes, Apples, Owes, Begin)>();
+        let bob = world.add_entity();
+        let hates = world.add_entity();
+        let idkwhat = world.add_entity();
+        let entity_tag = world.add_entity();
+
+        world
+            .add_entity()
+            .add_comp::<Velocity>(Velocity { x: 1, y: 2 });
+        let mut entity = None;
+        //also testing adding stuff inside a query
+        for _ in world.empty_query().with_comp::<Velocity>().build().iter() {
+            entity = Some(
+                world
+                    .add_entity()
+                    .add_comp(Position::new(3, 4))
+                    .add_tag::<IsCool>()
+                    .add_ent_tag(entity_tag)
+                    .add_ent_rel(hates, bob)
+                    .add_rel::<Likes, Apples>()
+                    .add_rel_first::<Owes, Apples>(Owes { amount: 10 })
+                    .add_rel_second::<Begin, Position>(Position::new(1, 2))
+                    .add_mixed_rel(idkwhat, Position::new(5, 6))
+                    .add_mixed_tag_rel::<Likes>(bob),
+            )
+        }
+
+        let entity = entity.unwrap();
+
+        assert!(entity.has_comp::<Position>());
+        assert!(entity.has_tag::<IsCool>());
+        assert!(entity.has_ent_tag(entity_tag));
+        assert!(entity.has_rel::<Likes, Apples>());
+        assert!(entity.has_rel::<Owes, Apples>());
         assert!(entity.has_rel::<Begin, Position>());
         assert!(entity.has_ent_rel(hates, bob));
         assert!(entity.has_mixed_rel::<Position>(idkwhat));
         assert!(entity.has_mixed_rel::<Likes>(bob));
 
-        entity.comp::<Position>(|pos| {
-            assert_eq!(pos.x, 3);
-            assert_eq!(pos.y, 4);
-        });
-        // entity.rel_first::<Owes, Begin>().try_get(|stuff| {});
-        entity.rel_first::<Owes, Apples>().get(|ows| {
-            assert_eq!(ows.amount, 10);
-        });
-        entity.rel_second::<Begin, Position>().get(|pos| {
-            assert_eq!(pos.x, 1);
-            assert_eq!(pos.y, 2);
+        entity.comp::<Position>(|pos| {
+            assert_eq!(pos.x, 3);
+            assert_eq!(pos.y, 4);
+        });
+        // entity.rel_first::<Owes, Begin>().try_get(|stuff| {});
+        entity.rel_first::<Owes, Apples>().get(|ows| {
+            assert_eq!(ows.amount, 10);
+        });
+        entity.rel_second::<Begin, Position>().get(|pos| {
+            assert_eq!(pos.x, 1);
+            assert_eq!(pos.y, 2);
+        });
+        entity.mixed_rel::<Position>(idkwhat).get(|pos| {
+            assert_eq!(pos.x, 5);
+            assert_eq!(pos.y, 6);
+        });
+        entity.remove_comp::<Position>();
+        assert!(!entity.has_comp::<Position>());
+        entity.remove_tag::<IsCool>();
+        assert!(!entity.has_tag::<IsCool>());
+        entity.remove_ent_tag(entity_tag);
+        assert!(!entity.has_ent_tag(entity_tag));
+        entity.remove_ent_rel(hates, bob);
+        assert!(!entity.has_ent_rel(hates, bob));
+        entity.remove_rel::<Likes, Apples>();
+        assert!(!entity.has_rel::<Likes, Apples>());
+        entity.remove_rel::<Owes, Apples>();
+        assert!(!entity.has_rel::<Owes, Apples>());
+        entity.remove_rel::<Begin, Position>();
+        assert!(!entity.has_rel::<Begin, Position>());
+        entity.remove_mixed_rel::<Position>(idkwhat);
+        assert!(!entity.has_mixed_rel::<Position>(idkwhat));
+        entity.remove_mixed_rel::<Likes>(bob);
+        assert!(!entity.has_mixed_rel::<Position>(idkwhat));
+    }
+
+    #[test]
+    fn data_rels_first() {
+        let world = World::new();
+        world.register_components::<(Owes, Apples, Oranges)>();
+        let ann = world.add_entity();
+        ann.add_rel_first::<Owes, Apples>(Owes { amount: 10 });
+        ann.add_rel_first::<Owes, Oranges>(Owes { amount: 10 });
+        ann.remove_rel::<Owes, Apples>();
+        assert!(!ann.has_rel::<Owes, Apples>());
+        assert!(ann.has_rel::<Owes, Oranges>());
+    }
+
+    #[test]
+    fn data_rels_second() {
+        let world = World::new();
+        world.register_components::<(Begin, Position, End, Apples)>();
+        let entity = world.add_entity();
+        entity
+            .add_rel_second::<Begin, _>(Position::new(1, 2))
+            .add_rel_second::<End, _>(Position::new(3, 4));
+        let p1 = entity.rel_second::<Begin, Position>().get(|c| *c);
+        let p2 = entity.rel_second::<End, Position>().get(|c| *c);
+
+        assert!(entity.has_rel::<Begin, Position>());
+        assert!(entity.has_rel::<End, Position>());
+        assert!(!entity.has_rel::<Apples, Position>());
+
+        assert_eq!(p1.x, 1);
+        assert_eq!(p1.y, 2);
+        assert_eq!(p2.x, 3);
+        assert_eq!(p2.y, 4);
+    }
+
+    #[test]
+    fn tag_rels() {
+        let world = World::new();
+        world.register_components::<(Likes, Apples, Position)>();
+        let entity = world.add_entity();
+        entity.add_rel::<Likes, Apples>();
+        entity.add_comp(Position { x: 10, y: 20 });
+
+        let pos = entity.comp_ret(|c: &Position| *c);
+        assert_eq!(pos.x, 10);
+        assert_eq!(pos.y, 20);
+
+        assert!(entity.has_rel::<Likes, Apples>());
+    }
+    #[test]
+    fn adding_comps_to_comps() {
+        // impl_component! {
+        //     struct Material {
+        //         data: u32,
+        //     }
+        // }
+        // impl_component! {
+        //     struct Uniform {
+        //         other_data: u32,
+        //     }
+        // }
+        // impl_component! {
+        //     struct HasMaterial {}
+        // }
+        // //for some reason has_components(COMPONENT_ID, HasMaterial) return true before IsComponent is added
+        // let world = World::new();
+        // world.comp_entity::<HasMaterial>();
+        // // let comp = world.comp_entity::<Uniform>();
+        // // comp.add_comp(Material { data: 5 });
+        // // dbg!("begin");
+        // // let e = world.comp_entity::<HasMaterial>();
+        // // dbg!(archetypes(|a| a.debug_print_archetypes()));
+        // // dbg!(archetypes(|a| a.record(e.into()).unwrap().arhetype_id));
+        // let entity = world.add_entity().add_tag::<HasMaterial>();
+    }
+
+    #[test]
+    fn bevy_reflection() {
+        #[derive(Debug, Reflect)]
+        struct SomeValues {
+            string: String,
+            int: i32,
+        }
+
+        let mut dynamic_struct = DynamicStruct::default();
+        dynamic_struct.insert("string".to_string(), "A string!".to_string());
+        dynamic_struct.insert("int".to_string(), 25);
+        let some_values = SomeValues::from_reflect(&dynamic_struct);
+        dbg!(some_values);
+    }
+    #[test]
+    fn deserialization() {
+        let json = json!(
+        {
+            "Name": "John",
+            "($Owes, Apples)": {
+                "amount": 10
+            },
+            "(Begin, $Position)": {
+                "x": 2,
+                "y": 3
+            },
+            "Position": {
+                "x": 1,
+                "y": 2
+            },
+            "Tags": [
+                "#Enemy",
+                "IsCool",
+                "(Likes, #Enemy)",
+                "(Likes, Apples)",
+            ]
+        });
+        let world = World::new();
+        world.register_components::<(Likes, Apples, IsCool, Position, Begin, Owes)>();
+        let entity = world
+            .deserialize_entity(&json.to_string())
+            .map_err(|e| e.to_string())
+            .unwrap();
+        entity.comp::<Position>(|p| {
+            assert_eq!(p.x, 1);
+            assert_eq!(p.y, 2);
+        });
+        //TODO: unite and add missing get/get_mut methods
+        let enemy = world.entity_by_global_name("Enemy").unwrap();
+        let mut enemy = enemy.0.unpack();
+        enemy.high32.is_target = false;
+        let enemy = Entity(enemy.into());
+        entity
+            .rel_first::<Owes, Apples>()
+            .get(|owes| assert_eq!(owes.amount, 10));
+        entity
+            .rel_second::<Begin, Position>()
+            .get(|pos| assert_eq!(pos.x + pos.y, 5));
+        entity.comp::<Position>(|pos| assert_eq!(pos.x + pos.y, 3));
+        entity.name().get(|n| assert!(n == "John"));
+        assert!(entity.has_tag::<IsCool>());
+        assert!(entity.has_rel::<Likes, Apples>());
+        assert!(entity.has_mixed_rel::<Likes>(enemy));
+        assert!(entity.has_ent_tag(enemy));
+    }
+    #[test]
+    fn serialization() {
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum MyEnumTag {
+                StateOne
+            }
+        }
+
+        let world = World::new();
+        world.register_components::<(MyEnumTag, Position, IsCool, Likes, Apples, Owes, Begin)>();
+        let enemy = world.add_entity_named("Enemy");
+        let entity = world
+            .add_entity_named("John")
+            .add_rel_first::<_, Apples>(Owes { amount: 10 })
+            .add_rel_second::<Begin, _>(Position::new(2, 3))
+            .add_comp(Position::new(1, 2))
+            .add_tag::<IsCool>()
+            .add_ent_tag(enemy)
+            .add_rel::<Likes, Apples>()
+            .add_mixed_tag_rel::<Likes>(enemy)
+            .serialize()
+            .unwrap();
+        println!("{entity}");
+    }
+
+    #[test]
+    fn many_entities() {
+        let world = World::new();
+        world.register_components::<(Position,)>();
+        let entities: Vec<_> = (0..2000)
+            .map(|i| world.add_entity().add_comp(Position::new(i, -i)))
+            .collect();
+        for (i, entity) in entities.iter().enumerate() {
+            assert!(entity.is_alive());
+            entity.comp::<Position>(|pos| {
+                assert_eq!(pos.x, i as i32);
+                assert_eq!(pos.y, -(i as i32));
+            });
+        }
+    }
+
+    #[test]
+    fn world_stats() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity, IsCool)>();
+        let before = world.stats();
+        world.add_entity().add_comp(Position::new(1, 2));
+        world
+            .add_entity()
+            .add_comp(Position::new(1, 2))
+            .add_comp(Velocity::new(3, 4));
+        world
+            .add_entity()
+            .add_comp(Position::new(1, 2))
+            .add_tag::<IsCool>();
+        let after = world.stats();
+        assert_eq!(after.entity_count, before.entity_count + 3);
+        assert!(after.archetype_count >= before.archetype_count + 3);
+        assert!(after.table_count >= before.table_count + 2);
+        assert!(
+            after.total_component_bytes
+                >= before.total_component_bytes
+                    + 3 * std::mem::size_of::<Position>()
+                    + std::mem::size_of::<Velocity>()
+        );
+    }
+
+    #[test]
+    fn toggle_component() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let entity = world.add_entity().add_comp(Position::new(1, 2));
+        entity
+            .add_comp(Velocity::new(0, 0))
+            .remove_comp::<Velocity>();
+        let archetype_count = world.stats().archetype_count;
+        for i in 0..100_000 {
+            entity.add_comp(Velocity::new(i, i));
+            entity.comp::<Velocity>(|vel| assert_eq!(vel.x, i));
+            entity.remove_comp::<Velocity>();
+            assert!(!entity.has_comp::<Velocity>());
+        }
+        entity.comp::<Position>(|pos| assert_eq!((pos.x, pos.y), (1, 2)));
+        assert_eq!(world.stats().archetype_count, archetype_count);
+    }
+
+    #[test]
+    fn removed_archetype_edges() {
+        use crate::world::{archetypes, archetypes_mut};
+
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let entity = world.add_entity().add_comp(Position::new(1, 2));
+        let archetype_id = |entity: Entity| archetypes(|a| a.record(entity.0).unwrap().arhetype_id);
+        for i in 0..3 {
+            entity.add_comp(Velocity::new(i, i));
+            let intermediate = archetype_id(entity);
+            entity.remove_comp::<Velocity>();
+            archetypes_mut(|a| a.remove_archetype(intermediate)).unwrap();
+            assert!(!archetypes(|a| a.archetype_exists(intermediate)));
+
+            entity.add_comp(Velocity::new(i, i));
+            assert_ne!(archetype_id(entity), intermediate);
+            entity.comps::<(&Position, &Velocity)>(|(pos, vel)| {
+                assert_eq!((pos.x, pos.y), (1, 2));
+                assert_eq!(vel.x, i);
+            });
+            entity.remove_comp::<Velocity>();
+        }
+        let occupied = archetype_id(entity);
+        assert!(archetypes_mut(|a| a.remove_archetype(occupied)).is_err());
+    }
+
+    #[test]
+    fn removed_archetype_queries() {
+        use crate::world::{archetypes, archetypes_mut};
+
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let mut query = world.query::<(&Position, &Velocity)>().build();
+        let entity = world
+            .add_entity()
+            .add_comp(Position::new(1, 2))
+            .add_comp(Velocity::new(3, 4));
+        let removed = archetypes(|a| a.record(entity.0).unwrap().arhetype_id);
+        assert_eq!(query.iter().count(), 1);
+
+        entity.remove_comp::<Velocity>();
+        archetypes_mut(|a| a.remove_archetype(removed)).unwrap();
+        assert!(query
+            .storage
+            .borrow()
+            .archetypes
+            .iter()
+            .all(|a| a.borrow().id() != removed));
+        assert_eq!(query.iter().count(), 0);
+
+        entity.add_comp(Velocity::new(5, 6));
+        assert_eq!(query.iter().count(), 1);
+    }
+
+    #[test]
+    fn column_iter() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let entity = world.add_entity().add_comp(Position::new(0, 0));
+        for i in 1..100 {
+            world.add_entity().add_comp(Position::new(i, 0));
+        }
+        let query_sum: i32 = world
+            .query::<&Position>()
+            .build()
+            .iter()
+            .map(|pos| pos.x)
+            .sum();
+        let column_sum: i32 = archetypes(|a| {
+            let record = a.record(entity.0).unwrap();
+            let archetype = a.archetype_from_record(&record).unwrap().borrow();
+            let table = archetype.table().borrow();
+            let storage = table.storage(a.component_id::<Position>()).unwrap();
+            let storage = storage.borrow();
+            assert_eq!(storage.iter_ptr(10..20).count(), 10);
+            //SAFETY: the storage was looked up by Position's id
+            unsafe { storage.iter::<Position>() }.map(|pos| pos.x).sum()
+        });
+        assert_eq!(query_sum, (0..100).sum::<i32>());
+        assert_eq!(column_sum, query_sum);
+    }
+
+    #[test]
+    fn compact_records() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let parent = world.add_entity_named("parent");
+        let entities: Vec<_> = (0..1000)
+            .map(|i| world.add_entity().add_comp(Position::new(i, 0)))
+            .collect();
+        let mut alive = vec![];
+        for (i, entity) in entities.into_iter().enumerate() {
+            if i % 10 == 0 {
+                alive.push((i as i32, entity));
+            } else {
+                entity.remove();
+            }
+        }
+        let child = world.add_entity_named("child").add_child_of(parent);
+        for _ in world.query::<&Position>().build().iter().take(1) {
+            assert!(world.compact_records().is_err());
+        }
+        let remap = world.compact_records().unwrap();
+        assert!(!remap.is_empty());
+        assert!(world.compact_records().unwrap().is_empty());
+        let alive: Vec<_> = alive
+            .into_iter()
+            .map(|(i, e)| (i, remap.get(&e).copied().unwrap_or(e)))
+            .collect();
+        let child = remap.get(&child).copied().unwrap_or(child);
+
+        let mut query = world.query::<(&Entity, &Position)>().build();
+        assert_eq!(query.iter().count(), alive.len());
+        for (i, entity) in alive.iter() {
+            assert!(entity.is_alive());
+            entity.comp::<Position>(|pos| assert_eq!(pos.x, *i));
+        }
+        for (old, _) in remap.iter() {
+            assert!(!old.is_alive());
+        }
+        let found = world.entity_by_global_name("parent").unwrap();
+        assert_eq!(found.0.low32(), parent.0.low32());
+        let child_name =
+            crate::archetypes::NameRight::new("child".into(), parent.0.low32() as usize);
+        let found = crate::world::archetypes(|a| a.entity_by_name(&child_name));
+        assert_eq!(found.unwrap().low32(), child.0.low32());
+        let new_entity = world.add_entity();
+        assert!(new_entity.0.low32() < 500);
+    }
+
+    #[test]
+    fn many_short_queries() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<(Position, Velocity)>();
+        let entity = world.add_entity().add_comp(Position::new(1, 2));
+        for i in 0..10_000 {
+            let mut query = world.query::<&Position>().build();
+            let mut iter = query.iter();
+            assert!(archetypes(|a| a.is_locked()));
+            assert_eq!(iter.next().map(|pos| pos.x), Some(1));
+            assert!(iter.next().is_none());
+            drop(iter);
+            assert!(!archetypes(|a| a.is_locked()));
+            if i == 0 {
+                for entity in world
+                    .query::<&Entity>()
+                    .with_comp::<Position>()
+                    .build()
+                    .iter()
+                {
+                    entity.add_comp(Velocity::new(3, 4));
+                }
+                assert!(entity.has_comp::<Velocity>());
+            }
+        }
+    }
+
+    #[test]
+    fn reserve_relationship() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<(Owes, Apples)>();
+        world.reserve_relationship::<Owes, Apples>(1000);
+        let capacity = |entity: Entity| {
+            archetypes(|a| {
+                let record = a.record(entity.0).unwrap();
+                let archetype = a.archetype_from_record(&record).unwrap().borrow();
+                let table = archetype.table().borrow();
+                let relationship = crate::archetypes::Archetypes::relationship_id(
+                    a.component_id::<Owes>(),
+                    a.component_id::<Apples>(),
+                );
+                let capacity = table.storage(relationship).unwrap().borrow().capacity();
+                capacity
+            })
+        };
+        let mut reallocations = 0;
+        let mut last_capacity = None;
+        for i in 0..1000 {
+            let entity = world
+                .add_entity()
+                .add_rel_first::<_, Apples>(Owes { amount: i });
+            let capacity = capacity(entity);
+            if last_capacity.is_some_and(|c| c != capacity) {
+                reallocations += 1;
+            }
+            last_capacity = Some(capacity);
+        }
+        assert_eq!(reallocations, 0);
+        assert!(last_capacity.unwrap() >= 1000);
+    }
+
+    #[test]
+    fn compact_records_keeps_dead_handles_dead() {
+        let world = World::new();
+        let entities: Vec<_> = (0..100).map(|_| world.add_entity()).collect();
+        for entity in entities[1..].iter() {
+            entity.remove();
+        }
+        let first = entities[0];
+        let last = *entities.last().unwrap();
+        let remap = world.compact_records().unwrap();
+        let first = remap.get(&first).copied().unwrap_or(first);
+        assert!(first.is_alive());
+
+        let spawned: Vec<_> = (0..200).map(|_| world.add_entity()).collect();
+        for entity in entities[1..].iter().chain(remap.keys()) {
+            assert!(!entity.is_alive());
+        }
+        assert!(!last.is_alive());
+        assert!(first.is_alive());
+        assert!(spawned.iter().all(|e| e.is_alive()));
+    }
+
+    #[test]
+    fn defragment_tables() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<(Position, IsCool)>();
+        let position_tables = || {
+            archetypes(|a| {
+                let components = std::collections::BTreeSet::from([a.component_id::<Position>()]);
+                a.tables()
+                    .filter(|t| t.borrow().component_ids() == &components)
+                    .count()
+            })
+        };
+        let first = world.add_entity().add_comp(Position::new(1, 1));
+        let second = world
+            .add_entity()
+            .add_tag::<IsCool>()
+            .add_comp(Position::new(2, 2));
+        let third = world.add_entity().add_comp(Position::new(3, 3));
+        assert_eq!(position_tables(), 2);
+
+        for _ in world.query::<&Position>().build().iter().take(1) {
+            assert!(world.defragment_tables().is_err());
+        }
+        assert_eq!(position_tables(), 2);
+        assert_eq!(world.defragment_tables().unwrap(), 1);
+        assert_eq!(position_tables(), 1);
+        for (entity, x) in [(first, 1), (second, 2), (third, 3)] {
+            entity.comp::<Position>(|pos| assert_eq!(pos.x, x));
+        }
+        let mut query = world.query::<&Position>().build();
+        assert_eq!(query.iter().map(|pos| pos.x).sum::<i32>(), 6);
+
+        second.remove_tag::<IsCool>();
+        first.remove_comp::<Position>();
+        second.comp::<Position>(|pos| assert_eq!(pos.x, 2));
+        third.comp::<Position>(|pos| assert_eq!(pos.x, 3));
+    }
+
+    #[test]
+    fn sparse_components_survive_compaction() {
+        let world = World::new()
+            .register_sparse_component::<Velocity>()
+            .register_sparse_component::<IsCool>();
+        world.register_components::<Position>();
+        let entities: Vec<_> = (0..100)
+            .map(|i| world.add_entity().add_comp(Position::new(i, i)))
+            .collect();
+        for entity in entities[..90].iter() {
+            entity.remove();
+        }
+        let sparse_only = world.add_entity().add_comp(Velocity::new(7, 7));
+        for (i, entity) in entities[90..].iter().enumerate() {
+            entity
+                .add_comp(Velocity::new(i as i32, 0))
+                .add_tag::<IsCool>();
+        }
+
+        let remap = world.compact_records().unwrap();
+        assert!(!remap.is_empty());
+        for (i, entity) in entities[90..].iter().enumerate() {
+            let entity = remap.get(entity).copied().unwrap_or(*entity);
+            assert!(entity.has_comp::<Velocity>() && entity.has_tag::<IsCool>());
+            entity.comp::<Velocity>(|vel| assert_eq!(vel.x, i as i32));
+        }
+        let sparse_only = remap.get(&sparse_only).copied().unwrap_or(sparse_only);
+        sparse_only.comp::<Velocity>(|vel| assert_eq!(vel.x, 7));
+        let mut query = world.query::<(&Velocity, &Position)>().build();
+        assert_eq!(query.iter().count(), 10);
+
+        sparse_only.remove_comp::<Velocity>();
+        assert!(!sparse_only.has_comp::<Velocity>());
+        let last = remap.get(&entities[99]).copied().unwrap_or(entities[99]);
+        last.comp::<Velocity>(|vel| assert_eq!(vel.x, 9));
+    }
+
+    #[test]
+    fn sparse_components() {
+        let world = World::new()
+            .register_sparse_component::<Velocity>()
+            .register_sparse_component::<IsCool>();
+        world.register_components::<(Position, Apples)>();
+        let entities: Vec<_> = (0..4)
+            .map(|i| world.add_entity().add_comp(Position::new(i, i)))
+            .collect();
+
+        let before = world.stats().archetype_count;
+        for (i, entity) in entities.iter().enumerate() {
+            entity.add_comp(Velocity::new(i as i32, 0));
+            if i % 2 == 0 {
+                entity.add_tag::<IsCool>();
+            }
+        }
+        assert_eq!(world.stats().archetype_count, before);
+        assert!(entities[0].has_comp::<Velocity>());
+        assert!(entities[0].has_tag::<IsCool>());
+        assert!(!entities[1].has_tag::<IsCool>());
+        entities[3].comp::<Velocity>(|vel| assert_eq!(vel.x, 3));
+
+        let mut query = world.query::<(&Position, &mut Velocity)>().build();
+        for (pos, mut vel) in query.iter() {
+            vel.y = pos.x * 10;
+        }
+        let mut query = world.query::<(&Velocity, &Entity)>().build();
+        let sum = query.iter().map(|(vel, _)| vel.y).sum::<i32>();
+        assert_eq!(sum, 60);
+        let cool = query.iter().filter(|(_, e)| e.has_tag::<IsCool>()).count();
+        assert_eq!(cool, 2);
+
+        entities[1].remove_comp::<Velocity>();
+        entities[2].remove();
+        let mut query = world.query::<&Velocity>().build();
+        assert_eq!(query.iter().map(|vel| vel.x).sum::<i32>(), 3);
+        assert_eq!(world.stats().archetype_count, before);
+
+        //the same toggling with a dense component creates new archetypes
+        for entity in [entities[0], entities[1]] {
+            entity.add_tag::<Apples>();
+            entity.remove_tag::<Apples>();
+        }
+        assert!(world.stats().archetype_count > before);
+    }
+
+    #[test]
+    fn table_rows_after_moves() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<(Position, Velocity, IsCool)>();
+        //creates the (IsCool, Position) archetype with its own table
+        let first = world
+            .add_entity()
+            .add_tag::<IsCool>()
+            .add_comp(Position::new(1, 1));
+        //reaches the same archetype through a tag, coming from another table
+        let second = world
+            .add_entity()
+            .add_comp(Position::new(2, 2))
+            .add_comp(Velocity::new(2, 2))
+            .remove_comp::<Velocity>()
+            .add_tag::<IsCool>();
+
+        let table_len = |entity: Entity| {
+            archetypes(|a| {
+                let record = a.record(entity.0).unwrap();
+                let archetype = a.archetype_by_id(record.arhetype_id).borrow();
+                let len = archetype.table().borrow().len();
+                len
+            })
+        };
+        for entity in [first, second] {
+            let row = archetypes(|a| a.entity_table_row(entity.0)).unwrap();
+            assert!(row.0 < table_len(entity));
+        }
+        first.comp::<Position>(|pos| assert_eq!(pos.x, 1));
+        second.comp::<Position>(|pos| assert_eq!(pos.x, 2));
+        first.remove_tag::<IsCool>();
+        second.comp::<Position>(|pos| assert_eq!(pos.x, 2));
+    }
+
+    #[test]
+    fn compact() {
+        let world = World::new();
+        world.register_components::<Position>();
+        let entities: Vec<_> = (0..1000)
+            .map(|i| world.add_entity().add_comp(Position::new(i, i)))
+            .collect();
+        for entity in entities[10..].iter() {
+            entity.remove();
+        }
+        let bytes_before = world.stats().total_component_bytes;
+        for _ in world.query::<&Position>().build().iter() {
+            assert!(world.compact().is_err());
+        }
+        assert_eq!(world.stats().total_component_bytes, bytes_before);
+        world.compact().unwrap();
+        assert!(world.stats().total_component_bytes < bytes_before);
+
+        for (i, entity) in entities[..10].iter().enumerate() {
+            entity.comp::<Position>(|pos| assert_eq!(pos.x, i as i32));
+        }
+        world.add_entity().add_comp(Position::new(7, 7));
+    }
+
+    #[test]
+    fn memory_report() {
+        let world = World::new();
+        world.register_components::<(Position, Velocity, Name)>();
+        for i in 0..100 {
+            let entity = world.add_entity().add_comp(Position::new(i, i));
+            if i % 2 == 0 {
+                entity.add_comp(Name {
+                    value: "name".into(),
+                });
+            }
+        }
+        world.add_entity().add_comp(Velocity::new(0, 0));
+
+        let report = world.memory_report();
+        let components_bytes: usize = report.components.iter().map(|(_, bytes, _)| bytes).sum();
+        assert!(report.total_bytes >= components_bytes);
+        let report = report.components;
+        let (name, bytes, count) = &report[0];
+        assert!(name.contains("Name"));
+        assert_eq!(*count, 50);
+        assert_eq!(*bytes, 50 * std::mem::size_of::<Name>());
+        let position = report.iter().find(|(name, ..)| name.contains("Position"));
+        assert_eq!(position.unwrap().2, 100);
+        let velocity = report.iter().find(|(name, ..)| name.contains("Velocity"));
+        assert_eq!(velocity.unwrap().2, 1);
+        assert!(report.windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+
+    #[test]
+    fn relationship_payload_drops() {
+        use std::cell::Cell;
+
+        thread_local! {
+            static DROPS: Cell<usize> = const { Cell::new(0) };
+        }
+        impl_component! {
+            pub struct Payload(i32);
+        }
+        impl Drop for Payload {
+            fn drop(&mut self) {
+                DROPS.set(DROPS.get() + 1);
+            }
+        }
+
+        let world = World::new();
+        world.register_components::<(Begin, Position, Payload)>();
+        let entities: Vec<_> = (0..4)
+            .map(|i| {
+                world
+                    .add_entity()
+                    .add_comp(Position::new(i, i))
+                    .add_rel_second::<Begin, Payload>(Payload(i))
+            })
+            .collect();
+        let target = world.add_entity();
+        let holder = world.add_entity().add_mixed_rel(target, Payload(10));
+        assert_eq!(DROPS.get(), 0);
+
+        entities[0].remove();
+        entities[2].remove();
+        assert_eq!(DROPS.get(), 2);
+        entities[1]
+            .rel_second::<Begin, Payload>()
+            .get(|p| assert_eq!(p.0, 1));
+        entities[3]
+            .rel_second::<Begin, Payload>()
+            .get(|p| assert_eq!(p.0, 3));
+
+        //removing the target removes the relationship from the holder
+        target.remove();
+        assert!(!holder.has_mixed_rel::<Payload>(target));
+        assert_eq!(DROPS.get(), 3);
+
+        crate::world::drop_archetypes();
+        assert_eq!(DROPS.get(), 5);
+    }
+
+    #[test]
+    fn world_with_capacity() {
+        use crate::world::archetypes;
+
+        let capacity = 20_000;
+        let world = World::with_capacity(capacity);
+        world.register_components::<Position>();
+        let records = archetypes(|a| a.records_capacity());
+        assert_eq!(records, capacity);
+        let spawned = capacity - world.stats().entity_count;
+        for i in 0..spawned {
+            world.add_entity().add_comp(Position::new(i as i32, 0));
+        }
+        assert_eq!(world.stats().entity_count, capacity);
+        assert_eq!(archetypes(|a| a.records_capacity()), records);
+    }
+
+    #[test]
+    fn archetype_match_cache() {
+        use crate::world::archetypes;
+
+        let world = World::new();
+        world.register_components::<Position>();
+        let tags: Vec<_> = (0..100).map(|_| world.add_entity()).collect();
+        let entities: Vec<_> = tags
+            .iter()
+            .map(|tag| {
+                world
+                    .add_entity()
+                    .add_comp(Position::new(0, 0))
+                    .add_ent_tag(*tag)
+            })
+            .collect();
+        let queries: Vec<_> = tags
+            .iter()
+            .map(|tag| world.query::<&Position>().with_ent_tag(*tag).build())
+            .collect();
+
+        let (hits, misses) = archetypes(|a| (a.match_cache().hits, a.match_cache().misses));
+        for (i, query) in queries.iter().enumerate() {
+            for (j, entity) in entities.iter().enumerate() {
+                assert_eq!(query.matches_entity(*entity), i == j);
+            }
+        }
+        let cache = archetypes(|a| (a.match_cache().hits, a.match_cache().misses));
+        assert_eq!(cache.1, misses);
+        assert_eq!(cache.0 - hits, queries.len() * entities.len());
+
+        //equal masks built separately share the cached results
+        let query = world.query::<&Position>().with_ent_tag(tags[0]).build();
+        assert!(query.matches_entity(entities[0]));
+        assert_eq!(archetypes(|a| a.match_cache().misses), misses);
+    }
+
+    #[test]
+    fn entity_path() {
+        let world = World::new();
+        let root = world.add_entity_named("Root");
+        let enemies = world.add_entity().add_child_of(root).set_name("Enemies");
+        let goblin = world.add_entity().add_child_of(enemies).set_name("Goblin");
+        assert_eq!(goblin.path().as_deref(), Some("Root/Enemies/Goblin"));
+        assert_eq!(root.path().as_deref(), Some("Root"));
+
+        let unnamed = world.add_entity().add_child_of(goblin);
+        assert_eq!(unnamed.path(), None);
+        let orphan = world
+            .add_entity()
+            .add_child_of(world.add_entity())
+            .set_name("Orphan");
+        assert_eq!(orphan.path(), None);
+        let middle = world.add_entity().add_child_of(root);
+        let leaf = world.add_entity().add_child_of(middle).set_name("Leaf");
+        assert_eq!(leaf.path(), None);
+    }
+
+    #[test]
+    fn entity_by_path() {
+        let world = World::new();
+        let root = world.add_entity_named("Root");
+        let child = world.add_entity().add_child_of(root).set_name("Child");
+        let grandchild = world
+            .add_entity()
+            .add_child_of(child)
+            .set_name("Grandchild");
+
+        //stored ids can carry relationship flags, so compare indices
+        let index = |path: &str| world.entity_by_path(path).map(|e| e.0.low32());
+        assert_eq!(index("Root"), Some(root.0.low32()));
+        assert_eq!(index("Root/Child"), Some(child.0.low32()));
+        assert_eq!(index("Root/Child/Grandchild"), Some(grandchild.0.low32()));
+        assert_eq!(index("Root/Grandchild"), None);
+        assert_eq!(index("Child/Grandchild"), None);
+        assert_eq!(index("Root/Child/Nobody"), None);
+        for path in ["", "/Root", "Root/", "Root//Child"] {
+            assert_eq!(index(path), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn name_parent_collision() {
+        use crate::archetypes::NameLeft;
+        use crate::world::archetypes_mut;
+
+        let world = World::new();
+        let parent = world.add_entity_named("Parent");
+        let child = world.add_entity().add_child_of(parent).set_name("Child");
+        let other = world.add_entity_named("Child");
+
+        let result =
+            archetypes_mut(|a| a.set_entity_name_parent(NameLeft::global(other.0), parent.0));
+        assert!(result.is_err());
+        let index = |path: &str| world.entity_by_path(path).map(|e| e.0.low32());
+        assert_eq!(index("Child"), Some(other.0.low32()));
+        assert_eq!(index("Parent/Child"), Some(child.0.low32()));
+
+        let moved = world.add_entity_named("Moved");
+        archetypes_mut(|a| a.set_entity_name_parent(NameLeft::global(moved.0), parent.0)).unwrap();
+        assert_eq!(index("Moved"), None);
+        assert_eq!(index("Parent/Moved"), Some(moved.0.low32()));
+        //the old global name is free again
+        world.add_entity_named("Moved");
+    }
+
+    #[test]
+    fn reparent() {
+        let world = World::new();
+        let first = world.add_entity_named("First");
+        let second = world.add_entity_named("Second");
+        world.add_entity().add_child_of(first).set_name("Sibling");
+        world.add_entity().add_child_of(second).set_name("Other");
+        let child = world.add_entity().add_child_of(first).set_name("Child");
+
+        child.reparent(second).unwrap();
+        assert!(child.is_child_of(second));
+        assert!(!child.is_child_of(first));
+        let index = |path: &str| world.entity_by_path(path).map(|e| e.0.low32());
+        assert_eq!(index("Second/Child"), Some(child.0.low32()));
+        assert_eq!(index("First/Child"), None);
+        //the old scope is free again
+        world.add_entity().add_child_of(first).set_name("Child");
+
+        let clash = world.add_entity().add_child_of(first).set_name("Other");
+        assert!(clash.reparent(second).is_err());
+        assert!(clash.is_child_of(first));
+        assert_eq!(index("First/Other"), Some(clash.0.low32()));
+
+        let orphan = world.add_entity_named("Orphan");
+        orphan.reparent(first).unwrap();
+        assert_eq!(index("Orphan"), None);
+        assert_eq!(index("First/Orphan"), Some(orphan.0.low32()));
+    }
+
+    #[test]
+    fn rename() {
+        let world = World::new();
+        let parent = world.add_entity_named("Parent");
+        let first = world.add_entity().add_child_of(parent).set_name("First");
+        let second = world.add_entity().add_child_of(parent).set_name("Second");
+
+        assert!(second.rename("First").is_err());
+        first.rename("Renamed").unwrap();
+        first.name().get(|name| assert_eq!(name, "Renamed"));
+        let index = |path: &str| world.entity_by_path(path).map(|e| e.0.low32());
+        assert_eq!(index("Parent/First"), None);
+        assert_eq!(index("Parent/Renamed"), Some(first.0.low32()));
+
+        second.rename("First").unwrap();
+        assert_eq!(index("Parent/First"), Some(second.0.low32()));
+        assert!(first.rename("First").is_err());
+        let third = world.add_entity().add_child_of(parent);
+        third.set_name("Second");
+    }
+
+    #[test]
+    fn named_children() {
+        let world = World::new();
+        let parent = world.add_entity_named("Parent");
+        let children: Vec<_> = ["A", "B", "C"]
+            .iter()
+            .map(|name| world.add_entity().add_child_of(parent).set_name(name))
+            .collect();
+        world.add_entity().add_child_of(parent);
+        world.add_entity_named("Global");
+
+        let mut named: Vec<_> = world
+            .named_children(&parent)
+            .into_iter()
+            .map(|(name, entity)| (name, entity.0.low32()))
+            .collect();
+        named.sort();
+        let expected: Vec<_> = ["A", "B", "C"]
+            .iter()
+            .zip(children.iter())
+            .map(|(name, entity)| (SmolStr::new(name), entity.0.low32()))
+            .collect();
+        assert_eq!(named, expected);
+        assert!(world.named_children(&children[0]).is_empty());
+    }
+
+    #[test]
+    fn unique_names() {
+        let world = World::new();
+        let parent = world.add_entity();
+        let names: Vec<_> = (0..3)
+            .map(|_| {
+                let entity = world
+                    .add_entity()
+                    .add_child_of(parent)
+                    .set_name_unique("Cube");
+                entity.name().get(|name| name.to_string())
+            })
+            .collect();
+        assert_eq!(names, ["Cube", "Cube_1", "Cube_2"]);
+
+        let global = world.add_entity().set_name_unique("Cube");
+        global.name().get(|name| assert_eq!(name, "Cube"));
+        global.set_name_unique("Cube");
+        global.name().get(|name| assert_eq!(name, "Cube"));
+    }
+
+    #[test]
+    fn display_name() {
+        let world = World::new();
+        world.register_components::<Position>();
+        let named = world.add_entity_named("Named");
+        assert_eq!(named.display_name(), "Named");
+        let child = world.add_entity().add_child_of(named).set_name("Child");
+        assert_eq!(child.display_name(), "Child");
+
+        let unnamed = world.add_entity();
+        assert_eq!(unnamed.display_name(), unnamed.debug_name());
+        assert!(!unnamed.display_name().is_empty());
+        let component = world.comp_entity::<Position>();
+        assert!(component.display_name().contains("Position"));
+    }
+
+    #[test]
+    fn name_change_callbacks() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let world = World::new();
+        let changes = Rc::new(RefCell::new(vec![]));
+        world.on_name_change({
+            let changes = changes.clone();
+            move |entity, old, new| {
+                let old = old.map(|old| old.to_string());
+                changes
+                    .borrow_mut()
+                    .push((entity.0.low32(), old, new.to_string()));
+            }
         });
-        entity.mixed_rel::<Position>(idkwhat).get(|pos| {
-            assert_eq!(pos.x, 5);
-            assert_eq!(pos.y, 6);
+        let entity = world.add_entity_named("Old");
+        entity.rename("New").unwrap();
+        entity.remove_name();
+
+        let index = entity.0.low32();
+        assert_eq!(
+            *changes.borrow(),
+            [
+                (index, None, "Old".to_string()),
+                (index, Some("Old".to_string()), "New".to_string()),
+                (index, Some("New".to_string()), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn name_callbacks_use_the_world() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let world = World::new();
+        let paths = Rc::new(RefCell::new(vec![]));
+        world.on_name_change({
+            let paths = paths.clone();
+            move |entity, _, _| paths.borrow_mut().push(entity.path())
         });
-        entity.remove_comp::<Position>();
-        assert!(!entity.has_comp::<Position>());
-        entity.remove_tag::<IsCool>();
-        assert!(!entity.has_tag::<IsCool>());
-        entity.remove_ent_tag(entity_tag);
-        assert!(!entity.has_ent_tag(entity_tag));
-        entity.remove_ent_rel(hates, bob);
-        assert!(!entity.has_ent_rel(hates, bob));
-        entity.remove_rel::<Likes, Apples>();
-        assert!(!entity.has_rel::<Likes, Apples>());
-        entity.remove_rel::<Owes, Apples>();
-        assert!(!entity.has_rel::<Owes, Apples>());
-        entity.remove_rel::<Begin, Position>();
-        assert!(!entity.has_rel::<Begin, Position>());
-        entity.remove_mixed_rel::<Position>(idkwhat);
-        assert!(!entity.has_mixed_rel::<Position>(idkwhat));
-        entity.remove_mixed_rel::<Likes>(bob);
-        assert!(!entity.has_mixed_rel::<Position>(idkwhat));
+        world.add_entity_named("Old").rename("New").unwrap();
+        assert_eq!(
+            *paths.borrow(),
+            [Some("Old".to_string()), Some("New".to_string())]
+        );
     }
 
     #[test]
-    fn data_rels_first() {
+    fn panicking_callback_keeps_the_queue_running() {
+        use std::cell::RefCell;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::rc::Rc;
+
         let world = World::new();
-        world.register_components::<(Owes, Apples, Oranges)>();
-        let ann = world.add_entity();
-        ann.add_rel_first::<Owes, Apples>(Owes { amount: 10 });
-        ann.add_rel_first::<Owes, Oranges>(Owes { amount: 10 });
-        ann.remove_rel::<Owes, Apples>();
-        assert!(!ann.has_rel::<Owes, Apples>());
-        assert!(ann.has_rel::<Owes, Oranges>());
+        let names = Rc::new(RefCell::new(vec![]));
+        world.on_name_change({
+            let names = names.clone();
+            move |_, _, new_name| {
+                if new_name == "Panic" {
+                    panic!("callback failed");
+                }
+                names.borrow_mut().push(new_name.to_string());
+            }
+        });
+        let result = catch_unwind(AssertUnwindSafe(|| world.add_entity_named("Panic")));
+        assert!(result.is_err());
+        world.add_entity_named("Fine");
+        assert_eq!(*names.borrow(), ["Fine"]);
     }
 
     #[test]
-    fn data_rels_second() {
+    fn try_set_name() {
         let world = World::new();
-        world.register_components::<(Begin, Position, End, Apples)>();
-        let entity = world.add_entity();
-        entity
-            .add_rel_second::<Begin, _>(Position::new(1, 2))
-            .add_rel_second::<End, _>(Position::new(3, 4));
-        let p1 = entity.rel_second::<Begin, Position>().get(|c| *c);
-        let p2 = entity.rel_second::<End, Position>().get(|c| *c);
+        let parent = world.add_entity_named("Parent");
+        let child = world.add_entity().add_child_of(parent).set_name("Child");
+        let other = world.add_entity().add_child_of(parent);
 
-        assert!(entity.has_rel::<Begin, Position>());
-        assert!(entity.has_rel::<End, Position>());
-        assert!(!entity.has_rel::<Apples, Position>());
+        let Err(NameError::Duplicate(name, parent_index)) = other.try_set_name("Child") else {
+            panic!("expected a duplicate name error");
+        };
+        assert_eq!(name, "Child");
+        assert_eq!(parent_index, parent.0.low32() as usize);
+        assert!(!other.has_name());
+        assert!(world.add_entity().try_set_name("Child").is_ok());
+        assert_eq!(child.name().get(|name| name.to_owned()), "Child");
 
-        assert_eq!(p1.x, 1);
-        assert_eq!(p1.y, 2);
-        assert_eq!(p2.x, 3);
-        assert_eq!(p2.y, 4);
+        let entity_count = world.stats().entity_count;
+        let result = world.deserialize_entity(r#"{"Name": "Parent"}"#);
+        assert!(matches!(
+            result,
+            Err(crate::entity_parser::ParseError::Name(_))
+        ));
+        assert_eq!(world.stats().entity_count, entity_count);
     }
 
     #[test]
-    fn tag_rels() {
-        let world = World::new();
-        world.register_components::<(Likes, Apples, Position)>();
-        let entity = world.add_entity();
-        entity.add_rel::<Likes, Apples>();
-        entity.add_comp(Position { x: 10, y: 20 });
+    fn name_conflicts() {
+        use crate::archetypes::{NameLeft, NameRight};
 
-        let pos = entity.comp_ret(|c: &Position| *c);
-        assert_eq!(pos.x, 10);
-        assert_eq!(pos.y, 20);
+        let world = World::new();
+        let parent = world.add_entity();
+        let first = world.add_entity();
+        first.add_child_of(parent).set_name("Child");
+        world.add_entity_named("Other");
+        assert!(world.name_conflicts().is_empty());
+
+        let second = world.add_entity();
+        let parent_index = parent.0.low32() as usize;
+        archetypes_mut(|a| {
+            let left = NameLeft::new(second.0.low32() as usize, parent_index);
+            a.names_mut()
+                .insert(left, NameRight::global("Child".into()));
+        });
 
-        assert!(entity.has_rel::<Likes, Apples>());
+        let conflicts = world.name_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        let (parent_id, name, entities) = &conflicts[0];
+        assert_eq!(*parent_id, parent.0.low32());
+        assert_eq!(name, "Child");
+        let entities: Vec<_> = entities.iter().map(|e| e.0.low32()).collect();
+        assert_eq!(entities, [first.0.low32(), second.0.low32()]);
     }
+
     #[test]
-    fn adding_comps_to_comps() {
-        // impl_component! {
-        //     struct Material {
-        //         data: u32,
-        //     }
-        // }
-        // impl_component! {
-        //     struct Uniform {
-        //         other_data: u32,
-        //     }
-        // }
-        // impl_component! {
-        //     struct HasMaterial {}
-        // }
-        // //for some reason has_components(COMPONENT_ID, HasMaterial) return true before IsComponent is added
-        // let world = World::new();
-        // world.comp_entity::<HasMaterial>();
-        // // let comp = world.comp_entity::<Uniform>();
-        // // comp.add_comp(Material { data: 5 });
-        // // dbg!("begin");
-        // // let e = world.comp_entity::<HasMaterial>();
-        // // dbg!(archetypes(|a| a.debug_print_archetypes()));
-        // // dbg!(archetypes(|a| a.record(e.into()).unwrap().arhetype_id));
-        // let entity = world.add_entity().add_tag::<HasMaterial>();
+    fn name_handle() {
+        let world = World::new();
+        let entity = world.add_entity_named("Cached");
+        let mut handle = entity.name_handle();
+        for _ in 0..3 {
+            assert_eq!(handle.get(|name| name.to_string()).unwrap(), "Cached");
+        }
+
+        let parent = world.add_entity();
+        entity.add_child_of(parent);
+        assert!(handle.is_stale());
+        assert!(handle.get(|name| name.to_string()).is_none());
+
+        handle.refresh();
+        assert!(!handle.is_stale());
+        assert_eq!(handle.get(|name| name.to_string()).unwrap(), "Cached");
+        handle.set("Renamed").unwrap();
+        assert_eq!(entity.name().get(|name| name.to_string()), "Renamed");
     }
 
     #[test]
-    fn bevy_reflection() {
-        #[derive(Debug, Reflect)]
-        struct SomeValues {
-            string: String,
-            int: i32,
+    fn enum_tag_variant_names() {
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum Weather {
+                Sunny,
+                Rainy,
+                Snowy,
+            }
         }
 
-        let mut dynamic_struct = DynamicStruct::default();
-        dynamic_struct.insert("string".to_string(), "A string!".to_string());
-        dynamic_struct.insert("int".to_string(), 25);
-        let some_values = SomeValues::from_reflect(&dynamic_struct);
-        dbg!(some_values);
+        assert_eq!(Weather::variant_names(), ["Sunny", "Rainy", "Snowy"]);
+        for variant in [Weather::Sunny, Weather::Rainy, Weather::Snowy] {
+            let name = Weather::variant_name(variant.id()).unwrap();
+            assert_eq!(name, format!("{variant:?}"));
+        }
+        assert_eq!(Weather::variant_name(100), None);
+
+        let world = World::new();
+        world.register_components::<Weather>();
+        //names are known as soon as the type is registered
+        let parsed = world
+            .deserialize_entity(r#"{"Tags": ["Weather::Snowy"]}"#)
+            .unwrap();
+        assert_eq!(parsed.get_enum_tag::<Weather>(), Some(Weather::Snowy));
+        let entity = world.add_entity().add_enum_tag(Weather::Rainy);
+        let json: serde_json::Value = serde_json::from_str(&entity.serialize().unwrap()).unwrap();
+        let tags = json["Tags"].as_array().unwrap();
+        assert!(tags
+            .iter()
+            .any(|tag| tag.as_str().unwrap().ends_with("Weather::Rainy")));
+
+        let json = entity.serialize().unwrap();
+        entity.remove();
+        let entity = world.deserialize_entity(&json).unwrap();
+        assert_eq!(entity.get_enum_tag::<Weather>(), Some(Weather::Rainy));
+        assert!(world
+            .deserialize_entity(r#"{"Tags": ["Weather::Windy"]}"#)
+            .is_err());
     }
+
     #[test]
-    fn deserialization() {
-        let json = json!(
-        {
-            "Name": "John",
-            "($Owes, Apples)": {
-                "amount": 10
-            },
-            "(Begin, $Position)": {
-                "x": 2,
-                "y": 3
-            },
-            "Position": {
-                "x": 1,
-                "y": 2
-            },
-            "Tags": [
-                "#Enemy",
-                "IsCool",
-                "(Likes, #Enemy)",
-                "(Likes, Apples)",
+    fn iter_enum_tags() {
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum Mood {
+                Happy,
+                Grumpy,
+            }
+        }
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum Stance {
+                Standing,
+                Crouching,
+            }
+        }
+
+        let world = World::new();
+        world.register_components::<(Mood, Stance)>();
+        let entity = world.add_entity().add_enum_tag(Mood::Grumpy);
+        assert_eq!(entity.enum_tag_or(Stance::Standing), Stance::Standing);
+        entity.add_enum_tag(Stance::Crouching);
+        assert_eq!(entity.enum_tag_or(Stance::Standing), Stance::Crouching);
+
+        let mut tags: Vec<_> = entity
+            .iter_enum_tags()
+            .map(|(type_name, variant)| {
+                let type_name = type_name.rsplit("::").next().unwrap().to_string();
+                (type_name, variant)
+            })
+            .collect();
+        tags.sort();
+        assert_eq!(
+            tags,
+            [
+                ("Mood".to_string(), "Grumpy"),
+                ("Stance".to_string(), "Crouching")
             ]
-        });
+        );
+    }
+
+    #[test]
+    fn enum_tag_any_queries() {
+        enum_tag! {
+            #[derive(Debug, Eq, PartialEq)]
+            enum PlayerState {
+                Idle,
+                Walking,
+                Running,
+            }
+        }
+
         let world = World::new();
-        world.register_components::<(Likes, Apples, IsCool, Position, Begin, Owes)>();
-        let entity = world
-            .deserialize_entity(&json.to_string())
-            .map_err(|e| e.to_string())
-            .unwrap();
-        entity.comp::<Position>(|p| {
-            assert_eq!(p.x, 1);
-            assert_eq!(p.y, 2);
+        world.register_components::<PlayerState>();
+        let [idle, walking, running] = [
+            PlayerState::Idle,
+            PlayerState::Walking,
+            PlayerState::Running,
+        ]
+        .map(|state| world.add_entity().add_enum_tag(state));
+
+        let moving = world
+            .query::<()>()
+            .with_enum_tag_any(&[PlayerState::Walking, PlayerState::Running])
+            .build()
+            .iter()
+            .count();
+        assert_eq!(moving, 2);
+        let idle_count = world
+            .query::<()>()
+            .with_enum_tag(PlayerState::Idle)
+            .build()
+            .iter()
+            .count();
+        assert_eq!(idle_count, 1);
+        let none = world
+            .query::<()>()
+            .with_enum_tag_any::<PlayerState>(&[])
+            .build()
+            .iter()
+            .count();
+        assert_eq!(none, 0);
+
+        let untagged = world.add_entity();
+        let not_idle: Vec<_> = world
+            .query::<&Entity>()
+            .without_enum_tag(PlayerState::Idle)
+            .build()
+            .iter()
+            .map(|entity| entity.0.low32())
+            .collect();
+        assert!(!not_idle.contains(&idle.0.low32()));
+        for entity in [walking, running, untagged] {
+            assert!(not_idle.contains(&entity.0.low32()));
+        }
+    }
+
+    #[test]
+    fn comp_set_callbacks() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let world = World::new();
+        world.register_components::<Position>();
+        let writes = Rc::new(RefCell::new(vec![]));
+        world.on_comp_set::<Position>({
+            let writes = writes.clone();
+            move |entity, replaced| {
+                let mut x = 0;
+                entity.comp::<Position>(|pos| x = pos.x);
+                writes.borrow_mut().push((entity.0.low32(), replaced, x))
+            }
         });
-        //TODO: unite and add missing get/get_mut methods
-        let enemy = world.entity_by_global_name("Enemy").unwrap();
-        let mut enemy = enemy.0.unpack();
-        enemy.high32.is_target = false;
-        let enemy = Entity(enemy.into());
-        entity
-            .rel_first::<Owes, Apples>()
-            .get(|owes| assert_eq!(owes.amount, 10));
-        entity
-            .rel_second::<Begin, Position>()
-            .get(|pos| assert_eq!(pos.x + pos.y, 5));
-        entity.comp::<Position>(|pos| assert_eq!(pos.x + pos.y, 3));
-        entity.name().get(|n| assert!(n == "John"));
-        assert!(entity.has_tag::<IsCool>());
-        assert!(entity.has_rel::<Likes, Apples>());
-        assert!(entity.has_mixed_rel::<Likes>(enemy));
-        assert!(entity.has_ent_tag(enemy));
+
+        let entity = world.add_entity().add_comp(Position::new(1, 1));
+        entity.add_comp(Position::new(2, 2));
+        let other = world.add_entity();
+        for _ in world.query::<&Position>().build().iter() {
+            other.add_comp(Position::new(3, 3));
+            assert_eq!(writes.borrow().len(), 2);
+        }
+        assert_eq!(
+            *writes.borrow(),
+            [
+                (entity.0.low32(), false, 1),
+                (entity.0.low32(), true, 2),
+                (other.0.low32(), false, 3),
+            ]
+        );
     }
+
     #[test]
-    fn serialization() {
+    fn enum_tag_change_callbacks() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
         enum_tag! {
             #[derive(Debug, Eq, PartialEq)]
-            enum MyEnumTag {
-                StateOne
+            enum PlayerState {
+                Walking,
+                Falling,
             }
         }
 
         let world = World::new();
-        world.register_components::<(MyEnumTag, Position, IsCool, Likes, Apples, Owes, Begin)>();
-        let enemy = world.add_entity_named("Enemy");
-        let entity = world
-            .add_entity_named("John")
-            .add_rel_first::<_, Apples>(Owes { amount: 10 })
-            .add_rel_second::<Begin, _>(Position::new(2, 3))
-            .add_comp(Position::new(1, 2))
-            .add_tag::<IsCool>()
-            .add_ent_tag(enemy)
-            .add_rel::<Likes, Apples>()
-            .add_mixed_tag_rel::<Likes>(enemy)
-            .serialize()
-            .unwrap();
-        println!("{entity}");
+        world.register_components::<PlayerState>();
+        let changes = Rc::new(RefCell::new(vec![]));
+        world.on_enum_tag_change::<PlayerState>({
+            let changes = changes.clone();
+            move |entity, old, new| {
+                //the new value is already visible to the callback
+                assert_eq!(entity.get_enum_tag::<PlayerState>(), Some(new));
+                changes.borrow_mut().push((old, new))
+            }
+        });
+
+        let entity = world.add_entity().add_enum_tag(PlayerState::Walking);
+        entity.add_enum_tag(PlayerState::Walking);
+        entity.add_enum_tag(PlayerState::Falling);
+        assert_eq!(
+            *changes.borrow(),
+            [
+                (None, PlayerState::Walking),
+                (Some(PlayerState::Walking), PlayerState::Falling),
+            ]
+        );
     }
 
     #[test]
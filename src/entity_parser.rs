@@ -6,11 +6,12 @@ use smol_str::{SmolStr, ToSmolStr};
 use thiserror::Error;
 
 use crate::{
-    archetypes::{Archetypes, DeserializeFn, MyTypeRegistry, NameLeft, NameRight, RelDataPosition}, either::Either, expect_fn::ExpectFnOption, identifier::Identifier
+    archetypes::{Archetypes, DeserializeFn, MyTypeRegistry, NameError, NameLeft, NameRight, RelDataPosition}, either::Either, expect_fn::ExpectFnOption, identifier::Identifier, systems::EnumId
 };
 
 pub struct EntityParser {
     tag: Regex,
+    enum_tag_regex: Regex,
     tag_rel_regex: Regex,
     rel_data_first_regex: Regex,
     rel_data_second_regex: Regex,
@@ -32,6 +33,16 @@ pub enum ParseError {
     JsonIsNotObject,
     #[error("expected 'Tags' to be an array (of tags)")]
     TagsIsNotArray,
+    #[error("unknown variant '{1}' of enum tag '{0}'")]
+    UnknownEnumVariant(SmolStr, SmolStr),
+    #[error("expected a reference with either 'Tag' or 'Relation' and 'Target'")]
+    InvalidReference,
+    #[error(transparent)]
+    Name(#[from] NameError),
+    #[error("unregistered types: {}", .0.join(", "))]
+    UnregisteredTypes(Vec<SmolStr>),
+    #[error("invalid binary snapshot")]
+    BinarySnapshot(#[from] bincode::Error),
 }
 
 impl TagType {
@@ -51,11 +62,13 @@ pub enum ParsedEntityItem {
     RelationshipTag(IdOrName, IdOrName),
     Component(Identifier, DeserializeFn, serde_json::Value, ComponentType),
     Name(SmolStr),
+    EnumTag(Identifier, EnumId),
 }
 impl EntityParser {
     pub fn new() -> Self {
         Self {
             tag: Regex::new(r"(#?)(\w+)").unwrap(),
+            enum_tag_regex: Regex::new(r"^(\w+)::(\w+)$").unwrap(),
             tag_rel_regex: Regex::new(r"\((#?)(\w+), (#?)(\w+)\)").unwrap(),
             rel_data_first_regex: Regex::new(r"\(\$(\w+), (\w+)\)").unwrap(),
             rel_data_second_regex: Regex::new(r"\((\w+), \$(\w+)\)").unwrap(),
@@ -84,11 +97,10 @@ impl EntityParser {
 
     pub fn parse(
         &self,
-        json: &str,
+        value: &Value,
         archetypes: &Archetypes,
     ) -> Result<impl Iterator<Item = ParsedEntityItem>, ParseError> {
         let mut components = vec![];
-        let value = serde_json::from_str::<Value>(json)?;
         let Some(object) = value.as_object() else {
             return Err(ParseError::JsonIsNotObject);
         };
@@ -122,6 +134,22 @@ impl EntityParser {
                             !captures[3].is_empty(),
                         )?;
                         components.push(ParsedEntityItem::RelationshipTag(relation, target));
+                    } else if let Some(captures) = self.enum_tag_regex.captures(&tag) {
+                        let enum_name = captures[1].to_smolstr();
+                        let variant = captures[2].to_smolstr();
+                        let Some(enum_type) =
+                            type_registry.identifiers_by_names.get(&enum_name).copied()
+                        else {
+                            return Err(ParseError::UnknownType(enum_name));
+                        };
+                        let Some(value) = type_registry
+                            .enum_variant_ids
+                            .get(&enum_type.stripped())
+                            .and_then(|variant_id| variant_id(&variant))
+                        else {
+                            return Err(ParseError::UnknownEnumVariant(enum_name, variant));
+                        };
+                        components.push(ParsedEntityItem::EnumTag(enum_type, value));
                     } else if let Some(captures) = self.tag.captures(&tag) {
                         let tag = &captures[2];
                         components.push(ParsedEntityItem::Tag(self.id_or_name(
@@ -191,6 +219,58 @@ impl EntityParser {
 
         Ok(components.into_iter())
     }
+
+    //every type name the json refers to that isn't registered, entity tags aside
+    pub fn unregistered_types(&self, value: &Value, archetypes: &Archetypes) -> Vec<SmolStr> {
+        let mut names = vec![];
+        let Some(object) = value.as_object() else {
+            return names;
+        };
+        for (key, value) in object.iter() {
+            if key == "Name" {
+                continue;
+            }
+            if key == "Tags" {
+                let tags = value.as_array().into_iter().flatten();
+                for tag in tags.filter_map(|v| v.as_str()) {
+                    if let Some(captures) = self.tag_rel_regex.captures(tag) {
+                        if captures[1].is_empty() {
+                            names.push(captures[2].to_smolstr());
+                        }
+                        if captures[3].is_empty() {
+                            names.push(captures[4].to_smolstr());
+                        }
+                    } else if let Some(captures) = self.enum_tag_regex.captures(tag) {
+                        names.push(captures[1].to_smolstr());
+                    } else if let Some(captures) = self.tag.captures(tag) {
+                        if captures[1].is_empty() {
+                            names.push(captures[2].to_smolstr());
+                        }
+                    }
+                }
+            } else if let Some(captures) = self
+                .rel_data_first_regex
+                .captures(key)
+                .or_else(|| self.rel_data_second_regex.captures(key))
+            {
+                names.push(captures[1].to_smolstr());
+                names.push(captures[2].to_smolstr());
+            } else {
+                names.push(key.to_smolstr());
+            }
+        }
+
+        let type_registry = archetypes.type_registry_rc();
+        let type_registry = type_registry.borrow();
+        let mut unregistered = vec![];
+        for name in names {
+            let registered = type_registry.identifiers_by_names.contains_key(&name);
+            if !registered && !unregistered.contains(&name) {
+                unregistered.push(name);
+            }
+        }
+        unregistered
+    }
 }
 
 impl Default for EntityParser {
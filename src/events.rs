@@ -1,7 +1,11 @@
-use std::{any::TypeId, cell::RefCell, rc::Rc};
+use std::{
+    any::TypeId,
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use bevy_reflect::Reflect;
-use bevy_utils::hashbrown::{HashMap, HashSet};
+use bevy_utils::hashbrown::HashMap;
 
 use crate::{systems::SystemId, world::World};
 
@@ -28,13 +32,14 @@ pub(crate) fn default_cleanup_system<T: Event>(world: &World) {
 
 pub struct EventReader<T: Event> {
     data: std::marker::PhantomData<T>,
-    read_ids: Rc<RefCell<HashSet<EventId>>>,
-    events: Rc<RefCell<Vec<EventData<T>>>>,
+    //id of the first event that hasn't been read yet
+    cursor: Cell<EventId>,
+    buffers: Rc<RefCell<EventBuffers<T>>>,
 }
 
 pub struct EventIter<'w, T: Event> {
-    events: &'w Rc<RefCell<Vec<EventData<T>>>>,
-    read_ids: &'w Rc<RefCell<HashSet<EventId>>>,
+    buffers: &'w Rc<RefCell<EventBuffers<T>>>,
+    cursor: &'w Cell<EventId>,
     index: usize,
 }
 
@@ -42,40 +47,33 @@ impl<'w, T: Event> Iterator for EventIter<'w, T> {
     type Item = &'w T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let events = self.events.borrow();
-        let mut read_ids = self.read_ids.borrow_mut();
+        let buffers = self.buffers.borrow();
         let event_data = loop {
-            if self.index == events.len() {
-                return None;
+            let event_data = buffers.get(self.index)?;
+            self.index += 1;
+            if event_data.id.0 >= self.cursor.get().0 {
+                break event_data;
             }
-
-            let event_data = &events[self.index];
-            if read_ids.contains(&event_data.id) {
-                self.index += 1;
-                continue;
-            }
-
-            read_ids.insert(event_data.id);
-            break event_data;
         };
+        self.cursor.set(EventId(event_data.id.0 + 1));
         Some(unsafe { &*(&event_data.event as *const T) })
     }
 }
 
 impl<T: Event> EventReader<T> {
-    pub fn new(events: Rc<RefCell<Vec<EventData<T>>>>) -> Self {
+    pub fn new(buffers: Rc<RefCell<EventBuffers<T>>>) -> Self {
         Self {
             data: std::marker::PhantomData,
-            read_ids: RefCell::new(HashSet::new()).into(),
-            events,
+            cursor: Cell::new(EventId(0)),
+            buffers,
         }
     }
 
     pub fn read(&self) -> EventIter<'_, T> {
         EventIter {
-            events: &self.events,
+            buffers: &self.buffers,
+            cursor: &self.cursor,
             index: 0,
-            read_ids: &self.read_ids,
         }
     }
 }
@@ -83,89 +81,87 @@ impl<T: Event> EventReader<T> {
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct EventId(pub u64);
 
+///Events sent during a frame are kept in `new` and moved to `old` by the update at the end of
+///that frame, so they stay readable until the end of the next frame and are dropped after that
+pub struct EventBuffers<T> {
+    old: Vec<EventData<T>>,
+    new: Vec<EventData<T>>,
+}
+
+impl<T> EventBuffers<T> {
+    fn get(&self, index: usize) -> Option<&EventData<T>> {
+        match index.checked_sub(self.old.len()) {
+            None => self.old.get(index),
+            Some(index) => self.new.get(index),
+        }
+    }
+}
+
 pub struct Events<T: Event> {
-    events: Rc<RefCell<Vec<EventData<T>>>>,
+    buffers: Rc<RefCell<EventBuffers<T>>>,
     readers: HashMap<SystemId, Rc<RefCell<EventReader<T>>>>,
     last_id: EventId,
 }
-///Fresh: given to an event upon creation
-///Dirty: assigned to an event when it reaches a cleanup system at the end of a frame
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum EventState {
-    Fresh,
-    Dirty,
-}
 
 pub struct EventData<T> {
     event: T,
     id: EventId,
-    state: EventState,
 }
 
 impl<T> EventData<T> {
     pub fn new(event: T, id: EventId) -> Self {
-        Self {
-            event,
-            id,
-            state: EventState::Fresh,
-        }
+        Self { event, id }
     }
 }
 
 impl<T: Event> Events<T> {
     pub fn new() -> Self {
         Self {
-            events: RefCell::new(vec![]).into(),
+            buffers: RefCell::new(EventBuffers {
+                old: vec![],
+                new: vec![],
+            })
+            .into(),
             readers: HashMap::new(),
             last_id: EventId(0),
         }
     }
 
     pub fn clear(&mut self) {
-        self.events.borrow_mut().clear();
+        let mut buffers = self.buffers.borrow_mut();
+        buffers.old.clear();
+        buffers.new.clear();
     }
 
-    ///Performs event double buffering, so that events are removed by the end of the next frame
+    ///Swaps the buffers, dropping the events sent the frame before the current one
     pub fn update(&mut self) {
-        let mut events = self.events.borrow_mut();
-        self.readers.values().for_each(|reader| {
-            events
-                .iter()
-                .filter(|e| e.state == EventState::Dirty)
-                .for_each(|event| {
-                    reader.borrow_mut().read_ids.borrow_mut().remove(&event.id);
-                })
-        });
-        events.retain(|e| e.state == EventState::Fresh);
-        events.iter_mut().for_each(|e| {
-            e.state = EventState::Dirty;
-        });
+        let buffers: &mut EventBuffers<T> = &mut self.buffers.borrow_mut();
+        std::mem::swap(&mut buffers.old, &mut buffers.new);
+        buffers.new.clear();
     }
 
     pub fn push(&mut self, event: T) {
         let id = self.next_id();
-        self.events.borrow_mut().push(EventData::new(event, id));
+        self.buffers
+            .borrow_mut()
+            .new
+            .push(EventData::new(event, id));
     }
 
     pub fn next_id(&mut self) -> EventId {
         let id = self.last_id;
-        self.last_id.0 = self.last_id.0.wrapping_add(1);
+        self.last_id.0 += 1;
         id
     }
 
-    // pub fn iter_events(&mut self, system_id: TypeId) {
-    //     let mut readers = self.readers.borrow_mut();
-    //     let reader = readers.entry(system_id).or_insert(EventReader::<T> {
-    //         data: Default::default(),
-    //         read_ids: RefCell::new(HashSet::new()).into(),
-    //     });
-    //     reader.iter(&self.events);
-    // }
-
     pub fn event_reader(&mut self, system_id: SystemId) -> Rc<RefCell<EventReader<T>>> {
         self.readers
             .entry(system_id)
-            .or_insert(RefCell::new(EventReader::new(self.events.clone())).into())
+            .or_insert(RefCell::new(EventReader::new(self.buffers.clone())).into())
             .clone()
     }
+
+    pub fn global_event_reader(&mut self) -> Rc<RefCell<EventReader<T>>> {
+        RefCell::new(EventReader::new(self.buffers.clone())).into()
+    }
 }
@@ -1,14 +1,24 @@
 use crate::{
-    archetypes::Archetypes, identifier::Identifier, systems::EnumId, wrappers::ArchetypeCell
+    archetypes::Archetypes,
+    identifier::{Identifier, WildcardKind},
+    systems::EnumId,
+    wrappers::ArchetypeCell,
 };
 
-#[derive(Hash, Debug, Clone)]
+#[derive(Hash, Debug, Clone, PartialEq, Eq)]
 pub struct FilterMask {
     pub has: Vec<Identifier>,
     pub not: Vec<Identifier>,
     pub any_has: Vec<Identifier>,
     pub any_not: Vec<Identifier>,
-    pub states: Vec<(Identifier, EnumId)>,
+    //the stored variant has to be one of the ids
+    pub states: Vec<(Identifier, Vec<EnumId>)>,
+    //entities without the enum tag still match
+    pub not_states: Vec<(Identifier, Vec<EnumId>)>,
+    pub added: Vec<Identifier>,
+    pub changed: Vec<Identifier>,
+    //every group needs at least one of its masks to match
+    pub any_of: Vec<Vec<FilterMask>>,
 }
 
 impl FilterMask {
@@ -19,6 +29,10 @@ impl FilterMask {
             any_has: vec![],
             any_not: vec![],
             states: vec![],
+            not_states: vec![],
+            added: vec![],
+            changed: vec![],
+            any_of: vec![],
         }
     }
 
@@ -28,12 +42,22 @@ impl FilterMask {
         self.any_has.sort();
         self.any_not.sort();
         self.states.sort();
+        self.not_states.sort();
+        self.added.sort();
+        self.changed.sort();
+        for mask in self.any_of.iter_mut().flatten() {
+            mask.sort();
+        }
     }
 
-    pub fn push_states(&mut self, state: (Identifier, EnumId)) {
+    pub fn push_states(&mut self, state: (Identifier, Vec<EnumId>)) {
         self.states.push(state);
     }
 
+    pub fn push_not_states(&mut self, state: (Identifier, Vec<EnumId>)) {
+        self.not_states.push(state);
+    }
+
     pub fn push_not(&mut self, id: Identifier) {
         self.not.push(id);
     }
@@ -50,6 +74,52 @@ impl FilterMask {
         self.has.push(id);
     }
 
+    pub fn push_added(&mut self, id: Identifier) {
+        self.added.push(id);
+    }
+
+    pub fn push_changed(&mut self, id: Identifier) {
+        self.changed.push(id);
+    }
+
+    pub fn push_any_of(&mut self, masks: Vec<FilterMask>) {
+        self.any_of.push(masks);
+    }
+
+    //tick and enum state filters and sparse components are checked per entity, archetype
+    //matching can't decide them
+    pub fn has_entity_filters(&self, archetypes: &Archetypes) -> bool {
+        !self.added.is_empty()
+            || !self.changed.is_empty()
+            || !self.states.is_empty()
+            || !self.not_states.is_empty()
+            || self.has_sparse_terms(archetypes)
+            || self
+                .any_of
+                .iter()
+                .flatten()
+                .any(|mask| mask.has_entity_filters(archetypes))
+    }
+
+    pub fn has_sparse_terms(&self, archetypes: &Archetypes) -> bool {
+        self.has
+            .iter()
+            .chain(self.not.iter())
+            .chain(self.any_has.iter())
+            .chain(self.any_not.iter())
+            .any(|id| archetypes.is_sparse(*id))
+    }
+
+    //the sparse terms of the mask, the other terms were checked by archetype matching
+    pub(crate) fn matches_sparse(&self, archetypes: &Archetypes, entity: Identifier) -> bool {
+        let is_sparse = |id: &&Identifier| archetypes.is_sparse(**id);
+        let has = |id: &Identifier| archetypes.has_component(*id, entity);
+        self.has.iter().filter(is_sparse).all(has)
+            && !self.not.iter().filter(is_sparse).any(has)
+            && (!self.any_has.iter().any(|id| is_sparse(&id)) || self.any_has.iter().any(has))
+            && (!self.any_not.iter().any(|id| is_sparse(&id)) || !self.any_not.iter().all(has))
+    }
+
     pub fn join(&mut self, mask: &FilterMask) {
         for id in mask.any_not.iter() {
             self.push_any_not(*id)
@@ -63,8 +133,20 @@ impl FilterMask {
         for id in mask.has.iter() {
             self.push_has(*id)
         }
-        for id in mask.states.iter() {
-            self.push_states(*id)
+        for state in mask.states.iter() {
+            self.push_states(state.clone())
+        }
+        for state in mask.not_states.iter() {
+            self.push_not_states(state.clone())
+        }
+        for id in mask.added.iter() {
+            self.push_added(*id)
+        }
+        for id in mask.changed.iter() {
+            self.push_changed(*id)
+        }
+        for masks in mask.any_of.iter() {
+            self.push_any_of(masks.clone())
         }
     }
 
@@ -73,40 +155,27 @@ impl FilterMask {
         archetypes: &Archetypes,
         archetype: &ArchetypeCell,
     ) -> bool {
-        if self.has.iter().any(|id| {
-            !archetypes
-                .get_archetypes_with_id(*id)
-                .map(|a| a.contains(archetype))
-                .unwrap_or(false)
-        }) {
+        let archetype_ref = archetype.borrow();
+        let components = archetype_ref.components_ids();
+        let has = |id: &Identifier| archetype_has(archetypes, archetype, components, *id);
+        //sparse components aren't stored in archetypes, every entity is checked for them instead
+        let maybe_has = |id: &Identifier| archetypes.is_sparse(*id) || has(id);
+        let surely_has = |id: &Identifier| !archetypes.is_sparse(*id) && has(id);
+
+        if !self.has.iter().all(maybe_has) || self.not.iter().any(surely_has) {
             return false;
         }
-        if self.not.iter().any(|id| {
-            archetypes
-                .get_archetypes_with_id(*id)
-                .map(|a| a.contains(archetype))
-                .unwrap_or(false)
-        }) {
+        if !self.any_has.is_empty() && !self.any_has.iter().any(maybe_has) {
             return false;
         }
-        if !self.any_has.is_empty()
-            && self.any_has.iter().all(|id| {
-                !archetypes
-                    .get_archetypes_with_id(*id)
-                    .map(|a| a.contains(archetype))
-                    .unwrap_or(false)
-            })
-        {
+        if !self.any_not.is_empty() && self.any_not.iter().all(surely_has) {
             return false;
         }
-        if !self.any_not.is_empty()
-            && self.any_not.iter().all(|id| {
-                archetypes
-                    .get_archetypes_with_id(*id)
-                    .map(|a| a.contains(archetype))
-                    .unwrap_or(false)
-            })
-        {
+        if self.any_of.iter().any(|masks| {
+            masks
+                .iter()
+                .all(|mask| !mask.matches_archetype(archetypes, archetype))
+        }) {
             return false;
         }
 
@@ -114,6 +183,24 @@ impl FilterMask {
     }
 }
 
+//plain ids are binary searched in the archetype's sorted components, wildcards aren't stored
+//there so they go through the archetypes index
+fn archetype_has(
+    archetypes: &Archetypes,
+    archetype: &ArchetypeCell,
+    components: &[Identifier],
+    id: Identifier,
+) -> bool {
+    if id.wildcard_kind() == WildcardKind::None {
+        let range = id.stripped().range();
+        let index = components.partition_point(|c| c < range.start());
+        return components.get(index).is_some_and(|c| c <= range.end());
+    }
+    archetypes
+        .get_archetypes_with_id(id)
+        .is_some_and(|a| a.contains(archetype))
+}
+
 impl Default for FilterMask {
     fn default() -> Self {
         Self::new()
@@ -128,6 +215,90 @@ mod tests {
 
     use super::*;
 
+    //run with --ignored --nocapture to compare matching times
+    #[test]
+    #[ignore]
+    fn match_benchmark() {
+        use crate::archetypes::Wildcard;
+        use crate::components::test_components::{
+            Apples, Begin, End, IsCool, Likes, Oranges, Position, Velocity,
+        };
+        use crate::world::{archetypes, World};
+
+        let world = World::new();
+        world.register_components::<(
+            Position,
+            Velocity,
+            Likes,
+            IsCool,
+            Begin,
+            End,
+            Apples,
+            Oranges,
+        )>();
+        let targets: Vec<_> = (0..500).map(|_| world.add_entity()).collect();
+        for (i, target) in targets.iter().enumerate() {
+            let entity = world
+                .add_entity()
+                .add_comp(Position::default())
+                .add_tag::<IsCool>()
+                .add_tag::<Begin>()
+                .add_mixed_tag_rel::<Likes>(*target);
+            if i % 2 == 0 {
+                entity.add_comp(Velocity::default());
+            }
+            if i % 3 == 0 {
+                entity.add_tag::<Apples>();
+            }
+        }
+        let masks: Vec<_> = (0..50)
+            .map(|i| {
+                let mut query = world
+                    .query::<()>()
+                    .with_comp::<Position>()
+                    .with_tag::<IsCool>()
+                    .with_tag::<Begin>()
+                    .without_tag::<End>();
+                if i % 2 == 0 {
+                    query = query.with_rel::<Likes, Wildcard>();
+                }
+                if i % 3 == 0 {
+                    query = query.without_comp::<Velocity>();
+                }
+                if i % 5 == 0 {
+                    query = query.without_tag::<Oranges>().with_tag::<Apples>();
+                }
+                query.build().state.mask.clone()
+            })
+            .collect();
+
+        archetypes(|a| {
+            let position = a.component_id::<Position>();
+            let all: Vec<_> = a
+                .get_archetypes_with_id(position)
+                .unwrap()
+                .iter()
+                .cloned()
+                .collect();
+            let start = std::time::Instant::now();
+            let mut matched = 0;
+            for _ in 0..10 {
+                for mask in masks.iter() {
+                    matched += all
+                        .iter()
+                        .filter(|arch| mask.matches_archetype(a, arch))
+                        .count();
+                }
+            }
+            println!(
+                "{} archetypes, {} masks: {matched} matches in {:?}",
+                all.len(),
+                masks.len(),
+                start.elapsed()
+            );
+        });
+    }
+
     #[test]
     fn hash() {
         let mut mask1 = FilterMask::new();
@@ -37,8 +37,12 @@ impl Identifier {
         std::convert::Into::<StrippedIdentifier>::into(*self)
     }
 
+    //reads the bits directly, unpacking is too slow for query matching
     pub fn wildcard_kind(&self) -> WildcardKind {
-        match (self.low32(), self.second()) {
+        let value = u64::from(*self);
+        let low32 = (value >> 32) as u32;
+        let second = (value >> 7) as u32 & WILDCARD_25;
+        match (low32, second) {
             (WILDCARD_32, WILDCARD_25) => WildcardKind::Both,
             (WILDCARD_32, _) => WildcardKind::Relation,
             (_, WILDCARD_25) => WildcardKind::Target,
@@ -14,6 +14,7 @@ pub mod children_iter;
 pub mod wrappers;
 pub mod relationship;
 pub mod table;
+pub mod sparse_set;
 pub mod borrow_traits;
 pub mod filter_mask;
 pub mod query;
@@ -21,6 +22,7 @@ pub mod events;
 pub mod archetype;
 pub mod identifier;
 pub mod blob_vec;
+pub mod binary_snapshot;
 pub mod archetypes;
 pub mod resources;
 pub mod world;
@@ -1,6 +1,13 @@
+use std::{cell::RefCell, collections::VecDeque};
+
+use bevy_ptr::Ptr;
 use bevy_utils::HashMap;
+use smol_str::SmolStr;
 
-use crate::{entity::Entity, identifier::Identifier, world::World};
+use crate::{
+    archetypes::StrippedIdentifier, entity::Entity, identifier::Identifier, systems::EnumId,
+    table::Storage, world::World,
+};
 
 pub trait OnAddCallback: 'static {
     fn run(&self, entity: Entity, world: World);
@@ -28,9 +35,115 @@ where
     }
 }
 
+pub trait OnNameChangeCallback: 'static {
+    fn run(&mut self, entity: Entity, old_name: Option<&str>, new_name: &str);
+}
+
+impl<T> OnNameChangeCallback for T
+where
+    T: FnMut(Entity, Option<&str>, &str) + 'static,
+{
+    fn run(&mut self, entity: Entity, old_name: Option<&str>, new_name: &str) {
+        self(entity, old_name, new_name);
+    }
+}
+
+pub trait OnEnumTagChangeCallback: 'static {
+    fn run(&mut self, entity: Entity, old: Option<EnumId>, new: EnumId);
+}
+
+impl<T> OnEnumTagChangeCallback for T
+where
+    T: FnMut(Entity, Option<EnumId>, EnumId) + 'static,
+{
+    fn run(&mut self, entity: Entity, old: Option<EnumId>, new: EnumId) {
+        self(entity, old, new);
+    }
+}
+
+//runs on every write of a component value, `replaced` is false for the first insert
+pub trait OnSetCallback: 'static {
+    fn run(&mut self, entity: Entity, replaced: bool);
+}
+
+impl<T> OnSetCallback for T
+where
+    T: FnMut(Entity, bool) + 'static,
+{
+    fn run(&mut self, entity: Entity, replaced: bool) {
+        self(entity, replaced);
+    }
+}
+
+//gets a copy of the value that was removed
+pub trait OnRemoveValueCallback: 'static {
+    fn run(&mut self, entity: Entity, value: Ptr);
+}
+
+impl<T> OnRemoveValueCallback for T
+where
+    T: FnMut(Entity, Ptr) + 'static,
+{
+    fn run(&mut self, entity: Entity, value: Ptr) {
+        self(entity, value);
+    }
+}
+
+//gets the holder and the target of a newly added (relation, *) relationship
+pub trait OnRelAddCallback: 'static {
+    fn run(&mut self, entity: Entity, target: Entity);
+}
+
+impl<T> OnRelAddCallback for T
+where
+    T: FnMut(Entity, Entity) + 'static,
+{
+    fn run(&mut self, entity: Entity, target: Entity) {
+        self(entity, target);
+    }
+}
+
+//callbacks that may use the world are queued while it's borrowed
+//and run by `archetypes_mut` once the borrow is released
+enum QueuedCallback {
+    NameChange {
+        entity: Identifier,
+        old_name: Option<SmolStr>,
+        new_name: SmolStr,
+    },
+    EnumTagChange {
+        enum_type: Identifier,
+        entity: Identifier,
+        old: Option<EnumId>,
+        new: EnumId,
+    },
+    Set {
+        component: Identifier,
+        entity: Identifier,
+        replaced: bool,
+    },
+    RemoveValue {
+        component: Identifier,
+        entity: Identifier,
+        value: Storage,
+    },
+    RelAdd {
+        wildcard: Identifier,
+        entity: Identifier,
+        target: Identifier,
+    },
+}
+
 pub struct OnChangeCallbacks {
     add_callbacks: HashMap<Identifier, Box<dyn OnAddCallback>>,
     remove_callbacks: HashMap<Identifier, Box<dyn OnRemoveCallback>>,
+    name_callbacks: Vec<Box<dyn OnNameChangeCallback>>,
+    enum_tag_callbacks: HashMap<Identifier, Vec<Box<dyn OnEnumTagChangeCallback>>>,
+    set_callbacks: HashMap<Identifier, Vec<Box<dyn OnSetCallback>>>,
+    remove_value_callbacks: HashMap<StrippedIdentifier, Vec<Box<dyn OnRemoveValueCallback>>>,
+    rel_add_callbacks: HashMap<StrippedIdentifier, Vec<Box<dyn OnRelAddCallback>>>,
+    queued: VecDeque<QueuedCallback>,
+    running_queued: bool,
 }
 
 impl OnChangeCallbacks {
@@ -38,6 +151,13 @@ impl OnChangeCallbacks {
         Self {
             add_callbacks: HashMap::new(),
             remove_callbacks: HashMap::new(),
+            name_callbacks: vec![],
+            enum_tag_callbacks: HashMap::new(),
+            set_callbacks: HashMap::new(),
+            remove_value_callbacks: HashMap::new(),
+            rel_add_callbacks: HashMap::new(),
+            queued: VecDeque::new(),
+            running_queued: false,
         }
     }
 
@@ -53,18 +173,242 @@ impl OnChangeCallbacks {
         self.remove_callbacks.insert(component, callback);
     }
 
+    pub fn insert_name_callback(&mut self, callback: Box<dyn OnNameChangeCallback>) {
+        self.name_callbacks.push(callback);
+    }
+
+    //a removed name is reported with an empty new name
+    pub fn queue_name_callbacks(
+        &mut self,
+        entity: Identifier,
+        old_name: Option<&str>,
+        new_name: &str,
+    ) {
+        if self.name_callbacks.is_empty() {
+            return;
+        }
+        self.queued.push_back(QueuedCallback::NameChange {
+            entity,
+            old_name: old_name.map(SmolStr::new),
+            new_name: SmolStr::new(new_name),
+        });
+    }
+
+    //false while the queue is already being run further up the stack
+    pub fn has_queued(&self) -> bool {
+        !self.queued.is_empty() && !self.running_queued
+    }
+
+    pub fn run_queued(callbacks: &RefCell<Self>) {
+        if !callbacks.borrow().has_queued() {
+            return;
+        }
+        callbacks.borrow_mut().running_queued = true;
+        let _running = RunningQueued(callbacks);
+        loop {
+            let Some(queued) = callbacks.borrow_mut().queued.pop_front() else {
+                break;
+            };
+            match queued {
+                QueuedCallback::NameChange {
+                    entity,
+                    old_name,
+                    new_name,
+                } => Self::run_each(
+                    callbacks,
+                    |c| &mut c.name_callbacks,
+                    |callback| callback.run(Entity(entity), old_name.as_deref(), &new_name),
+                ),
+                QueuedCallback::EnumTagChange {
+                    enum_type,
+                    entity,
+                    old,
+                    new,
+                } => Self::run_each(
+                    callbacks,
+                    |c| c.enum_tag_callbacks.entry(enum_type).or_default(),
+                    |callback| callback.run(Entity(entity), old, new),
+                ),
+                QueuedCallback::Set {
+                    component,
+                    entity,
+                    replaced,
+                } => Self::run_each(
+                    callbacks,
+                    |c| c.set_callbacks.entry(component).or_default(),
+                    |callback| callback.run(Entity(entity), replaced),
+                ),
+                QueuedCallback::RemoveValue {
+                    component,
+                    entity,
+                    value,
+                } => Self::run_each(
+                    callbacks,
+                    |c| {
+                        c.remove_value_callbacks
+                            .entry(component.stripped())
+                            .or_default()
+                    },
+                    |callback| callback.run(Entity(entity), value.try_component(0.into()).unwrap()),
+                ),
+                QueuedCallback::RelAdd {
+                    wildcard,
+                    entity,
+                    target,
+                } => Self::run_each(
+                    callbacks,
+                    |c| c.rel_add_callbacks.entry(wildcard.stripped()).or_default(),
+                    |callback| callback.run(Entity(entity), Entity(target)),
+                ),
+            }
+        }
+    }
+
+    //the callbacks are taken out while they run, so they can register new ones
+    fn run_each<C: ?Sized>(
+        callbacks: &RefCell<Self>,
+        list: impl Fn(&mut Self) -> &mut Vec<Box<C>>,
+        mut run: impl FnMut(&mut C),
+    ) {
+        let taken = std::mem::take(list(&mut callbacks.borrow_mut()));
+        let mut restore = RestoreCallbacks {
+            callbacks,
+            list,
+            taken,
+        };
+        for callback in restore.taken.iter_mut() {
+            run(callback.as_mut());
+        }
+    }
+
+    pub fn insert_enum_tag_callback(
+        &mut self,
+        enum_type: Identifier,
+        callback: Box<dyn OnEnumTagChangeCallback>,
+    ) {
+        self.enum_tag_callbacks
+            .entry(enum_type)
+            .or_default()
+            .push(callback);
+    }
+
+    pub fn queue_enum_tag_callbacks(
+        &mut self,
+        enum_type: Identifier,
+        entity: Identifier,
+        old: Option<EnumId>,
+        new: EnumId,
+    ) {
+        if !self.enum_tag_callbacks.contains_key(&enum_type) {
+            return;
+        }
+        self.queued.push_back(QueuedCallback::EnumTagChange {
+            enum_type,
+            entity,
+            old,
+            new,
+        });
+    }
+
+    pub fn insert_set_callback(&mut self, component: Identifier, callback: Box<dyn OnSetCallback>) {
+        self.set_callbacks
+            .entry(component)
+            .or_default()
+            .push(callback);
+    }
+
+    pub fn queue_set_callbacks(
+        &mut self,
+        component: Identifier,
+        entity: Identifier,
+        replaced: bool,
+    ) {
+        if !self.set_callbacks.contains_key(&component) {
+            return;
+        }
+        self.queued.push_back(QueuedCallback::Set {
+            component,
+            entity,
+            replaced,
+        });
+    }
+
+    pub fn insert_remove_value_callback(
+        &mut self,
+        component: Identifier,
+        callback: Box<dyn OnRemoveValueCallback>,
+    ) {
+        self.remove_value_callbacks
+            .entry(component.stripped())
+            .or_default()
+            .push(callback);
+    }
+
+    pub fn has_remove_value_callbacks(&self, component: Identifier) -> bool {
+        self.remove_value_callbacks
+            .contains_key(&component.stripped())
+    }
+
+    //`value` holds a single copy of the removed value
+    pub fn queue_remove_value_callbacks(
+        &mut self,
+        component: Identifier,
+        entity: Identifier,
+        value: Storage,
+    ) {
+        self.queued.push_back(QueuedCallback::RemoveValue {
+            component,
+            entity,
+            value,
+        });
+    }
+
+    //keyed by the (relation, *) wildcard relationship
+    pub fn insert_rel_add_callback(
+        &mut self,
+        wildcard: Identifier,
+        callback: Box<dyn OnRelAddCallback>,
+    ) {
+        self.rel_add_callbacks
+            .entry(wildcard.stripped())
+            .or_default()
+            .push(callback);
+    }
+
+    pub fn queue_rel_add_callbacks(
+        &mut self,
+        wildcard: Identifier,
+        entity: Identifier,
+        target: Identifier,
+    ) {
+        if !self.rel_add_callbacks.contains_key(&wildcard.stripped()) {
+            return;
+        }
+        self.queued.push_back(QueuedCallback::RelAdd {
+            wildcard,
+            entity,
+            target,
+        });
+    }
+
     pub fn run_add_callback(&self, component: Identifier, entity: Identifier) {
         let Some(callback) = self.add_callbacks.get(&component) else {
             return;
         };
-        callback.run(Entity(entity), World::default());
+        callback.run(
+            Entity(entity),
+            World::active().expect("expected an active world"),
+        );
     }
 
     pub fn run_remove_callback(&self, component: Identifier, entity: Identifier) {
         let Some(callback) = self.remove_callbacks.get(&component) else {
             return;
         };
-        callback.run(Entity(entity), World::default());
+        callback.run(
+            Entity(entity),
+            World::active().expect("expected an active world"),
+        );
     }
 }
 
@@ -73,3 +417,35 @@ impl Default for OnChangeCallbacks {
         Self::new()
     }
 }
+
+//clears the flag even if a callback panics, otherwise the queue would never run again
+struct RunningQueued<'a>(&'a RefCell<OnChangeCallbacks>);
+
+impl Drop for RunningQueued<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut callbacks) = self.0.try_borrow_mut() {
+            callbacks.running_queued = false;
+        }
+    }
+}
+
+//puts taken callbacks back in front of the ones registered meanwhile, also when one panics
+struct RestoreCallbacks<'a, C: ?Sized, L: Fn(&mut OnChangeCallbacks) -> &mut Vec<Box<C>>> {
+    callbacks: &'a RefCell<OnChangeCallbacks>,
+    list: L,
+    taken: Vec<Box<C>>,
+}
+
+impl<C: ?Sized, L: Fn(&mut OnChangeCallbacks) -> &mut Vec<Box<C>>> Drop
+    for RestoreCallbacks<'_, C, L>
+{
+    fn drop(&mut self) {
+        let Ok(mut callbacks) = self.callbacks.try_borrow_mut() else {
+            return;
+        };
+        let list = (self.list)(&mut callbacks);
+        let mut taken = std::mem::take(&mut self.taken);
+        taken.append(list);
+        *list = taken;
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     fmt::Debug,
     hash::{DefaultHasher, Hash, Hasher},
     marker::PhantomData,
@@ -7,26 +7,30 @@ use std::{
     rc::Rc,
 };
 
+use bevy_ptr::Ptr;
 use packed_struct::PackedStruct;
+use thiserror::Error;
 
 use crate::{archetypes::ChildOf, entity::Entity};
 use crate::identifier::IdentifierUnpacked;
 use crate::world::archetypes;
+use crate::archetype::Archetype;
 pub use crate::{
     archetype::ArchetypeRow, components::component::EnumTag, relationship::RelationshipsIter,
 };
 use crate::{
-    archetypes::QueryStorage,
+    archetypes::{QueryStorage, QueryTickKey},
     borrow_traits::BorrowFn,
     components::component::AbstractComponent,
     filter_mask::FilterMask,
     identifier::Identifier,
-    table::TableRow,
+    table::{ComponentTicks, Storage, TableRow},
     world::{self, archetypes_mut},
 };
 use crate::{
-    archetypes::{Archetypes, EnumTagId, Prefab},
+    archetypes::{Archetypes, EntityRecord, EnumTagId, Prefab},
     entity::WILDCARD,
+    wrappers::ArchetypeCell,
 };
 #[derive(Debug, Clone, Copy, Default)]
 pub enum FilterMaskHint {
@@ -119,10 +123,49 @@ impl WorldQuery for () {
     ) -> Self::Item<'w> {
     }
 }
+
+fn sparse_component<'w, T: AbstractComponent>(
+    id: Identifier,
+    archetype: &Archetype,
+    archetype_row: ArchetypeRow,
+) -> Option<Mut<'w, T>> {
+    let index = archetype.entity_indices()[archetype_row.0];
+    archetypes(|archetypes| {
+        let sparse_set = archetypes.sparse_set(id)?;
+        let row = sparse_set.row(index)?;
+        let table = sparse_set.table().borrow();
+        let storage = table.storage(id)?.borrow();
+        Some(tracked_component(&storage, row))
+    })
+}
+
+//rows past the end of the column panic instead of reading out of bounds
+fn stored_component<'s>(storage: &'s Storage, row: TableRow) -> Ptr<'s> {
+    storage
+        .try_component(row)
+        .unwrap_or_else(|| panic!("stale table row {}", row.0))
+}
+
+fn tracked_component<'w, T: AbstractComponent>(storage: &Storage, row: TableRow) -> Mut<'w, T> {
+    let component_ptr = stored_component(storage, row);
+    Mut::tracked(
+        unsafe { &mut *(component_ptr.as_ptr() as *mut T) },
+        unsafe { &mut *storage.ticks_ptr(row) },
+        storage.change_tick(),
+    )
+}
+
 pub trait QueryData: WorldQuery {
     fn ids(ids: &mut RequiredIds);
 }
 
+//only shared access, so items of different entities can be held at the same time
+pub trait ReadOnlyQueryData: QueryData {}
+
+impl<T: AbstractComponent> ReadOnlyQueryData for Option<&T> {}
+impl<T: AbstractComponent> ReadOnlyQueryData for &T {}
+impl ReadOnlyQueryData for &Entity {}
+
 impl<T: AbstractComponent> WorldQuery for Option<&T> {
     type Item<'i> = Option<Ref<'i, T>>;
 
@@ -131,14 +174,17 @@ impl<T: AbstractComponent> WorldQuery for Option<&T> {
         archetype_index: usize,
         ids: &mut IdsIterator,
         row: TableRow,
-        _: ArchetypeRow,
+        archetype_row: ArchetypeRow,
     ) -> Self::Item<'w> {
         let storage = storage.borrow();
         let archetype = &storage.archetypes[archetype_index].borrow();
         let table = archetype.table().borrow();
         let id = ids.next().unwrap();
-        let storage = table.storage(id)?.borrow();
-        let component_ptr = storage.component(row);
+        let Some(storage) = table.storage(id) else {
+            return sparse_component::<T>(id, archetype, archetype_row).map(|c| Ref::new(c.untracked()));
+        };
+        let storage = storage.borrow();
+        let component_ptr = stored_component(&storage, row);
         Some(Ref::new(unsafe { &*(component_ptr.as_ptr() as *mut T) }))
     }
 }
@@ -151,17 +197,17 @@ impl<T: AbstractComponent> WorldQuery for Option<&mut T> {
         archetype_index: usize,
         ids: &mut IdsIterator,
         row: TableRow,
-        _: ArchetypeRow,
+        archetype_row: ArchetypeRow,
     ) -> Self::Item<'w> {
         let storage = storage.borrow();
         let archetype = &storage.archetypes[archetype_index].borrow();
         let table = archetype.table().borrow();
         let id = ids.next().unwrap();
-        let storage = table.storage(id)?.borrow();
-        let component_ptr = storage.component(row);
-        Some(Mut::new(unsafe {
-            &mut *(component_ptr.as_ptr() as *mut T)
-        }))
+        let Some(storage) = table.storage(id) else {
+            return sparse_component::<T>(id, archetype, archetype_row);
+        };
+        let storage = storage.borrow();
+        Some(tracked_component(&storage, row))
     }
 }
 impl<T: AbstractComponent> WorldQuery for &T {
@@ -172,15 +218,19 @@ impl<T: AbstractComponent> WorldQuery for &T {
         archetype_index: usize,
         ids: &mut IdsIterator,
         row: TableRow,
-        _: ArchetypeRow,
+        archetype_row: ArchetypeRow,
     ) -> Self::Item<'w> {
         let storage = storage.borrow();
         let archetype = &storage.archetypes[archetype_index].borrow();
         let table = archetype.table().borrow();
         let id = ids.next().unwrap();
         //TODO: find a way to replace wildcard data ids to actual ids
-        let storage = table.storage(id).unwrap().borrow();
-        let component_ptr = storage.component(row);
+        let Some(storage) = table.storage(id) else {
+            let component = sparse_component::<T>(id, archetype, archetype_row).unwrap();
+            return Ref::new(component.untracked());
+        };
+        let storage = storage.borrow();
+        let component_ptr = stored_component(&storage, row);
         Ref::new(unsafe { &*(component_ptr.as_ptr() as *mut T) })
     }
 }
@@ -193,15 +243,17 @@ impl<T: AbstractComponent> WorldQuery for &mut T {
         archetype_index: usize,
         ids: &mut IdsIterator,
         row: TableRow,
-        _: ArchetypeRow,
+        archetype_row: ArchetypeRow,
     ) -> Self::Item<'w> {
         let storage = storage.borrow();
         let archetype = &storage.archetypes[archetype_index].borrow();
         let table = archetype.table().borrow();
         let id = ids.next().unwrap();
-        let storage = table.storage(id).unwrap().borrow();
-        let component_ptr = storage.component(row);
-        Mut::new(unsafe { &mut *(component_ptr.as_ptr() as *mut T) })
+        let Some(storage) = table.storage(id) else {
+            return sparse_component::<T>(id, archetype, archetype_row).unwrap();
+        };
+        let storage = storage.borrow();
+        tracked_component(&storage, row)
     }
 }
 
@@ -292,6 +344,7 @@ macro_rules! impl_query_data {
                 )+
             }
         }
+        impl <$($params: ReadOnlyQueryData),+> ReadOnlyQueryData for ($($params),+,) {}
         impl <$($params: QueryData),+> WorldQuery for ($($params),+,) {
             #[allow(unused_parens)]
             type Item<'i> = ($(
@@ -427,6 +480,7 @@ impl Default for RequiredIds {
     }
 }
 
+/// Read access to a component. Unlike [`Mut`], it never marks the component as changed.
 pub struct Ref<'a, T> {
     value: &'a T,
 }
@@ -462,8 +516,10 @@ impl<'a, T> Deref for Ref<'a, T> {
     }
 }
 
+/// Write access to a component. Mutably dereferencing it marks the component as changed.
 pub struct Mut<'a, T> {
     value: &'a mut T,
+    ticks: Option<(&'a mut ComponentTicks, u32)>,
 }
 
 impl<'a, T> Mut<'a, T>
@@ -494,12 +550,26 @@ where
 
 impl<'a, T> Mut<'a, T> {
     pub fn new(value: &'a mut T) -> Self {
-        Self { value }
+        Self { value, ticks: None }
+    }
+
+    pub fn tracked(value: &'a mut T, ticks: &'a mut ComponentTicks, change_tick: u32) -> Self {
+        Self {
+            value,
+            ticks: Some((ticks, change_tick)),
+        }
+    }
+
+    fn untracked(self) -> &'a mut T {
+        self.value
     }
 }
 
 impl<'a, T> DerefMut for Mut<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        if let Some((ticks, change_tick)) = &mut self.ticks {
+            ticks.changed = *change_tick;
+        }
         self.value
     }
 }
@@ -512,21 +582,37 @@ impl<'a, T> Deref for Mut<'a, T> {
 }
 
 pub struct QueryIterator<'w, D: QueryData, F: QueryFilterData> {
-    state: &'w QueryState<D, F>,
-    storage: &'w Rc<RefCell<QueryStorage>>,
+    query: &'w Query<D, F>,
     archetype_index: usize,
     entity_index: usize,
 }
 
 impl<'w, D: QueryData, F: QueryFilterData> Drop for QueryIterator<'w, D, F> {
     fn drop(&mut self) {
-        archetypes_mut(|a| a.unlock());
+        archetypes_mut(|a| {
+            let tick = a.increment_change_tick();
+            self.query.last_run_tick.set(tick);
+            a.set_query_tick(self.query.tick_key, tick);
+            a.unlock_iteration()
+        });
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum QuerySingleError {
+    #[error("expected the query to match an entity, but it matched none")]
+    NoEntities,
+    #[error("expected the query to match a single entity, but it matched {0}")]
+    MultipleEntities(usize),
+}
+
 pub struct Query<D: QueryData, F: QueryFilterData = ()> {
     pub state: QueryState<D, F>,
     pub storage: Rc<RefCell<QueryStorage>>,
+    //change tick of this query's last finished iteration, used by Added and Changed filters.
+    //Starts from the tick of the last query built with the same mask in the same system
+    last_run_tick: Cell<u32>,
+    tick_key: QueryTickKey,
 }
 
 impl<D: QueryData, F: QueryFilterData> Query<D, F> {
@@ -542,11 +628,30 @@ impl<D: QueryData, F: QueryFilterData> Query<D, F> {
             )
         })
     }
+    pub fn get_single(&mut self) -> Result<D::Item<'_>, QuerySingleError> {
+        let mut iter = self.iter();
+        let Some(item) = iter.next() else {
+            return Err(QuerySingleError::NoEntities);
+        };
+        if iter.next().is_some() {
+            return Err(QuerySingleError::MultipleEntities(iter.count() + 2));
+        }
+        Ok(item)
+    }
+    pub fn single(&mut self) -> D::Item<'_> {
+        self.get_single().unwrap_or_else(|err| {
+            panic!(
+                "query {0} with filter {1}: {err}",
+                tynm::type_name::<D>(),
+                tynm::type_name::<F>()
+            )
+        })
+    }
     pub fn matches_entity(&self, entity: Entity) -> bool {
         archetypes_mut(|a| {
             let record = a.record(entity.0).unwrap();
             let archetype = a.archetype_from_record(&record).unwrap().clone();
-            self.state.mask.matches_archetype(a, &archetype)
+            a.matches_archetype(&self.state.mask, &archetype)
         })
     }
     pub fn is_empty(&self) -> bool {
@@ -556,14 +661,147 @@ impl<D: QueryData, F: QueryFilterData> Query<D, F> {
 
 impl<D: QueryData, F: QueryFilterData> Query<D, F> {
     pub fn iter(&mut self) -> QueryIterator<D, F> {
-        archetypes_mut(|a| a.lock());
+        archetypes_mut(|a| a.lock_iteration());
         QueryIterator {
-            state: &self.state,
-            storage: &self.storage,
+            query: self,
             archetype_index: 0,
             entity_index: 0,
         }
     }
+
+    pub fn iter_entities(&mut self) -> EntitiesQueryIterator<'_, D, F> {
+        self.iter().enumerate_entities()
+    }
+
+    ///Number of entities in the matched archetypes, including inactive ones and the ones
+    ///rejected by enum tag or change filters. Use `count_active` to get what `iter` would yield
+    pub fn len(&self) -> usize {
+        self.storage
+            .borrow()
+            .archetypes
+            .iter()
+            .map(|a| a.len())
+            .sum()
+    }
+
+    ///Number of entities `iter().count()` would yield, without locking the world
+    pub fn count_active(&self) -> usize {
+        let mut count = 0;
+        self.for_each_matched(|_| count += 1);
+        count
+    }
+
+    //pushes every entity the query would iterate over
+    pub(crate) fn matched_entities(&self, entities: &mut Vec<Identifier>) {
+        self.for_each_matched(|entity| entities.push(entity));
+    }
+
+    fn for_each_matched(&self, mut f: impl FnMut(Identifier)) {
+        let storage = self.storage.borrow();
+        for archetype in storage.archetypes.iter() {
+            let indices = archetype.borrow().entity_indices().to_vec();
+            for index in indices {
+                let Some(record) = world::archetypes(|a| *a.record_by_index(index)) else {
+                    continue;
+                };
+                if record.entity.is_active()
+                    && entity_passes_filters(
+                        &storage,
+                        &self.state.mask,
+                        archetype,
+                        &record,
+                        self.last_run_tick.get(),
+                    )
+                {
+                    f(record.entity);
+                }
+            }
+        }
+    }
+
+    //the keys are collected first and the items are fetched again in order, so no item
+    //outlives the pass that computed its key. The world stays locked in between, so the
+    //filters checked by the key pass still hold and aren't checked again
+    pub fn sorted_by<K: Ord>(
+        &mut self,
+        mut key: impl FnMut(&D::Item<'_>) -> K,
+    ) -> SortedQueryIter<'_, D, F> {
+        archetypes_mut(|a| a.lock_iteration());
+        let mut keys: Vec<_> = self
+            .iter_entities()
+            .map(|(entity, item)| (key(&item), entity))
+            .collect();
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+        SortedQueryIter {
+            query: self,
+            entities: keys
+                .into_iter()
+                .map(|(_, entity)| entity)
+                .collect::<Vec<_>>()
+                .into_iter(),
+        }
+    }
+
+    //every unordered pair of matched entities once, so n * (n - 1) / 2 items.
+    //Locked before the entities are collected, like sorted_by
+    pub fn pairs(&mut self) -> QueryPairs<'_, D, F>
+    where
+        D: ReadOnlyQueryData,
+    {
+        archetypes_mut(|a| a.lock_iteration());
+        let entities = self.iter_entities().map(|(entity, _)| entity).collect();
+        QueryPairs {
+            query: self,
+            entities,
+            first: 0,
+            second: 1,
+        }
+    }
+
+    //None if the entity is dead, inactive or not matched by the query
+    pub fn get(&mut self, entity: Entity) -> Option<D::Item<'_>> {
+        archetypes_mut(|a| a.lock_iteration());
+        let item = self.fetch_entity(entity, true);
+        archetypes_mut(|a| a.unlock_iteration());
+        item
+    }
+
+    fn fetch_entity(&self, entity: Entity, check_filters: bool) -> Option<D::Item<'_>> {
+        let record = world::archetypes(|a| {
+            if !a.is_entity_alive(entity.0) {
+                return None;
+            }
+            a.record(entity.0)
+        })?;
+        if !record.entity.is_active() {
+            return None;
+        }
+        let storage = self.storage.borrow();
+        let archetype_index = storage
+            .archetypes
+            .iter()
+            .position(|a| a.borrow().id() == record.arhetype_id)?;
+        let archetype = &storage.archetypes[archetype_index];
+        if check_filters
+            && !entity_passes_filters(
+            &storage,
+            &self.state.mask,
+            archetype,
+            &record,
+            self.last_run_tick.get(),
+        ) {
+            return None;
+        }
+        drop(storage);
+        let mut ids = IdsIterator::new(&self.state.ids.values[..]);
+        Some(D::fetch(
+            &self.storage,
+            archetype_index,
+            &mut ids,
+            record.table_row,
+            record.archetype_row,
+        ))
+    }
 }
 pub struct QueryState<D: QueryData, F: QueryFilterData = ()> {
     pub mask: FilterMask,
@@ -615,12 +853,13 @@ impl<D: QueryData, F: QueryFilterData> QueryState<D, F> {
         self.ids.hash(&mut hasher);
         self.mask.hash(&mut hasher);
         let hash = hasher.finish();
-        let storage = archetypes_mut(|archetypes| {
-            archetypes
+        let (storage, tick_key) = archetypes_mut(|archetypes| {
+            let storage = archetypes
                 .query_storage(&sorted_ids, &self.mask, hash)
-                .clone()
+                .clone();
+            (storage, (archetypes.current_system_id(), hash))
         });
-        Query::new(self, storage)
+        Query::new(self, storage, tick_key)
     }
 
     pub fn term_relation<T: AbstractComponent>(mut self, term_index: usize) -> Self {
@@ -808,14 +1047,31 @@ impl<D: QueryData, F: QueryFilterData> QueryState<D, F> {
         self
     }
 
-    pub fn with_enum_tag<T: EnumTag>(mut self, tag: T) -> Self {
+    pub fn with_enum_tag<T: EnumTag>(self, tag: T) -> Self {
+        self.with_enum_tag_any(&[tag])
+    }
+
+    pub fn with_enum_tag_any<T: EnumTag>(mut self, tags: &[T]) -> Self {
+        let mut ids: Vec<_> = tags.iter().map(|tag| tag.id()).collect();
+        ids.sort();
+        ids.dedup();
         archetypes_mut(|archetypes| {
             let enum_tag_id = archetypes.component_id::<EnumTagId>();
             let enum_type_id = archetypes.component_id::<T>();
             let relationship = Archetypes::relationship_id(enum_type_id, enum_tag_id);
             let wildcard_relationship = Archetypes::relationship_id(enum_type_id, WILDCARD.into());
             self.mask.push_has(wildcard_relationship);
-            self.mask.push_states((relationship, tag.id()));
+            self.mask.push_states((relationship, ids));
+        });
+        self
+    }
+
+    pub fn without_enum_tag<T: EnumTag>(mut self, tag: T) -> Self {
+        archetypes_mut(|archetypes| {
+            let enum_tag_id = archetypes.component_id::<EnumTagId>();
+            let enum_type_id = archetypes.component_id::<T>();
+            let relationship = Archetypes::relationship_id(enum_type_id, enum_tag_id);
+            self.mask.push_not_states((relationship, vec![tag.id()]));
         });
         self
     }
@@ -928,11 +1184,107 @@ impl<D: QueryData, F: QueryFilterData> Default for QueryState<D, F> {
     }
 }
 
-impl<'w, D: QueryData, F: QueryFilterData> Iterator for QueryIterator<'w, D, F> {
-    type Item = D::Item<'w>;
+fn component_ticks(
+    archetype: &ArchetypeCell,
+    record: &EntityRecord,
+    id: Identifier,
+) -> Option<ComponentTicks> {
+    let archetype = archetype.borrow();
+    let table = archetype.table().borrow();
+    if let Some(storage) = table.storage(id) {
+        return Some(storage.borrow().ticks(record.table_row));
+    }
+    world::archetypes(|archetypes| {
+        let sparse_set = archetypes.sparse_set(id)?;
+        let row = sparse_set.row(record.entity.low32() as usize)?;
+        let table = sparse_set.table().borrow();
+        let ticks = table.storage(id)?.borrow().ticks(row);
+        Some(ticks)
+    })
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let storage = self.storage.borrow();
+//checks the parts of a query that archetype matching can't: sparse components and enum tag states
+fn entity_passes_filters(
+    storage: &QueryStorage,
+    mask: &FilterMask,
+    archetype: &ArchetypeCell,
+    record: &EntityRecord,
+    last_run_tick: u32,
+) -> bool {
+    if !storage.sparse.is_empty() {
+        let index = record.entity.low32() as usize;
+        let has_sparse = world::archetypes(|archetypes| {
+            storage.sparse.iter().all(|id| {
+                archetypes
+                    .sparse_set(*id)
+                    .is_some_and(|sparse_set| sparse_set.contains(index))
+            })
+        });
+        if !has_sparse {
+            return false;
+        }
+    }
+    entity_passes_mask(mask, archetype, record, last_run_tick)
+}
+
+//sparse, tick and enum state filters of the mask and of every Or alternative that has them
+fn entity_passes_mask(
+    mask: &FilterMask,
+    archetype: &ArchetypeCell,
+    record: &EntityRecord,
+    last_run_tick: u32,
+) -> bool {
+    if world::archetypes(|a| a.has_sparse_sets() && !mask.matches_sparse(a, record.entity)) {
+        return false;
+    }
+    let is_newer = |id: &Identifier, tick: fn(ComponentTicks) -> u32| {
+        component_ticks(archetype, record, *id).is_some_and(|t| tick(t) > last_run_tick)
+    };
+    if !mask.added.iter().all(|id| is_newer(id, |t| t.added))
+        || !mask.changed.iter().all(|id| is_newer(id, |t| t.changed))
+    {
+        return false;
+    }
+
+    let enum_id = |component_id: Identifier| {
+        archetype.borrow_fn(|archetype| {
+            archetype.table().borrow_fn(|table| {
+                let storage = table.storage(component_id)?;
+                storage.borrow_fn(|storage| {
+                    let component = storage.component(record.table_row);
+                    let component = unsafe { &*(component.as_ptr() as *mut EnumTagId) };
+                    Some(component.0)
+                })
+            })
+        })
+    };
+    if !mask.states.iter().all(|(component_id, enum_ids)| {
+        enum_id(*component_id).is_some_and(|id| enum_ids.contains(&id))
+    }) || mask.not_states.iter().any(|(component_id, enum_ids)| {
+            enum_id(*component_id).is_some_and(|id| enum_ids.contains(&id))
+        })
+    {
+        return false;
+    }
+
+    //archetype matching only tells that some alternative matches, it has to be one whose
+    //entity filters pass too
+    mask.any_of.iter().all(|masks| {
+        !world::archetypes(|a| masks.iter().any(|m| m.has_entity_filters(a)))
+            || masks.iter().any(|alternative| {
+                world::archetypes(|a| a.matches_archetype(alternative, archetype))
+                    && entity_passes_mask(alternative, archetype, record, last_run_tick)
+            })
+    })
+}
+
+impl<'w, D: QueryData, F: QueryFilterData> QueryIterator<'w, D, F> {
+    pub fn enumerate_entities(self) -> EntitiesQueryIterator<'w, D, F> {
+        EntitiesQueryIterator { iter: self }
+    }
+
+    fn next_record(&mut self) -> Option<EntityRecord> {
+        let storage = self.query.storage.borrow();
         let archetypes = &storage.archetypes;
         let record = loop {
             let archetype = archetypes.get(self.archetype_index)?;
@@ -959,27 +1311,13 @@ impl<'w, D: QueryData, F: QueryFilterData> Iterator for QueryIterator<'w, D, F>
                 continue;
             }
 
-            let has_enum_tags = self
-                .state
-                .mask
-                .states
-                .iter()
-                .all(|(component_id, enum_id)| {
-                    archetype.borrow_fn(|archetype| {
-                        archetype.table().borrow_fn(|table| {
-                            let Some(storage) = table.storage(*component_id) else {
-                                return false;
-                            };
-                            storage.borrow_fn(|storage| {
-                                let component = storage.component(record.table_row);
-                                let component = unsafe { &*(component.as_ptr() as *mut EnumTagId) };
-                                component.0 == *enum_id
-                            })
-                        })
-                    })
-                });
-
-            if !has_enum_tags {
+            if !entity_passes_filters(
+                &storage,
+                &self.query.state.mask,
+                archetype,
+                &record,
+                self.query.last_run_tick.get(),
+            ) {
                 self.entity_index += 1;
                 continue;
             }
@@ -987,20 +1325,118 @@ impl<'w, D: QueryData, F: QueryFilterData> Iterator for QueryIterator<'w, D, F>
             self.entity_index += 1;
             break record;
         };
-        let mut ids = IdsIterator::new(&self.state.ids.values[..]);
-        drop(storage);
-        Some(D::fetch(
-            self.storage,
+        Some(record)
+    }
+
+    fn fetch_record(&self, record: &EntityRecord) -> D::Item<'w> {
+        let mut ids = IdsIterator::new(&self.query.state.ids.values[..]);
+        D::fetch(
+            &self.query.storage,
             self.archetype_index,
             &mut ids,
             record.table_row,
             record.archetype_row,
-        ))
+        )
+    }
+}
+
+impl<'w, D: QueryData, F: QueryFilterData> Iterator for QueryIterator<'w, D, F> {
+    type Item = D::Item<'w>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.next_record()?;
+        Some(self.fetch_record(&record))
     }
 }
+
+pub struct EntitiesQueryIterator<'w, D: QueryData, F: QueryFilterData> {
+    iter: QueryIterator<'w, D, F>,
+}
+
+impl<'w, D: QueryData, F: QueryFilterData> Iterator for EntitiesQueryIterator<'w, D, F> {
+    type Item = (Entity, D::Item<'w>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.iter.next_record()?;
+        Some((record.entity.into(), self.iter.fetch_record(&record)))
+    }
+}
+//refetches every entity in the sorted order, keeping the world locked until dropped
+pub struct SortedQueryIter<'w, D: QueryData, F: QueryFilterData> {
+    query: &'w Query<D, F>,
+    entities: std::vec::IntoIter<Entity>,
+}
+
+impl<'w, D: QueryData, F: QueryFilterData> Iterator for SortedQueryIter<'w, D, F> {
+    type Item = D::Item<'w>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entity = self.entities.next()?;
+            if let Some(item) = self.query.fetch_entity(entity, false) {
+                return Some(item);
+            }
+        }
+    }
+}
+
+impl<'w, D: QueryData, F: QueryFilterData> Drop for SortedQueryIter<'w, D, F> {
+    fn drop(&mut self) {
+        archetypes_mut(|a| a.unlock_iteration());
+    }
+}
+
+pub struct QueryPairs<'w, D: ReadOnlyQueryData, F: QueryFilterData> {
+    query: &'w Query<D, F>,
+    entities: Vec<Entity>,
+    first: usize,
+    second: usize,
+}
+
+impl<'w, D: ReadOnlyQueryData, F: QueryFilterData> Iterator for QueryPairs<'w, D, F> {
+    type Item = ((Entity, D::Item<'w>), (Entity, D::Item<'w>));
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.second >= self.entities.len() {
+                self.first += 1;
+                self.second = self.first + 1;
+                if self.second >= self.entities.len() {
+                    return None;
+                }
+            }
+            let first = self.entities[self.first];
+            let second = self.entities[self.second];
+            self.second += 1;
+            if let (Some(first_item), Some(second_item)) = (
+                self.query.fetch_entity(first, false),
+                self.query.fetch_entity(second, false),
+            ) {
+                return Some(((first, first_item), (second, second_item)));
+            }
+        }
+    }
+}
+
+impl<'w, D: ReadOnlyQueryData, F: QueryFilterData> Drop for QueryPairs<'w, D, F> {
+    fn drop(&mut self) {
+        archetypes_mut(|a| a.unlock_iteration());
+    }
+}
+
 impl<D: QueryData, F: QueryFilterData> Query<D, F> {
-    pub fn new(state: QueryState<D, F>, storage: Rc<RefCell<QueryStorage>>) -> Self {
-        Self { state, storage }
+    pub fn new(
+        state: QueryState<D, F>,
+        storage: Rc<RefCell<QueryStorage>>,
+        tick_key: QueryTickKey,
+    ) -> Self {
+        let last_run_tick = archetypes(|a| a.query_tick(tick_key));
+        Self {
+            state,
+            storage,
+            last_run_tick: Cell::new(last_run_tick),
+            tick_key,
+        }
     }
 }
 
@@ -75,6 +75,52 @@ impl<T: QueryFilterData> QueryFilterData for Not<T> {
     }
 }
 
+pub trait OrTerms {
+    fn alternatives(masks: &mut Vec<FilterMask>);
+}
+
+macro_rules! impl_or_terms {
+    ($($params:ident),+) => {
+        impl<$($params: QueryFilterData),+> OrTerms for ($($params,)+) {
+            fn alternatives(masks: &mut Vec<FilterMask>) {
+                $(
+                    let mut mask = FilterMask::new();
+                    $params::mask(&mut mask, FilterMaskHint::Regular);
+                    masks.push(mask);
+                )+
+            }
+        }
+    };
+}
+
+impl_or_terms!(T0);
+impl_or_terms!(T0, T1);
+impl_or_terms!(T0, T1, T2);
+impl_or_terms!(T0, T1, T2, T3);
+impl_or_terms!(T0, T1, T2, T3, T4);
+impl_or_terms!(T0, T1, T2, T3, T4, T5);
+impl_or_terms!(T0, T1, T2, T3, T4, T5, T6);
+impl_or_terms!(T0, T1, T2, T3, T4, T5, T6, T7);
+
+//matches archetypes that satisfy at least one of the filters in the tuple
+pub struct Or<T: OrTerms + QueryFilterData> {
+    data: PhantomData<T>,
+}
+
+impl<T: OrTerms + QueryFilterData> QueryFilterData for Or<T> {
+    fn mask(mask: &mut FilterMask, hint: FilterMaskHint) {
+        match hint {
+            FilterMaskHint::Regular => {
+                let mut masks = vec![];
+                T::alternatives(&mut masks);
+                mask.push_any_of(masks);
+            }
+            //not (a or b) is (not a) and (not b)
+            FilterMaskHint::Not => T::mask(mask, FilterMaskHint::Not),
+        }
+    }
+}
+
 pub struct With<T: AbstractComponent> {
     data: PhantomData<T>,
 }
@@ -89,3 +135,42 @@ impl<T: AbstractComponent> QueryFilterData for With<T> {
         });
     }
 }
+
+//matches entities whose component was added after the query last finished iterating
+pub struct Added<T: AbstractComponent> {
+    data: PhantomData<T>,
+}
+
+impl<T: AbstractComponent> QueryFilterData for Added<T> {
+    fn mask(mask: &mut FilterMask, hint: FilterMaskHint) {
+        assert!(
+            matches!(hint, FilterMaskHint::Regular),
+            "Added<T> can't be negated"
+        );
+        archetypes_mut(|a| {
+            let id = a.component_id::<T>();
+            mask.push_has(id);
+            mask.push_added(id);
+        });
+    }
+}
+
+//matches entities whose component was added or mutably accessed after the query last finished
+//iterating
+pub struct Changed<T: AbstractComponent> {
+    data: PhantomData<T>,
+}
+
+impl<T: AbstractComponent> QueryFilterData for Changed<T> {
+    fn mask(mask: &mut FilterMask, hint: FilterMaskHint) {
+        assert!(
+            matches!(hint, FilterMaskHint::Regular),
+            "Changed<T> can't be negated"
+        );
+        archetypes_mut(|a| {
+            let id = a.component_id::<T>();
+            mask.push_has(id);
+            mask.push_changed(id);
+        });
+    }
+}
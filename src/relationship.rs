@@ -57,6 +57,11 @@ impl Relationship {
     pub fn target(&self) -> Entity {
         Entity(archetypes(|a| a.target_entity(self.0).unwrap()))
     }
+
+    //true when the relationship carries no data
+    pub fn is_tag(&self) -> bool {
+        archetypes(|a| a.is_component_empty(self.0))
+    }
 }
 
 #[derive()]
@@ -0,0 +1,94 @@
+use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+
+use bevy_ptr::OwningPtr;
+use bevy_utils::HashMap;
+
+use crate::{
+    archetypes::MyTypeRegistry,
+    identifier::Identifier,
+    table::{Table, TableRow},
+    wrappers::TableCell,
+};
+
+//stores a single component outside of archetypes, so adding and removing it doesn't move entities
+pub struct SparseSet {
+    component: Identifier,
+    table: TableCell,
+    rows: HashMap<usize, TableRow>,
+}
+
+impl SparseSet {
+    pub fn new(component: Identifier, registry: Rc<RefCell<MyTypeRegistry>>) -> Self {
+        let components = BTreeSet::from([component]);
+        Self {
+            component,
+            table: Table::new(&components, registry).into(),
+            rows: HashMap::new(),
+        }
+    }
+
+    pub fn component(&self) -> Identifier {
+        self.component
+    }
+
+    pub fn table(&self) -> &TableCell {
+        &self.table
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn contains(&self, entity_index: usize) -> bool {
+        self.rows.contains_key(&entity_index)
+    }
+
+    pub fn row(&self, entity_index: usize) -> Option<TableRow> {
+        self.rows.get(&entity_index).copied()
+    }
+
+    pub fn entity_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.rows.keys().copied()
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity_index: usize, value: T) {
+        OwningPtr::make(value, |value| self.insert_ptr(entity_index, value));
+    }
+
+    pub fn insert_ptr(&mut self, entity_index: usize, value: OwningPtr) {
+        let mut table = self.table.borrow_mut();
+        if let Some(row) = self.rows.get(&entity_index) {
+            if let Some(storage) = table.storage(self.component) {
+                storage.borrow_mut().replace_unchecked_ptr(row.0, value);
+            }
+            return;
+        }
+        let row = table.push_entity(entity_index);
+        table.push_component_ptr(self.component, value);
+        self.rows.insert(entity_index, row);
+    }
+
+    //re-keys the entity's row after its record moved to another index
+    pub fn move_entity(&mut self, from: usize, to: usize) {
+        let Some(row) = self.rows.remove(&from) else {
+            return;
+        };
+        self.table.borrow_mut().set_entity_index(row, to);
+        self.rows.insert(to, row);
+    }
+
+    pub fn remove(&mut self, entity_index: usize) -> bool {
+        let Some(row) = self.rows.remove(&entity_index) else {
+            return false;
+        };
+        let moved = self.table.borrow_mut().swap_remove_row(row);
+        if let Some(moved) = moved {
+            self.rows.insert(moved, row);
+        }
+        true
+    }
+}
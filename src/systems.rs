@@ -8,6 +8,7 @@ use std::{
 use bevy_reflect::Reflect;
 use bevy_utils::hashbrown::HashMap;
 use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
 
 use crate::{events::CurrentSystemTypeId, world::World};
 
@@ -159,6 +160,8 @@ impl<T: System + 'static> AbstractSystemsWithStateData for T {
             state_ids: ids,
             should_run: None,
             systems: vec![(Box::new(self), next_system_id())],
+            label: None,
+            after: vec![],
         }
     }
 
@@ -291,7 +294,8 @@ macro_rules! impl_systems_with_state {
                     should_run: None,
                     systems: system_fns,
                     state_ids: ids,
-
+                    label: None,
+                    after: vec![],
                 }
             }
 
@@ -409,6 +413,8 @@ impl<F: SystemsData, S: StateData> AbstractSystemsWithStateData for SystemsWithS
             state_ids: ids,
             should_run: self.should_run,
             systems,
+            label: None,
+            after: vec![],
         }
     }
 
@@ -434,6 +440,51 @@ pub struct SystemsWithStateData<F: SystemsData, S: StateData> {
     should_run: Option<Box<dyn ShouldRun>>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SystemLabel(pub SmolStr);
+
+impl From<&str> for SystemLabel {
+    fn from(value: &str) -> Self {
+        Self(value.into())
+    }
+}
+
+pub struct OrderedSystems<S: AbstractSystemsWithStateData> {
+    systems: S,
+    label: Option<SystemLabel>,
+    after: Vec<SystemLabel>,
+}
+
+impl<S: AbstractSystemsWithStateData> AbstractSystemsWithStateData for OrderedSystems<S> {
+    fn add_state_ids(&self, state_ids: &mut HashMap<TypeId, EnumId>) {
+        self.systems.add_state_ids(state_ids);
+    }
+
+    fn into_system_data(self, systems: &mut Systems, stage: SystemStage) -> SystemData {
+        let mut data = self.systems.into_system_data(systems, stage);
+        if self.label.is_some() {
+            data.label = self.label;
+        }
+        data.after.extend(self.after);
+        data
+    }
+
+    fn run_if<R: FnMut(&World) -> bool + 'static>(
+        self,
+        should_run: R,
+    ) -> impl AbstractSystemsWithStateData {
+        OrderedSystems {
+            systems: self.systems.run_if(should_run),
+            label: self.label,
+            after: self.after,
+        }
+    }
+
+    fn add_systems(self, systems: &mut Vec<(Box<dyn System>, SystemId)>) {
+        self.systems.add_systems(systems);
+    }
+}
+
 pub trait System {
     fn run(&mut self, world: &World, states: &States);
 }
@@ -509,6 +560,29 @@ pub trait AbstractSystemsWithStateData {
         self,
         should_run: R,
     ) -> impl AbstractSystemsWithStateData;
+
+    fn labeled(self, label: impl Into<SystemLabel>) -> OrderedSystems<Self>
+    where
+        Self: Sized,
+    {
+        OrderedSystems {
+            systems: self,
+            label: Some(label.into()),
+            after: vec![],
+        }
+    }
+
+    //runs after every system of the same stage that has this label
+    fn after(self, label: impl Into<SystemLabel>) -> OrderedSystems<Self>
+    where
+        Self: Sized,
+    {
+        OrderedSystems {
+            systems: self,
+            label: None,
+            after: vec![label.into()],
+        }
+    }
 }
 
 pub trait SystemState: 'static {
@@ -540,16 +614,54 @@ impl SystemStage {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StateTransition {
+    OnEnter(EnumId),
+    OnExit(EnumId),
+}
+
 pub struct SystemData {
     pub stage: SystemStage,
     pub state_ids: HashMap<TypeId, Option<EnumId>>,
     pub should_run: Option<Box<dyn ShouldRun>>,
     pub systems: Vec<(Box<dyn System>, SystemId)>,
+    pub label: Option<SystemLabel>,
+    pub after: Vec<SystemLabel>,
 }
+
+impl SystemData {
+    pub fn is_ready(&mut self, states: &States, world: &World) -> bool {
+        self.state_ids.iter().all(|(k, v)| {
+            let state = states.states.borrow().get(k).unwrap().0;
+            v.map(|v| v == state).unwrap_or(true)
+        }) && self
+            .should_run
+            .as_mut()
+            .map(|f| f.should_run(world))
+            .unwrap_or(true)
+    }
+
+    pub fn run(&mut self, world: &World, states: &States) {
+        for (system, id) in self.systems.iter_mut() {
+            world.get_or_add_resource_mut(
+                || CurrentSystemTypeId::new(*id),
+                |current_id| {
+                    current_id.value = *id;
+                },
+            );
+            system.run(world, states);
+        }
+    }
+}
+
 type StatesMap = HashMap<TypeId, (EnumId, Rc<RefCell<dyn Any>>)>;
 pub struct Systems {
     systems: Vec<SystemData>,
     states: Rc<RefCell<StatesMap>>,
+    sorted: bool,
+    transition_systems: Vec<(TypeId, StateTransition, SystemData)>,
+    //state type, old and new state ids, waiting for the next run
+    transitions: Vec<(TypeId, Option<EnumId>, EnumId)>,
 }
 
 pub struct StateGetter<T: 'static> {
@@ -584,7 +696,6 @@ impl<T: 'static> StateGetter<T> {
     }
 }
 pub struct States {
-    #[allow(dead_code)]
     states: Rc<RefCell<StatesMap>>,
 }
 
@@ -623,6 +734,9 @@ impl Systems {
         Self {
             systems: vec![],
             states: RefCell::new(HashMap::new()).into(),
+            sorted: true,
+            transition_systems: vec![],
+            transitions: vec![],
         }
     }
 
@@ -632,6 +746,7 @@ impl Systems {
         type_id: TypeId,
         state_id: EnumId,
     ) {
+        self.record_transition(type_id, state_id);
         self.states.borrow_mut().insert(type_id, (state_id, state));
 
         for system in &mut self.systems {
@@ -644,6 +759,7 @@ impl Systems {
     pub fn set_state<T: SystemState>(&mut self, state: T) {
         let type_id = TypeId::of::<T>();
         let id = state.id();
+        self.record_transition(type_id, id);
         self.states
             .borrow_mut()
             .insert(type_id, (id, Rc::new(RefCell::new(state))));
@@ -655,6 +771,56 @@ impl Systems {
         }
     }
 
+    pub fn remove_system(&mut self, id: SystemId) -> bool {
+        let data = self
+            .systems
+            .iter_mut()
+            .chain(self.transition_systems.iter_mut().map(|(_, _, data)| data))
+            .find(|data| data.systems.iter().any(|(_, system_id)| *system_id == id));
+        let Some(data) = data else {
+            return false;
+        };
+        data.systems.retain(|(_, system_id)| *system_id != id);
+        self.systems.retain(|data| !data.systems.is_empty());
+        self.transition_systems
+            .retain(|(_, _, data)| !data.systems.is_empty());
+        true
+    }
+
+    fn record_transition(&mut self, type_id: TypeId, state_id: EnumId) {
+        let old = self.states.borrow().get(&type_id).map(|(id, _)| *id);
+        if old != Some(state_id) {
+            self.transitions.push((type_id, old, state_id));
+        }
+    }
+
+    pub fn add_transition_systems<S: AbstractSystemsWithStateData + 'static>(
+        &mut self,
+        type_id: TypeId,
+        transition: StateTransition,
+        systems: S,
+    ) {
+        let data = self.system_data(systems);
+        self.transition_systems.push((type_id, transition, data));
+    }
+
+    fn run_transitions(&mut self, world: &World, states: &States) {
+        for (type_id, old, new) in std::mem::take(&mut self.transitions) {
+            let exit = old.map(StateTransition::OnExit);
+            for transition in exit.into_iter().chain([StateTransition::OnEnter(new)]) {
+                for (_, _, data) in self
+                    .transition_systems
+                    .iter_mut()
+                    .filter(|(id, t, _)| *id == type_id && *t == transition)
+                {
+                    if data.is_ready(states, world) {
+                        data.run(world, states);
+                    }
+                }
+            }
+        }
+    }
+
     pub fn get_state<T: SystemState>(&self) -> Option<StateGetter<T>> {
         let type_id = TypeId::of::<T>();
         self.states
@@ -689,12 +855,41 @@ impl Systems {
         let data = systems.into_system_data(self, stage);
         self.systems.push(data);
         self.systems.sort_by_key(|s| s.stage.id());
+        self.sorted = false;
     }
 
-    pub fn run(&mut self, world: &World) {
-        let states = States {
+    fn sort_by_labels(&mut self) {
+        let mut systems = std::mem::take(&mut self.systems);
+        while !systems.is_empty() {
+            let stage = systems[0].stage;
+            let len = systems.iter().take_while(|s| s.stage == stage).count();
+            let rest = systems.split_off(len);
+            self.systems.extend(sort_stage(systems));
+            systems = rest;
+        }
+        self.sorted = true;
+    }
+
+    //builds the data without scheduling it, for systems that are run right away
+    pub fn system_data<S: AbstractSystemsWithStateData + 'static>(
+        &mut self,
+        systems: S,
+    ) -> SystemData {
+        systems.into_system_data(self, SystemStage::Update)
+    }
+
+    pub fn states(&self) -> States {
+        States {
             states: self.states.clone(),
-        };
+        }
+    }
+
+    pub fn run(&mut self, world: &World) {
+        let states = self.states();
+        if !self.sorted {
+            self.sort_by_labels();
+        }
+        self.run_transitions(world, &states);
         self.systems.retain_mut(|s| {
             if s.stage == SystemStage::Init {
                 s.systems
@@ -705,29 +900,40 @@ impl Systems {
             true
         });
         for system_data in self.systems.iter_mut() {
-            if system_data.state_ids.iter().all(|(k, v)| {
-                let state = self.states.borrow().get(k).unwrap().0;
-                v.map(|v| v == state).unwrap_or(true)
-            }) && system_data
-                .should_run
-                .as_mut()
-                .map(|f| f.should_run(world))
-                .unwrap_or(true)
-            {
-                for (system, id) in system_data.systems.iter_mut() {
-                    world.get_or_add_resource_mut(
-                        || CurrentSystemTypeId::new(*id),
-                        |current_id| {
-                            current_id.value = *id;
-                        },
-                    );
-                    system.run(world, &states);
-                }
+            if system_data.is_ready(&states, world) {
+                system_data.run(world, &states);
             }
         }
     }
 }
 
+//topological sort that keeps insertion order between independent systems
+fn sort_stage(systems: Vec<SystemData>) -> Vec<SystemData> {
+    let mut remaining: Vec<_> = systems.into_iter().map(Some).collect();
+    let mut sorted = Vec::with_capacity(remaining.len());
+    while sorted.len() < remaining.len() {
+        let is_pending = |label: &SystemLabel| {
+            remaining
+                .iter()
+                .flatten()
+                .any(|s| s.label.as_ref() == Some(label))
+        };
+        let Some(next) = remaining.iter().position(|s| {
+            s.as_ref()
+                .is_some_and(|s| s.after.iter().all(|label| !is_pending(label)))
+        }) else {
+            let labels: Vec<_> = remaining
+                .iter()
+                .flatten()
+                .filter_map(|s| s.label.as_ref().map(|l| l.0.as_str()))
+                .collect();
+            panic!("systems have cyclic ordering constraints: {labels:?}");
+        };
+        sorted.push(remaining[next].take().unwrap());
+    }
+    sorted
+}
+
 impl Default for Systems {
     fn default() -> Self {
         Self::new()
@@ -1,8 +1,11 @@
 use std::{
+    alloc::Layout,
     any::TypeId,
     cell::{Cell, RefCell, RefMut},
     collections::BTreeSet,
     hash::Hash,
+    ops::Range,
+    ptr::NonNull,
     rc::Rc,
 };
 
@@ -49,13 +52,53 @@ impl From<usize> for TableId {
     }
 }
 
-pub struct Storage(pub BlobVec);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+}
+
+pub type ChangeTick = Rc<Cell<u32>>;
+
+//values, their change ticks and the world's current tick.
+//values must be added and removed through the methods below to keep the ticks in sync
+pub struct Storage(pub BlobVec, Vec<ComponentTicks>, ChangeTick);
 
 impl Storage {
+    pub fn new(values: BlobVec, change_tick: ChangeTick) -> Self {
+        Self(values, vec![], change_tick)
+    }
+
     pub fn len(&self) -> usize {
         self.0.len()
     }
 
+    pub fn change_tick(&self) -> u32 {
+        self.2.get()
+    }
+
+    pub fn ticks(&self, row: TableRow) -> ComponentTicks {
+        self.1[row.0]
+    }
+
+    pub(crate) fn ticks_ptr(&self, row: TableRow) -> *mut ComponentTicks {
+        assert!(row.0 < self.1.len(), "stale table row {}", row.0);
+        unsafe { self.1.as_ptr().add(row.0) as *mut ComponentTicks }
+    }
+
+    pub fn mark_changed(&mut self, row: TableRow) {
+        self.1[row.0].changed = self.change_tick();
+    }
+
     #[must_use]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
@@ -65,32 +108,102 @@ impl Storage {
         self.0.capacity()
     }
 
+    pub fn shrink_to_fit(&mut self) {
+        self.0.shrink_to_fit();
+        self.1.shrink_to_fit();
+    }
+
     pub fn push<T: 'static>(&mut self, value: T) {
-        OwningPtr::make(value, |v| unsafe { self.0.push(v) })
+        OwningPtr::make(value, |v| unsafe { self.push_ptr(v) })
+    }
+
+    /// # Safety
+    /// `value` must point to a value of the storage's layout
+    pub unsafe fn push_ptr(&mut self, value: OwningPtr) {
+        let ticks = ComponentTicks::new(self.change_tick());
+        self.push_moved(value, ticks);
+    }
+
+    /// Pushes a value that keeps the ticks from its previous storage.
+    /// # Safety
+    /// `value` must point to a value of the storage's layout
+    pub unsafe fn push_moved(&mut self, value: OwningPtr, ticks: ComponentTicks) {
+        self.0.push(value);
+        self.1.push(ticks);
+    }
+
+    /// Moves every value of `other` to the back of this storage with a single reservation,
+    /// keeping their ticks and leaving `other` empty.
+    /// # Safety
+    /// Both storages must hold values of the same layout
+    pub unsafe fn append_moved(&mut self, other: &mut Storage) {
+        let len = other.0.len();
+        let size = other.0.layout().size();
+        //the values are owned by this storage from here on
+        other.0.set_len(0);
+        let base = other.0.get_ptr_mut().as_ptr();
+        let values =
+            (0..len).map(|row| OwningPtr::new(NonNull::new_unchecked(base.add(row * size))));
+        self.0.extend_from_ptrs(values);
+        self.1.append(&mut other.1);
+    }
+
+    /// # Safety
+    /// `row` must be in bounds
+    pub unsafe fn swap_remove_and_forget(&mut self, row: usize) -> (OwningPtr<'_>, ComponentTicks) {
+        let ticks = self.1.swap_remove(row);
+        (self.0.swap_remove_and_forget_unchecked(row), ticks)
+    }
+
+    /// # Safety
+    /// `row` must be in bounds
+    pub unsafe fn swap_remove_and_drop(&mut self, row: usize) {
+        self.1.swap_remove(row);
+        self.0.swap_remove_and_drop_unchecked(row);
     }
 
     pub fn replace_unchecked<T: 'static>(&mut self, index: usize, value: T) {
-        OwningPtr::make(value, |v| unsafe { self.0.replace_unchecked(index, v) });
+        OwningPtr::make(value, |v| self.replace_unchecked_ptr(index, v));
     }
 
     pub fn replace_unchecked_ptr(&mut self, index: usize, value: OwningPtr) {
         unsafe { self.0.replace_unchecked(index, value) };
+        self.mark_changed(TableRow(index));
+    }
+
+    pub fn iter_ptr(&self, range: Range<usize>) -> impl Iterator<Item = Ptr<'_>> {
+        self.0.iter_range(range)
+    }
+
+    /// # Safety
+    /// the storage must hold values of `T`; only the layout is checked
+    pub unsafe fn iter<T: 'static>(&self) -> impl Iterator<Item = &T> {
+        assert_eq!(self.0.layout(), Layout::new::<T>());
+        self.0.iter().map(|ptr| unsafe { ptr.deref::<T>() })
     }
 }
 
 impl From<BlobVec> for Storage {
     fn from(value: BlobVec) -> Self {
-        Self(value)
+        Self::new(value, ChangeTick::default())
     }
 }
 
 impl Storage {
+    //marks the value as changed
     pub fn component_mut(&mut self, row: TableRow) -> PtrMut {
+        debug_assert!(self.0.get(row.0).is_some(), "stale table row {}", row.0);
+        self.mark_changed(row);
         unsafe { self.0.get_checked_mut(row.0) }
     }
     pub fn component(&self, row: TableRow) -> Ptr {
+        debug_assert!(self.0.get(row.0).is_some(), "stale table row {}", row.0);
         unsafe { self.0.get_checked(row.0) }
     }
+    //None for a row past the end, e.g. one gone stale after deferred edits
+    pub fn try_component(&self, row: TableRow) -> Option<Ptr<'_>> {
+        self.0.get(row.0)
+    }
 }
 
 pub type StorageCell = Rc<RefCell<Storage>>;
@@ -121,20 +234,30 @@ impl Table {
             .copied()
             .collect();
         //SAFETY: creating a blob vec
+        let change_tick = registry_ref.change_tick.clone();
         let storages: Vec<_> = unsafe {
             components
                 .iter()
                 //so that we don't create storage for the root archetype
                 .flat_map(|id| registry_ref.layouts.get(&id.stripped()).map(|l| (l, id)))
                 .map(|(l, id)| {
-                    let capacity = if id.is_relationship() {
+                    let default_capacity = if id.is_relationship() {
                         RELATIONSHIPS_CAPACITY
                     } else {
                         COMPONENT_CAPACITY
                     };
-                    BlobVec::new(*l, None, capacity)
+                    let capacity = registry_ref
+                        .capacity_hints
+                        .get(&id.stripped())
+                        .copied()
+                        .map_or(default_capacity, |hint| hint.max(default_capacity));
+                    let drop = registry_ref
+                        .functions
+                        .get(&id.stripped())
+                        .and_then(|f| f.drop);
+                    BlobVec::new(*l, drop, capacity)
                 })
-                .map(|v| Rc::new(RefCell::new(v.into())))
+                .map(|v| Rc::new(RefCell::new(Storage::new(v, change_tick.clone()))))
                 .collect()
         };
         drop(registry_ref);
@@ -187,6 +310,10 @@ impl Table {
         TableRow(self.count - 1)
     }
 
+    pub fn storages(&self) -> &[StorageCell] {
+        &self.storages
+    }
+
     pub fn storage(&self, id: Identifier) -> Option<&StorageCell> {
         self.storages.get(*self.storage_indices.get(&id)?)
     }
@@ -196,7 +323,7 @@ impl Table {
         //SAFETY: out of bounds checked, correct align checked, everthing else checked
         unsafe {
             OwningPtr::make(value, |p| {
-                storage.borrow_mut().0.push(p);
+                storage.borrow_mut().push_ptr(p);
             });
         }
         Some(())
@@ -206,7 +333,7 @@ impl Table {
         let storage = self.storage(component)?;
         //SAFETY: out of bounds checked, correct align checked, everthing else checked
         unsafe {
-            storage.borrow_mut().0.push(value);
+            storage.borrow_mut().push_ptr(value);
         }
         Some(())
     }
@@ -223,21 +350,10 @@ impl Table {
                 continue;
             }
             //SAFETY: out of bounds checked
-            let _ = unsafe { storage.0.swap_remove_and_forget_unchecked(row) };
+            let _ = unsafe { storage.swap_remove_and_forget(row) };
         }
 
-        self.count -= 1;
-        let removed = self.entity_indices.swap_remove(row);
-        let remove_id = if self.entity_indices.is_empty() || row == self.entity_indices.len() {
-            removed
-        } else {
-            self.entity_indices[row]
-        };
-        archetypes.modify_record_by_index(remove_id, |r| {
-            if let Some(r) = r {
-                r.table_row = row.into();
-            }
-        });
+        self.remove_entity_row(archetypes, row);
     }
 
     pub fn remove_drop(&mut self, archetypes: &mut Archetypes, row: TableRow) {
@@ -249,9 +365,14 @@ impl Table {
             let mut storage = storage.borrow_mut();
             assert!(row < storage.len());
             //SAFETY: out of bounds checked
-            unsafe { storage.0.swap_remove_and_drop_unchecked(row) };
+            unsafe { storage.swap_remove_and_drop(row) };
         }
 
+        self.remove_entity_row(archetypes, row);
+    }
+
+    //removes the entity at `row` after its values were taken out of the storages
+    fn remove_entity_row(&mut self, archetypes: &mut Archetypes, row: usize) {
         self.count -= 1;
         let removed = self.entity_indices.swap_remove(row);
         let remove_id = if self.entity_indices.is_empty() || row == self.entity_indices.len() {
@@ -266,6 +387,38 @@ impl Table {
         });
     }
 
+    /// Drops the values at `row` without touching entity records.
+    /// Returns the entity index that was moved into `row`, if any.
+    pub fn swap_remove_row(&mut self, row: TableRow) -> Option<usize> {
+        let row = row.0;
+        for storage in self.storages.iter() {
+            let mut storage = storage.borrow_mut();
+            assert!(row < storage.len());
+            //SAFETY: out of bounds checked
+            unsafe { storage.swap_remove_and_drop(row) };
+        }
+        self.count -= 1;
+        self.entity_indices.swap_remove(row);
+        self.entity_indices.get(row).copied()
+    }
+
+    /// Moves every row of this table to the end of `other`, which must have the same components.
+    /// Returns the entity index and the new row of each moved row.
+    pub fn drain_into(&mut self, other: &mut Table) -> Vec<(usize, TableRow)> {
+        assert_eq!(self.components, other.components);
+        for (id, storage_index) in self.storage_indices.iter() {
+            let mut storage = self.storages[*storage_index].borrow_mut();
+            let other_storage = other.storage(*id).unwrap();
+            //SAFETY: both tables have the same components, so their storages share layouts
+            unsafe { other_storage.borrow_mut().append_moved(&mut storage) };
+        }
+        self.count = 0;
+        self.entity_indices
+            .drain(..)
+            .map(|index| (index, other.push_entity(index)))
+            .collect()
+    }
+
     pub fn len(&self) -> usize {
         self.count
     }
@@ -282,6 +435,29 @@ impl Table {
         &self.entity_indices[..]
     }
 
+    pub fn memory_bytes(&self) -> usize {
+        let storages: usize = self
+            .storages
+            .iter()
+            .map(|s| {
+                let storage = s.borrow();
+                storage.capacity() * storage.0.layout().size()
+            })
+            .sum();
+        storages + self.entity_indices.capacity() * std::mem::size_of::<usize>()
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.entity_indices.shrink_to_fit();
+        for storage in self.storages.iter() {
+            storage.borrow_mut().shrink_to_fit();
+        }
+    }
+
+    pub fn set_entity_index(&mut self, row: TableRow, index: usize) {
+        self.entity_indices[row.0] = index;
+    }
+
     pub fn component_id<T: 'static>(&self) -> Option<Identifier> {
         self.registry
             .borrow()
@@ -331,31 +507,34 @@ impl Table {
         mut new_archetype: RefMut<Archetype>,
         mut old_archetype: RefMut<Archetype>,
     ) -> (ArchetypeRow, TableRow) {
-        let (archetype_row, table_row) = {
-            let old_table = old_archetype.table();
-            let new_table = new_archetype.table().clone();
-            let (arhetype_row, table_row) =
-                new_archetype.push_entity(entity.low32() as usize, ArchetypeAdd::ArchetypeAndTable);
-            let old = old_table.borrow();
+        let old_table = old_archetype.table().clone();
+        let new_table = new_archetype.table().clone();
+        let (archetype_row, table_row) =
+            new_archetype.push_entity(entity.low32() as usize, ArchetypeAdd::ArchetypeAndTable);
+        if !old_table.borrow().storages.is_empty() {
+            let mut old = old_table.borrow_mut();
             let new = new_table.borrow();
+            let row = old_table_row.0;
             for (id, old_index) in old.storage_indices.iter() {
-                let Some(new_index) = new.storage_indices.get(id) else {
-                    continue;
-                };
+                let mut old_storage = old.storages[*old_index].borrow_mut();
+                assert!(row < old_storage.len());
+                //SAFETY: out of bounds checked, the new storage has the same layout
                 unsafe {
-                    let old_storage = &old.storages[*old_index];
-                    let mut old_storage_mut = old_storage.borrow_mut();
-                    let value = old_storage_mut
-                        .0
-                        .swap_remove_and_forget_unchecked(old_table_row.0);
-                    let new_storage = &new.storages[*new_index];
-                    let mut new_storage_mut = new_storage.borrow_mut();
-                    new_storage_mut.0.push(value);
+                    match new.storage_indices.get(id) {
+                        Some(new_index) => {
+                            let (value, ticks) = old_storage.swap_remove_and_forget(row);
+                            new.storages[*new_index]
+                                .borrow_mut()
+                                .push_moved(value, ticks);
+                        }
+                        //the component was removed from the entity
+                        None => old_storage.swap_remove_and_drop(row),
+                    }
                 }
             }
-            (arhetype_row, table_row)
-        };
-        old_archetype.remove_forget(archetypes, old_archetype_row, old_table_row.into());
+            old.remove_entity_row(archetypes, row);
+        }
+        old_archetype.remove_forget(archetypes, old_archetype_row, None);
         (archetype_row, table_row.unwrap())
     }
 }
@@ -446,4 +625,44 @@ mod tests {
         assert_eq!(pos.x, 10);
         assert_eq!(pos.y, 20);
     }
+
+    #[test]
+    fn try_component_out_of_range() {
+        let registry = Rc::new(RefCell::new(MyTypeRegistry::new()));
+        let component = Identifier::from(420);
+        registry
+            .borrow_mut()
+            .add_type_id(TypeId::of::<Position>(), component, "Position");
+        registry
+            .borrow_mut()
+            .layouts
+            .insert(component.stripped(), Layout::new::<Position>());
+        let components = BTreeSet::from([component]);
+        let mut table = Table::new(&components, registry.clone());
+        table.push_entity(0);
+        table.push_component::<Position>(component, Position { x: 10, y: 20 });
+        let storage = table.storage(component).unwrap().clone();
+        let storage_ref = storage.borrow();
+        let pos = storage_ref.try_component(TableRow(0)).unwrap();
+        assert_eq!(unsafe { pos.deref::<Position>() }.y, 20);
+        assert!(storage_ref.try_component(TableRow(1)).is_none());
+        assert!(storage_ref.try_component(TableRow(usize::MAX)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "stale table row")]
+    fn ticks_ptr_out_of_range() {
+        let registry = Rc::new(RefCell::new(MyTypeRegistry::new()));
+        let component = Identifier::from(420);
+        registry
+            .borrow_mut()
+            .layouts
+            .insert(component.stripped(), Layout::new::<Position>());
+        let components = BTreeSet::from([component]);
+        let mut table = Table::new(&components, registry.clone());
+        table.push_entity(0);
+        table.push_component::<Position>(component, Position { x: 10, y: 20 });
+        let storage = table.storage(component).unwrap().clone();
+        storage.borrow().ticks_ptr(TableRow(1));
+    }
 }
@@ -1,29 +1,45 @@
 use std::{any::TypeId, cell::RefCell, rc::Rc};
 
+use bevy_ptr::Ptr;
+use bevy_utils::HashMap;
 use smol_str::{SmolStr, ToSmolStr};
 
 use crate::{
-    archetypes::{Archetypes, EntityKind, Prefab, StateOperation, ENTITY_ID},
-    components::{component::AbstractComponent, register::RegisterComponentQuery},
+    archetypes::{
+        Archetypes, EntityKind, InstanceOf, MemoryReport, OnTargetDelete, Prefab, StateOperation,
+        Wildcard, WorldStats, ENTITY_ID,
+    },
+    components::{
+        component::{AbstractComponent, EnumTag},
+        register::RegisterComponentQuery,
+    },
     entity::Entity,
     entity_parser::ParseError,
     events::{self, CurrentSystemTypeId, Event, EventReader, Events},
-    on_change_callbacks::{OnAddCallback, OnRemoveCallback},
+    identifier::Identifier,
+    on_change_callbacks::{OnAddCallback, OnChangeCallbacks, OnRemoveCallback},
     plugins::Plugins,
-    query::{QueryData, QueryFilterData, QueryState},
+    query::{Query, QueryData, QueryFilterData, QueryState},
     resources::ResourceQuery,
-    systems::{AbstractSystemsWithStateData, StateGetter, SystemStage, SystemState, Systems},
+    systems::{
+        AbstractSystemsWithStateData, EnumId, StateGetter, StateTransition, SystemId, SystemStage,
+        SystemState, Systems,
+    },
 };
 
-#[derive(Default)]
+//holds a world's archetypes while another world is the active one
+type ParkedArchetypes = Rc<RefCell<Option<Archetypes>>>;
+
 pub struct World {
     currently_running_systems: bool,
+    parked: ParkedArchetypes,
 }
 
 impl Clone for World {
     fn clone(&self) -> Self {
         Self {
             currently_running_systems: self.currently_running_systems,
+            parked: self.parked.clone(),
         }
     }
 }
@@ -39,40 +55,331 @@ pub fn archetypes_mut<F, U>(f: F) -> U
 where
     F: FnOnce(&mut Archetypes) -> U,
 {
-    ARCHETYPES.with(|a| f(a.borrow_mut().as_mut().unwrap()))
+    let (result, queued) = ARCHETYPES.with(|a| {
+        let mut a = a.borrow_mut();
+        let a = a.as_mut().unwrap();
+        let result = f(a);
+        let queued = a
+            .callbacks()
+            .try_borrow_mut()
+            .is_ok_and(|c| c.has_queued())
+            .then(|| a.callbacks().clone());
+        (result, queued)
+    });
+    if let Some(callbacks) = queued {
+        OnChangeCallbacks::run_queued(&callbacks);
+    }
+    result
 }
 
 pub fn drop_archetypes() {
     ARCHETYPES.with(|a| *a.borrow_mut() = None);
+    ACTIVE_WORLD.with(|w| *w.borrow_mut() = None);
 }
 
 thread_local! {
     pub static ARCHETYPES: RefCell<Option<Archetypes>> = const { RefCell::new(None) };
+    static ACTIVE_WORLD: RefCell<Option<ParkedArchetypes>> = const { RefCell::new(None) };
+}
+
+//parks the active archetypes in their world and moves the given world's ones in,
+//returning the previously active world
+fn activate(world: Option<ParkedArchetypes>) -> Option<ParkedArchetypes> {
+    let previous = ACTIVE_WORLD.with(|w| w.borrow().clone());
+    if let (Some(world), Some(previous)) = (&world, &previous) {
+        if Rc::ptr_eq(world, previous) {
+            return Some(previous.clone());
+        }
+    }
+    ACTIVE_WORLD.with(|w| *w.borrow_mut() = world.clone());
+    let incoming = world.and_then(|w| w.borrow_mut().take());
+    let outgoing = ARCHETYPES.with(|a| std::mem::replace(&mut *a.borrow_mut(), incoming));
+    if let Some(previous) = &previous {
+        *previous.borrow_mut() = outgoing;
+    }
+    previous
+}
+
+struct RestoreWorld(Option<ParkedArchetypes>);
+
+impl Drop for RestoreWorld {
+    fn drop(&mut self) {
+        activate(self.0.take());
+    }
+}
+
+//moves the entity from one world into another. The source entity is only despawned once it
+//was spawned in the target
+pub fn transfer(from: &World, to: &World, entity: Entity) -> anyhow::Result<Entity> {
+    let Some(json) = from.scope(|| entity.serialize()) else {
+        anyhow::bail!("entity {entity:?} is not alive");
+    };
+    let moved = to.spawn_from_serialized(&json)?;
+    from.scope(|| entity.remove());
+    Ok(moved)
 }
 
 impl World {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        ARCHETYPES.with(|a| {
-            *a.borrow_mut() = Some(Archetypes::new());
-        });
+        Self::with_archetypes(Archetypes::new())
+    }
+
+    pub fn with_capacity(entities: usize) -> Self {
+        Self::with_archetypes(Archetypes::with_capacity(entities))
+    }
+
+    //the new world becomes the active one, the previous one stays parked until scoped into
+    fn with_archetypes(archetypes: Archetypes) -> Self {
+        let parked: ParkedArchetypes = RefCell::new(Some(archetypes)).into();
+        activate(Some(parked.clone()));
         Self {
             currently_running_systems: false,
+            parked,
+        }
+    }
+
+    //a handle to the world entities and queries currently operate on
+    pub fn active() -> Option<Self> {
+        ACTIVE_WORLD
+            .with(|w| w.borrow().clone())
+            .map(|parked| Self {
+                currently_running_systems: false,
+                parked,
+            })
+    }
+
+    pub fn is_active(&self) -> bool {
+        ACTIVE_WORLD.with(|w| {
+            w.borrow()
+                .as_ref()
+                .is_some_and(|w| Rc::ptr_eq(w, &self.parked))
+        })
+    }
+
+    //makes this world the one entities and queries operate on for the closure's duration
+    pub fn scope<U>(&self, f: impl FnOnce() -> U) -> U {
+        if self.is_active() {
+            return f();
         }
+        let _restore = RestoreWorld(activate(Some(self.parked.clone())));
+        f()
     }
 
     pub fn entity_by_global_name(&self, name: &str) -> Option<Entity> {
         archetypes_mut(|a| a.entity_by_global_name(name.to_smolstr())).map(|id| id.into())
     }
 
+    //case-insensitive, matches anywhere in the name
+    pub fn search_entities(&self, fragment: &str) -> Vec<Entity> {
+        archetypes(|a| a.find_entities_by_name_ci(fragment))
+            .into_iter()
+            .map(Entity)
+            .collect()
+    }
+
+    pub fn type_name(&self, id: Identifier) -> Option<SmolStr> {
+        archetypes(|a| a.type_name(id))
+    }
+
+    pub fn named_children(&self, parent: &Entity) -> Vec<(SmolStr, Entity)> {
+        archetypes(|a| a.named_children(parent.0))
+            .into_iter()
+            .map(|(name, entity)| (name, entity.into()))
+            .collect()
+    }
+
+    pub fn name_conflicts(&self) -> Vec<(u32, SmolStr, Vec<Entity>)> {
+        archetypes(|a| a.name_conflicts())
+            .into_iter()
+            .map(|(parent, name, entities)| {
+                (parent, name, entities.into_iter().map(Entity).collect())
+            })
+            .collect()
+    }
+
+    pub fn entity_by_path(&self, path: &str) -> Option<Entity> {
+        archetypes(|a| a.entity_by_path(path)).map(Entity)
+    }
+
+    pub fn add_comp_by_name(
+        &self,
+        entity: Entity,
+        name: &str,
+        value: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        entity.add_comp_by_name(name, value).map(|_| ())
+    }
+
+    pub fn spawn_default_by_name(&self, name: &str) -> anyhow::Result<Entity> {
+        archetypes_mut(|a| a.spawn_default_by_name(name))
+    }
+
+    pub fn entity_from_raw(&self, raw: u64) -> Option<Entity> {
+        let id = Identifier::from(raw);
+        let unpacked = id.unpack();
+        if unpacked.high32.is_relationship {
+            return None;
+        }
+        let record = archetypes(|a| a.record(id))?;
+        //a recycled slot has a newer generation than the stored id
+        (unpacked.high32.second == record.entity.unpack().high32.second)
+            .then_some(Entity(record.entity))
+    }
+
+    pub fn entity_count(&self) -> usize {
+        archetypes(|a| a.alive_entity_count())
+    }
+
+    pub fn archetype_count(&self) -> usize {
+        archetypes(|a| a.archetype_count())
+    }
+
+    pub fn table_count(&self) -> usize {
+        archetypes(|a| a.table_count())
+    }
+
+    pub fn stats(&self) -> WorldStats {
+        archetypes(|a| a.stats())
+    }
+
+    //fails inside a query iteration
+    pub fn flush(&self) -> anyhow::Result<()> {
+        archetypes_mut(|a| a.flush())
+    }
+
+    pub fn compact(&self) -> anyhow::Result<()> {
+        archetypes_mut(|a| a.compact())
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        archetypes(|a| a.memory_report())
+    }
+
+    pub fn clear_entities(&self) -> anyhow::Result<()> {
+        archetypes_mut(|a| a.clear_entities())
+    }
+
+    pub fn compact_records(&self) -> anyhow::Result<HashMap<Entity, Entity>> {
+        Ok(archetypes_mut(|a| a.compact_records())?
+            .into_iter()
+            .map(|(old, new)| (old.into(), new.into()))
+            .collect())
+    }
+
+    pub fn reserve_relationship<R: AbstractComponent, T: AbstractComponent>(
+        &self,
+        count: usize,
+    ) -> Self {
+        archetypes_mut(|a| {
+            let relationship = a.relationship_id_typed::<R, T>();
+            a.reserve_storage(relationship, count);
+        });
+        self.clone()
+    }
+
+    pub fn resync_instance(&self, instance: Entity) -> anyhow::Result<Self> {
+        if let Some(rel) = instance.find_rel::<InstanceOf, Wildcard>() {
+            let prefab = rel.target();
+            archetypes_mut(|a| a.resync_instance(instance.0, prefab.0))?;
+        }
+        Ok(self.clone())
+    }
+
+    pub fn defragment_tables(&self) -> anyhow::Result<usize> {
+        archetypes_mut(|a| a.defragment_tables())
+    }
+
+    //children of despawned entities are despawned with them. Inside a query iteration the
+    //removals are deferred until it ends
+    pub fn despawn_all_matching<D: QueryData, F: QueryFilterData>(&self) -> Self {
+        let query = self.query_filtered::<D, F>().build();
+        let pool = archetypes(|a| a.entities_pool_rc().clone());
+        let mut entities = std::mem::take(&mut *pool.borrow_mut());
+        entities.clear();
+        archetypes_mut(|a| a.lock());
+        query.matched_entities(&mut entities);
+        archetypes_mut(|a| a.unlock());
+
+        archetypes_mut(|a| {
+            let mut children_pool = pool.borrow_mut();
+            for entity in entities.iter() {
+                //children of other matched entities are already gone
+                let _ = a.remove_entity(*entity, 0.into(), &mut children_pool);
+            }
+        });
+        entities.clear();
+        *pool.borrow_mut() = entities;
+        self.clone()
+    }
+
+    pub fn register_exclusive_relation<R: AbstractComponent>(&self) -> Self {
+        archetypes_mut(|a| {
+            let relation = a.component_id::<R>();
+            a.register_exclusive_relation(relation);
+        });
+        self.clone()
+    }
+
+    pub fn set_on_target_delete<R: AbstractComponent>(&self, policy: OnTargetDelete) -> Self {
+        archetypes_mut(|a| {
+            let relation = a.component_id::<R>();
+            a.set_on_target_delete(relation, policy);
+        });
+        self.clone()
+    }
+
+    pub fn register_sparse_component<T: AbstractComponent>(&self) -> Self {
+        archetypes_mut(|a| a.register_sparse_component::<T>());
+        self.clone()
+    }
+
+    pub fn register_enum_tag<T: EnumTag>(&self) -> Self {
+        archetypes_mut(|a| a.register_enum_tag::<T>());
+        self.clone()
+    }
+
     pub fn register_components<T: RegisterComponentQuery>(&self) {
         T::register();
     }
 
+    //serializes the entity and despawns it, the json can be spawned again with spawn_from_serialized
+    pub fn take_entity_serialized(&self, entity: Entity) -> Option<String> {
+        self.scope(|| {
+            let json = entity.serialize()?;
+            entity.remove();
+            Some(json)
+        })
+    }
+
+    //unlike deserialize_entity, fails with every type the json uses that this world doesn't know
+    pub fn spawn_from_serialized(&self, json: &str) -> Result<Entity, ParseError> {
+        self.scope(|| archetypes_mut(|a| a.spawn_from_serialized(json)))
+    }
+
     pub fn deserialize_entity(&self, json: &str) -> Result<Entity, ParseError> {
         archetypes_mut(|a| a.deserialize_entity(json))
     }
 
+    pub fn serialize_world(&self) -> String {
+        serde_json::to_string_pretty(&archetypes(|a| a.serialize_world())).unwrap()
+    }
+
+    //same entities as serialize_world, stored column by column with bincode
+    pub fn save_binary(&self) -> Vec<u8> {
+        archetypes(|a| a.save_binary())
+    }
+
+    pub fn load_binary(&self, bytes: &[u8]) -> Result<HashMap<SmolStr, Entity>, ParseError> {
+        archetypes_mut(|a| a.load_binary(bytes))
+    }
+
+    //returns the spawned entities by their keys in the json
+    pub fn deserialize_world(&self, json: &str) -> Result<HashMap<SmolStr, Entity>, ParseError> {
+        let value = serde_json::from_str::<serde_json::Value>(json)?;
+        archetypes_mut(|a| a.deserialize_world(&value))
+    }
+
     pub fn send_event<T: Event>(&self, event: T) {
         self.resources::<&mut Events<T>>(|events| {
             events.push(event);
@@ -85,6 +392,12 @@ impl World {
         })
     }
 
+    //unlike event_reader, the reader isn't tied to the running system and keeps
+    //its cursor for as long as it's held
+    pub fn global_event_reader<T: Event>(&self) -> Rc<RefCell<EventReader<T>>> {
+        self.resources_ret::<&mut Events<T>, _>(|events| events.global_event_reader())
+    }
+
     pub fn add_event_type<T: Event>(&self) -> Self {
         let events = Events::<T>::new();
         self.add_resource(events);
@@ -112,6 +425,45 @@ impl World {
         self.clone()
     }
 
+    //the systems run once at the start of the next run after the state becomes `variant`
+    pub fn add_systems_on_enter<T: SystemState, S: AbstractSystemsWithStateData + 'static>(
+        &self,
+        variant: T,
+        systems: S,
+    ) -> Self {
+        self.add_transition_systems::<T, S>(StateTransition::OnEnter(variant.id()), systems)
+    }
+
+    pub fn add_systems_on_exit<T: SystemState, S: AbstractSystemsWithStateData + 'static>(
+        &self,
+        variant: T,
+        systems: S,
+    ) -> Self {
+        self.add_transition_systems::<T, S>(StateTransition::OnExit(variant.id()), systems)
+    }
+
+    fn add_transition_systems<T: SystemState, S: AbstractSystemsWithStateData + 'static>(
+        &self,
+        transition: StateTransition,
+        systems: S,
+    ) -> Self {
+        archetypes_mut(|a| {
+            a.systems()
+                .borrow_mut()
+                .add_transition_systems(TypeId::of::<T>(), transition, systems);
+        });
+        self.clone()
+    }
+
+    //can't be called from a running system, since the systems are borrowed while they run
+    pub fn remove_system(&self, id: SystemId) -> bool {
+        archetypes_mut(|a| a.systems().borrow_mut().remove_system(id))
+    }
+
+    pub fn current_system_id(&self) -> Option<SystemId> {
+        archetypes(|a| a.current_system_id())
+    }
+
     pub fn on_comp_add<T: AbstractComponent>(&self, callback: impl Fn(Entity, World) + 'static) {
         assert!(std::mem::size_of::<T>() > 0);
         archetypes_mut(|a| {
@@ -120,11 +472,68 @@ impl World {
         })
     }
 
-    pub fn on_comp_remove<T: AbstractComponent>(&self, callback: impl Fn(Entity, World) + 'static) {
+    //gets a copy of the removed value, also when the entity is despawned
+    pub fn on_comp_remove<T: AbstractComponent>(
+        &self,
+        mut callback: impl FnMut(Entity, &T) + 'static,
+    ) {
         assert!(std::mem::size_of::<T>() > 0);
         archetypes_mut(|a| {
             let id = a.component_id::<T>();
-            a.insert_remove_callback(id, Box::new(callback));
+            a.callbacks().borrow_mut().insert_remove_value_callback(
+                id,
+                Box::new(move |entity, value: Ptr| {
+                    //SAFETY: values in the storages of T's id are of type T
+                    callback(entity, unsafe { value.deref::<T>() })
+                }),
+            );
+        })
+    }
+
+    //fires for every (R, *) relationship added, with the holder and the target
+    pub fn on_rel_add<R: AbstractComponent>(&self, callback: impl FnMut(Entity, Entity) + 'static) {
+        archetypes_mut(|a| {
+            let relation = a.component_id::<R>();
+            a.callbacks().borrow_mut().insert_rel_add_callback(
+                Archetypes::wildcard_target_relationship(relation),
+                Box::new(callback),
+            );
+        })
+    }
+
+    pub fn on_comp_set<T: AbstractComponent>(&self, callback: impl FnMut(Entity, bool) + 'static) {
+        assert!(std::mem::size_of::<T>() > 0);
+        archetypes_mut(|a| {
+            let id = a.component_id::<T>();
+            a.callbacks()
+                .borrow_mut()
+                .insert_set_callback(id, Box::new(callback))
+        })
+    }
+
+    pub fn on_name_change(&self, callback: impl FnMut(Entity, Option<&str>, &str) + 'static) {
+        archetypes_mut(|a| {
+            a.callbacks()
+                .borrow_mut()
+                .insert_name_callback(Box::new(callback))
+        })
+    }
+
+    pub fn on_enum_tag_change<T: EnumTag>(
+        &self,
+        mut callback: impl FnMut(Entity, Option<T>, T) + 'static,
+    ) {
+        archetypes_mut(|a| {
+            let id = a.component_id::<T>();
+            a.callbacks().borrow_mut().insert_enum_tag_callback(
+                id,
+                Box::new(move |entity, old: Option<EnumId>, new| {
+                    let old = old.and_then(T::from_id);
+                    if let Some(new) = T::from_id(new) {
+                        callback(entity, old, new);
+                    }
+                }),
+            );
         })
     }
 
@@ -172,7 +581,21 @@ impl World {
         systems.get_state::<T>().unwrap()
     }
 
+    //runs the systems a single time right now, without adding them to a stage.
+    //can't be called from a system, since the scheduled systems are borrowed while they run
+    pub fn run_once<S: AbstractSystemsWithStateData + 'static>(&self, system: S) {
+        let systems = archetypes_mut(|a| a.systems().clone());
+        let (mut data, states) = {
+            let mut systems = systems.borrow_mut();
+            (systems.system_data(system), systems.states())
+        };
+        if data.is_ready(&states, self) {
+            data.run(self, &states);
+        }
+    }
+
     pub fn run(&mut self) {
+        archetypes(|a| a.increment_change_tick());
         self.remove_empty_entities();
         let systems = archetypes_mut(|a| a.systems().clone());
         self.currently_running_systems = true;
@@ -253,6 +676,29 @@ impl World {
         archetypes_mut(|a| a.resource_exists::<T>())
     }
 
+    pub fn has_resource<T: 'static>(&self) -> bool {
+        self.resource_exists::<T>()
+    }
+
+    //the resource map isn't borrowed while `f` runs, so it can add or remove resources
+    pub fn get_resource<T: 'static, U>(&self, f: impl FnOnce(Option<&T>) -> U) -> U {
+        let resources = archetypes(|a| a.resources().clone());
+        let resource = resources.borrow().get(&TypeId::of::<T>()).cloned();
+        match resource {
+            Some(resource) => f(resource.borrow().downcast_ref::<T>()),
+            None => f(None),
+        }
+    }
+
+    pub fn get_resource_mut<T: 'static, U>(&self, f: impl FnOnce(Option<&mut T>) -> U) -> U {
+        let resources = archetypes(|a| a.resources().clone());
+        let resource = resources.borrow().get(&TypeId::of::<T>()).cloned();
+        match resource {
+            Some(resource) => f(resource.borrow_mut().downcast_mut::<T>()),
+            None => f(None),
+        }
+    }
+
     pub fn add_entity_named(&self, name: &str) -> Entity {
         let id = archetypes_mut(|a| a.add_entity(EntityKind::Regular));
         let entity = Entity(id);
@@ -280,6 +726,19 @@ impl World {
         QueryState::new()
     }
 
+    //the query is built once per (D, F) and shared, skipping the id and mask setup
+    pub fn query_cached<D: QueryData + 'static, F: QueryFilterData + 'static>(
+        &self,
+    ) -> Rc<RefCell<Query<D, F>>> {
+        let type_id = TypeId::of::<(D, F)>();
+        if let Some(query) = archetypes(|a| a.cached_query(type_id)) {
+            return query.downcast().unwrap();
+        }
+        let query = Rc::new(RefCell::new(QueryState::<D, F>::new().build()));
+        archetypes_mut(|a| a.cache_query(type_id, query.clone()));
+        query
+    }
+
     pub fn empty_query(&self) -> QueryState<(), ()> {
         QueryState::new()
     }